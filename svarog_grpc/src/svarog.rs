@@ -13,6 +13,20 @@ pub struct SessionConfig {
     pub players: ::std::collections::HashMap<::prost::alloc::string::String, bool>,
     #[prost(map = "string, bool", tag = "6")]
     pub players_reshared: ::std::collections::HashMap<::prost::alloc::string::String, bool>,
+    #[prost(uint64, tag = "7")]
+    pub max_absent_consumers: u64,
+    #[prost(map = "string, bytes", tag = "8")]
+    pub mailbox_pks:
+        ::std::collections::HashMap<::prost::alloc::string::String, ::prost::alloc::vec::Vec<u8>>,
+    /// Seconds to wait for all attending parties to post ready markers; 0 disables the check.
+    /// With signer_quorum set, it also bounds the CheckIn wait, capped by the outbox wait;
+    /// when 0, CheckIn waits for the outbox wait.
+    #[prost(uint64, tag = "9")]
+    pub ready_timeout_secs: u64,
+    #[prost(uint64, tag = "10")]
+    pub signer_quorum: u64,
+    #[prost(string, tag = "11")]
+    pub exporter: ::prost::alloc::string::String,
 }
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -48,6 +62,97 @@ pub struct VecMessage {
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Mail {
+    #[prost(string, tag = "1")]
+    pub session_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub recipient: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub sender: ::prost::alloc::string::String,
+    #[prost(bytes = "vec", tag = "4")]
+    pub payload: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MailboxId {
+    #[prost(string, tag = "1")]
+    pub session_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub recipient: ::prost::alloc::string::String,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VecMail {
+    #[prost(message, repeated, tag = "1")]
+    pub values: ::prost::alloc::vec::Vec<Mail>,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BlameRecord {
+    #[prost(string, tag = "1")]
+    pub session_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub reporter: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub culprit: u64,
+    #[prost(string, tag = "4")]
+    pub culprit_name: ::prost::alloc::string::String,
+    #[prost(string, tag = "5")]
+    pub reason: ::prost::alloc::string::String,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VecBlameRecord {
+    #[prost(message, repeated, tag = "1")]
+    pub values: ::prost::alloc::vec::Vec<BlameRecord>,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReadyMarker {
+    #[prost(string, tag = "1")]
+    pub session_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub player: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub version: ::prost::alloc::string::String,
+    #[prost(string, repeated, tag = "4")]
+    pub capabilities: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VecReadyMarker {
+    #[prost(message, repeated, tag = "1")]
+    pub values: ::prost::alloc::vec::Vec<ReadyMarker>,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CheckIn {
+    #[prost(string, tag = "1")]
+    pub session_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub player: ::prost::alloc::string::String,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SignerSet {
+    #[prost(string, repeated, tag = "1")]
+    pub signers: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(bool, tag = "2")]
+    pub selected: bool,
+    #[prost(bool, tag = "3")]
+    pub complete: bool,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct EchoMessage {
     #[prost(string, tag = "1")]
     pub value: ::prost::alloc::string::String,
@@ -56,6 +161,113 @@ pub struct EchoMessage {
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Void {}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SignatureFormat {
+    #[prost(enumeration = "SignatureEncoding", tag = "1")]
+    pub encoding: i32,
+    #[prost(uint32, tag = "2")]
+    pub sighash: u32,
+    #[prost(enumeration = "EthV", tag = "3")]
+    pub eth_v: i32,
+    #[prost(uint64, tag = "4")]
+    pub chain_id: u64,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EncodedSignature {
+    #[prost(bytes = "vec", tag = "1")]
+    pub value: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub pk: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    ::prost::Enumeration,
+)]
+#[repr(i32)]
+pub enum SignatureEncoding {
+    Der = 0,
+    Compact = 1,
+    EthRsv = 2,
+    Ed25519 = 3,
+}
+impl SignatureEncoding {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            SignatureEncoding::Der => "DER",
+            SignatureEncoding::Compact => "COMPACT",
+            SignatureEncoding::EthRsv => "ETH_RSV",
+            SignatureEncoding::Ed25519 => "ED25519",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "DER" => Some(Self::Der),
+            "COMPACT" => Some(Self::Compact),
+            "ETH_RSV" => Some(Self::EthRsv),
+            "ED25519" => Some(Self::Ed25519),
+            _ => None,
+        }
+    }
+}
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    ::prost::Enumeration,
+)]
+#[repr(i32)]
+pub enum EthV {
+    RecoveryId = 0,
+    Legacy = 1,
+    Eip155 = 2,
+}
+impl EthV {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            EthV::RecoveryId => "RECOVERY_ID",
+            EthV::Legacy => "LEGACY",
+            EthV::Eip155 => "EIP155",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "RECOVERY_ID" => Some(Self::RecoveryId),
+            "LEGACY" => Some(Self::Legacy),
+            "EIP155" => Some(Self::Eip155),
+            _ => None,
+        }
+    }
+}
 /// Generated client implementations.
 pub mod mpc_session_manager_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -228,6 +440,128 @@ pub mod mpc_session_manager_client {
                 .insert(GrpcMethod::new("svarog.MpcSessionManager", "Ping"));
             self.inner.unary(req, path, codec).await
         }
+        /// One mail per (session, recipient, sender). A different mail under the same sender
+        /// is rejected with ALREADY_EXISTS; senders are not authenticated.
+        pub async fn put_mail(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Mail>,
+        ) -> std::result::Result<tonic::Response<super::Void>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/svarog.MpcSessionManager/PutMail");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("svarog.MpcSessionManager", "PutMail"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_mail(
+            &mut self,
+            request: impl tonic::IntoRequest<super::MailboxId>,
+        ) -> std::result::Result<tonic::Response<super::VecMail>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/svarog.MpcSessionManager/GetMail");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("svarog.MpcSessionManager", "GetMail"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn report_blame(
+            &mut self,
+            request: impl tonic::IntoRequest<super::BlameRecord>,
+        ) -> std::result::Result<tonic::Response<super::Void>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path =
+                http::uri::PathAndQuery::from_static("/svarog.MpcSessionManager/ReportBlame");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("svarog.MpcSessionManager", "ReportBlame"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_blame(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SessionId>,
+        ) -> std::result::Result<tonic::Response<super::VecBlameRecord>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/svarog.MpcSessionManager/GetBlame");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("svarog.MpcSessionManager", "GetBlame"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn post_ready(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ReadyMarker>,
+        ) -> std::result::Result<tonic::Response<super::Void>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/svarog.MpcSessionManager/PostReady");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("svarog.MpcSessionManager", "PostReady"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_ready(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SessionId>,
+        ) -> std::result::Result<tonic::Response<super::VecReadyMarker>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/svarog.MpcSessionManager/GetReady");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("svarog.MpcSessionManager", "GetReady"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn check_in(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CheckIn>,
+        ) -> std::result::Result<tonic::Response<super::SignerSet>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/svarog.MpcSessionManager/CheckIn");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("svarog.MpcSessionManager", "CheckIn"));
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -257,6 +591,36 @@ pub mod mpc_session_manager_server {
             &self,
             request: tonic::Request<super::Void>,
         ) -> std::result::Result<tonic::Response<super::EchoMessage>, tonic::Status>;
+        /// One mail per (session, recipient, sender). A different mail under the same sender
+        /// is rejected with ALREADY_EXISTS; senders are not authenticated.
+        async fn put_mail(
+            &self,
+            request: tonic::Request<super::Mail>,
+        ) -> std::result::Result<tonic::Response<super::Void>, tonic::Status>;
+        async fn get_mail(
+            &self,
+            request: tonic::Request<super::MailboxId>,
+        ) -> std::result::Result<tonic::Response<super::VecMail>, tonic::Status>;
+        async fn report_blame(
+            &self,
+            request: tonic::Request<super::BlameRecord>,
+        ) -> std::result::Result<tonic::Response<super::Void>, tonic::Status>;
+        async fn get_blame(
+            &self,
+            request: tonic::Request<super::SessionId>,
+        ) -> std::result::Result<tonic::Response<super::VecBlameRecord>, tonic::Status>;
+        async fn post_ready(
+            &self,
+            request: tonic::Request<super::ReadyMarker>,
+        ) -> std::result::Result<tonic::Response<super::Void>, tonic::Status>;
+        async fn get_ready(
+            &self,
+            request: tonic::Request<super::SessionId>,
+        ) -> std::result::Result<tonic::Response<super::VecReadyMarker>, tonic::Status>;
+        async fn check_in(
+            &self,
+            request: tonic::Request<super::CheckIn>,
+        ) -> std::result::Result<tonic::Response<super::SignerSet>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct MpcSessionManagerServer<T: MpcSessionManager> {
@@ -533,6 +897,283 @@ pub mod mpc_session_manager_server {
                     };
                     Box::pin(fut)
                 }
+                "/svarog.MpcSessionManager/PutMail" => {
+                    #[allow(non_camel_case_types)]
+                    struct PutMailSvc<T: MpcSessionManager>(pub Arc<T>);
+                    impl<T: MpcSessionManager> tonic::server::UnaryService<super::Mail> for PutMailSvc<T> {
+                        type Response = super::Void;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(&mut self, request: tonic::Request<super::Mail>) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MpcSessionManager>::put_mail(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PutMailSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/svarog.MpcSessionManager/GetMail" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetMailSvc<T: MpcSessionManager>(pub Arc<T>);
+                    impl<T: MpcSessionManager> tonic::server::UnaryService<super::MailboxId> for GetMailSvc<T> {
+                        type Response = super::VecMail;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::MailboxId>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MpcSessionManager>::get_mail(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetMailSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/svarog.MpcSessionManager/ReportBlame" => {
+                    #[allow(non_camel_case_types)]
+                    struct ReportBlameSvc<T: MpcSessionManager>(pub Arc<T>);
+                    impl<T: MpcSessionManager> tonic::server::UnaryService<super::BlameRecord> for ReportBlameSvc<T> {
+                        type Response = super::Void;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::BlameRecord>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MpcSessionManager>::report_blame(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ReportBlameSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/svarog.MpcSessionManager/GetBlame" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetBlameSvc<T: MpcSessionManager>(pub Arc<T>);
+                    impl<T: MpcSessionManager> tonic::server::UnaryService<super::SessionId> for GetBlameSvc<T> {
+                        type Response = super::VecBlameRecord;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SessionId>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MpcSessionManager>::get_blame(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetBlameSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/svarog.MpcSessionManager/PostReady" => {
+                    #[allow(non_camel_case_types)]
+                    struct PostReadySvc<T: MpcSessionManager>(pub Arc<T>);
+                    impl<T: MpcSessionManager> tonic::server::UnaryService<super::ReadyMarker> for PostReadySvc<T> {
+                        type Response = super::Void;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ReadyMarker>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MpcSessionManager>::post_ready(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PostReadySvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/svarog.MpcSessionManager/GetReady" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetReadySvc<T: MpcSessionManager>(pub Arc<T>);
+                    impl<T: MpcSessionManager> tonic::server::UnaryService<super::SessionId> for GetReadySvc<T> {
+                        type Response = super::VecReadyMarker;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SessionId>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MpcSessionManager>::get_ready(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetReadySvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/svarog.MpcSessionManager/CheckIn" => {
+                    #[allow(non_camel_case_types)]
+                    struct CheckInSvc<T: MpcSessionManager>(pub Arc<T>);
+                    impl<T: MpcSessionManager> tonic::server::UnaryService<super::CheckIn> for CheckInSvc<T> {
+                        type Response = super::SignerSet;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CheckIn>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MpcSessionManager>::check_in(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = CheckInSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => Box::pin(async move {
                     Ok(http::Response::builder()
                         .status(200)
@@ -30,12 +30,23 @@ pub fn mock_sign_tasks() -> Vec<SignTask> {
         tasks.push(SignTask {
             message: hmsg,
             bip32_path: dpath.to_string(),
+            kind: MessageKind::Prehashed32,
         });
     }
 
     tasks
 }
 
+pub fn mock_sign_tasks_ed25519() -> Vec<SignTask> {
+    mock_sign_tasks()
+        .into_iter()
+        .map(|task| SignTask {
+            kind: MessageKind::RawEd25519,
+            ..task
+        })
+        .collect()
+}
+
 pub fn mock_one_sign_task() -> Vec<SignTask> {
     vec![mock_sign_tasks().pop().unwrap()]
 }
@@ -2,8 +2,8 @@
 use std::collections::BTreeMap;
 
 use erreur::*;
-use mock_data::mock_sign_tasks;
-use svarog_peer::{btc, new_session, solana};
+use mock_data::{mock_sign_tasks, mock_sign_tasks_ed25519};
+use svarog_peer::{bip32::derive_pk_ed25519, btc, new_session, solana, solana_tx};
 
 // 改成通配符引用之后, 会难以检查到底用了哪些符号. 通配符看着优雅, 但是不利于代码审查.
 use crate::mock_data::{mock_keygen_config, mock_sign_config, players1, th1};
@@ -121,7 +121,7 @@ async fn test_solana() -> Resultat<()> {
                 sesman_url.to_owned(),
                 sid.clone(),
                 keystore.clone(),
-                mock_sign_tasks(),
+                mock_sign_tasks_ed25519(),
             );
             let thread = tokio::spawn(future);
             threads.insert(player, thread);
@@ -140,5 +140,27 @@ async fn test_solana() -> Resultat<()> {
         assert_throw!(sig == sig0);
     }
 
+    // 本crate的ed25519派生须与svarog_algo签名时的派生一致, 否则非根路径上的签名都会被拒绝.
+    let keystore = keystores.values().next().ifnone_()?;
+    for (task, sig) in mock_sign_tasks_ed25519().iter().zip(sig0.iter()) {
+        let pk = derive_pk_ed25519(&keystore.share, &task.bip32_path).catch_()?;
+        assert_throw!(
+            sig.pk == pk.compress().to_bytes(),
+            format!(
+                "ed25519 derivation at {} differs from svarog_algo",
+                &task.bip32_path
+            )
+        );
+        // 派生的Solana地址必须能由MPC签名花费.
+        let address = solana_tx::derive_address(&keystore.share, &task.bip32_path).catch_()?;
+        assert_throw!(
+            address == bs58::encode(&sig.pk).into_string(),
+            format!(
+                "solana address at {} is not the signing key",
+                &task.bip32_path
+            )
+        );
+    }
+
     Ok(())
 }
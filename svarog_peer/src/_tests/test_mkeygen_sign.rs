@@ -2,7 +2,7 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use erreur::*;
-use mock_data::{mock_mnem, mock_sign_tasks};
+use mock_data::{mock_mnem, mock_sign_tasks, mock_sign_tasks_ed25519};
 use svarog_peer::{btc, new_session, solana};
 
 // 改成通配符引用之后, 会难以检查到底用了哪些符号. 通配符看着优雅, 但是不利于代码审查.
@@ -149,7 +149,7 @@ async fn test_solana() -> Resultat<()> {
                 sesman_url.to_owned(),
                 sid.clone(),
                 keystore.clone(),
-                mock_sign_tasks(),
+                mock_sign_tasks_ed25519(),
             );
             let thread = tokio::spawn(future);
             threads.insert(player, thread);
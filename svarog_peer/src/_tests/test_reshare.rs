@@ -2,8 +2,8 @@
 use std::collections::BTreeMap;
 
 use erreur::*;
-use mock_data::mock_sign_tasks;
-use svarog_peer::{btc, new_session, solana};
+use mock_data::{mock_sign_tasks, mock_sign_tasks_ed25519};
+use svarog_peer::{btc, new_session, refresh::refresh_config, solana};
 
 use crate::mock_data::{
     mock_keygen_config, mock_reshare_config, mock_sign_config, players1, players2, th1, th2,
@@ -12,7 +12,7 @@ use crate::mock_data::{
 mod mock_data;
 const sesman_url: &str = "http://127.0.0.1:2000";
 
-/// 集成测试普通的keygen, sign
+/// 集成测试keygen, reshare, refresh, sign
 #[tokio::main]
 async fn main() -> Resultat<()> {
     test_btc().await.catch_()?;
@@ -75,6 +75,34 @@ async fn test_btc() -> Resultat<()> {
         keystores
     };
 
+    // refresh the reshared keystores without changing membership
+    let keystores = {
+        let keystores: BTreeMap<String, _> = keystores
+            .into_iter()
+            .filter_map(|(player, keystore)| keystore.map(|keystore| (player, keystore)))
+            .collect();
+        let cfg = refresh_config(keystores.values().next().ifnone_()?, sesman_url);
+        let sid = new_session(cfg.clone()).await.catch_()?;
+        let mut threads = BTreeMap::new();
+        for (player, keystore) in keystores.iter() {
+            let future = btc::biz_refresh(
+                sesman_url.to_owned(),
+                sid.clone(),
+                player.clone(),
+                keystore.clone(),
+            );
+            let thread = tokio::spawn(future);
+            threads.insert(player.clone(), thread);
+        }
+        let mut refreshed = BTreeMap::new();
+        for (player, thread) in threads.iter_mut() {
+            let resp = thread.await.catch("Panic", "")?.catch("Exception", "")?;
+            assert_throw!(resp.epoch == keystores.get(player).ifnone_()?.epoch + 1);
+            refreshed.insert(player.clone(), Some(resp));
+        }
+        refreshed
+    };
+
     let signatures = {
         let cfg = mock_sign_config(th2, &players2, sesman_url);
         let sid = new_session(cfg.clone()).await.catch_()?;
@@ -165,6 +193,34 @@ async fn test_solana() -> Resultat<()> {
         keystores
     };
 
+    // refresh the reshared keystores without changing membership
+    let keystores = {
+        let keystores: BTreeMap<String, _> = keystores
+            .into_iter()
+            .filter_map(|(player, keystore)| keystore.map(|keystore| (player, keystore)))
+            .collect();
+        let cfg = refresh_config(keystores.values().next().ifnone_()?, sesman_url);
+        let sid = new_session(cfg.clone()).await.catch_()?;
+        let mut threads = BTreeMap::new();
+        for (player, keystore) in keystores.iter() {
+            let future = solana::biz_refresh(
+                sesman_url.to_owned(),
+                sid.clone(),
+                player.clone(),
+                keystore.clone(),
+            );
+            let thread = tokio::spawn(future);
+            threads.insert(player.clone(), thread);
+        }
+        let mut refreshed = BTreeMap::new();
+        for (player, thread) in threads.iter_mut() {
+            let resp = thread.await.catch("Panic", "")?.catch("Exception", "")?;
+            assert_throw!(resp.epoch == keystores.get(player).ifnone_()?.epoch + 1);
+            refreshed.insert(player.clone(), Some(resp));
+        }
+        refreshed
+    };
+
     let signatures = {
         let cfg = mock_sign_config(th2, &players2, sesman_url);
         let sid = new_session(cfg.clone()).await.catch_()?;
@@ -178,7 +234,7 @@ async fn test_solana() -> Resultat<()> {
                 sesman_url.to_owned(),
                 sid.clone(),
                 keystore.clone(),
-                mock_sign_tasks(),
+                mock_sign_tasks_ed25519(),
             );
             let thread = tokio::spawn(future);
             threads.insert(player, thread);
@@ -0,0 +1,418 @@
+//! 签名审批. 启用后, 每个签名请求先进入待审批队列, 附带任务的可读摘要;
+//! 审批人通过 `approve` / `reject` 确认后, 本方才进入 `sign_batch`.
+//! 超时未处理的请求按 `ApprovalConfig.approve_on_expiry` 通过或拒绝.
+//!
+//! 通过的请求生成一张审批回执, 由本方的回执密钥做Ed25519签名,
+//! 与签名结果一起写入 `receipt_dir/<session_id>-<i>.json`.
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Mutex, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ed25519_dalek::{Signer, SigningKey};
+use erreur::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::oneshot;
+
+use crate::{policy::SignRequest, structs::Signature, verify::verify_ed25519};
+
+/// `Debug` 不输出回执密钥.
+#[derive(Clone)]
+pub struct ApprovalConfig {
+    /// 等待审批的最长秒数, 应小于会话的有效期.
+    pub timeout_secs: u64,
+    /// 超时后是否视为通过.
+    pub approve_on_expiry: bool,
+    /// 回执密钥, 32字节的Ed25519种子.
+    pub receipt_key: [u8; 32],
+    pub receipt_dir: PathBuf,
+}
+
+impl std::fmt::Debug for ApprovalConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApprovalConfig")
+            .field("timeout_secs", &self.timeout_secs)
+            .field("approve_on_expiry", &self.approve_on_expiry)
+            .field("receipt_dir", &self.receipt_dir)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 队列中的一条待审批请求.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PendingApproval {
+    /// `<session_id>-<i>`.
+    pub request_id: String,
+    pub session_id: String,
+    pub key_id: String,
+    pub summary: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Approved { approver: String },
+    Rejected { approver: String },
+    Expired,
+}
+
+/// 回执中被签名的部分.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReceiptBody {
+    pub request: PendingApproval,
+    /// 所有任务 `(消息, bip32路径)` 的SHA256.
+    pub tasks_digest: [u8; 32],
+    pub decision: Decision,
+    pub decided_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApprovalReceipt {
+    pub body: ReceiptBody,
+    /// 对 `body` 的JSON编码的Ed25519签名.
+    pub signature: Signature,
+}
+
+/// `receipt_dir` 中保存的记录.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApprovedSignatures {
+    pub receipt: ApprovalReceipt,
+    pub signatures: Vec<Signature>,
+}
+
+struct Pending {
+    info: PendingApproval,
+    tx: oneshot::Sender<Decision>,
+}
+
+static APPROVAL: RwLock<Option<ApprovalConfig>> = RwLock::new(None);
+static PENDING: Mutex<BTreeMap<String, Pending>> = Mutex::new(BTreeMap::new());
+
+/// 启用或关闭(`None`)签名审批.
+pub fn install_approval(config: Option<ApprovalConfig>) -> Resultat<()> {
+    if let Some(config) = &config {
+        assert_throw!(
+            config.timeout_secs > 0,
+            "approval timeout should be positive"
+        );
+        std::fs::create_dir_all(&config.receipt_dir).catch(
+            "InvalidApprovalConfig",
+            format!("cannot create {}", config.receipt_dir.display()),
+        )?;
+    }
+    *APPROVAL.write().ok().ifnone("PoisonedLock", "approval")? = config;
+    Ok(())
+}
+
+fn installed_approval() -> Option<ApprovalConfig> {
+    APPROVAL.read().ok().and_then(|config| config.clone())
+}
+
+/// 当前等待审批的请求, 按 `request_id` 排序.
+pub fn pending_approvals() -> Resultat<Vec<PendingApproval>> {
+    let pending = PENDING.lock().ok().ifnone("PoisonedLock", "pending")?;
+    Ok(pending.values().map(|p| p.info.clone()).collect())
+}
+
+fn decide(request_id: &str, decision: Decision) -> Resultat<()> {
+    let entry = PENDING
+        .lock()
+        .ok()
+        .ifnone("PoisonedLock", "pending")?
+        .remove(request_id)
+        .ifnone(
+            "UnknownRequest",
+            format!("no pending request {}", request_id),
+        )?;
+    // 等待方已放弃(例如会话失败)时发送失败, 无需处理.
+    let _ = entry.tx.send(decision);
+    Ok(())
+}
+
+pub fn approve(request_id: &str, approver: &str) -> Resultat<()> {
+    decide(
+        request_id,
+        Decision::Approved {
+            approver: approver.to_owned(),
+        },
+    )
+}
+
+pub fn reject(request_id: &str, approver: &str) -> Resultat<()> {
+    decide(
+        request_id,
+        Decision::Rejected {
+            approver: approver.to_owned(),
+        },
+    )
+}
+
+/// 给审批人看的任务摘要.
+pub fn summarize(algorithm: &str, req: &SignRequest, tasks: &[(Vec<u8>, String)]) -> String {
+    let cosigners: Vec<&str> = req.cosigners.iter().map(|s| s.as_str()).collect();
+    let mut lines = vec![format!(
+        "{} sign {} task(s) with key {}, cosigners: {}",
+        algorithm,
+        tasks.len(),
+        &req.key_id,
+        cosigners.join(", ")
+    )];
+    for (k, (message, path)) in tasks.iter().enumerate() {
+        let shown = if message.len() > 64 {
+            format!(
+                "{}... ({} bytes)",
+                hex::encode(&message[..64]),
+                message.len()
+            )
+        } else {
+            hex::encode(message)
+        };
+        lines.push(format!("  #{} {} {}", k, path, shown));
+    }
+    lines.join("\n")
+}
+
+pub fn tasks_digest(tasks: &[(Vec<u8>, String)]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for (message, path) in tasks {
+        hasher.update((message.len() as u64).to_be_bytes());
+        hasher.update(message);
+        hasher.update((path.len() as u64).to_be_bytes());
+        hasher.update(path.as_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// RFC 8032 Ed25519签名.
+fn ed25519_sign(seed: &[u8; 32], message: &[u8]) -> Signature {
+    let sk = SigningKey::from_bytes(seed);
+    let sig = sk.sign(message);
+    Signature {
+        r: *sig.r_bytes(),
+        s: *sig.s_bytes(),
+        v: 0,
+        pk: sk.verifying_key().to_bytes().to_vec(),
+    }
+}
+
+impl ApprovalReceipt {
+    fn sign(body: ReceiptBody, receipt_key: &[u8; 32]) -> Resultat<Self> {
+        let msg = serde_json::to_vec(&body).catch_()?;
+        Ok(Self {
+            signature: ed25519_sign(receipt_key, &msg),
+            body,
+        })
+    }
+
+    /// 验证回执签名. `receipt_pk` 是签发方回执密钥的公钥.
+    pub fn verify(&self, receipt_pk: &[u8]) -> Resultat<()> {
+        assert_throw!(
+            self.signature.pk == receipt_pk,
+            "receipt is signed by another key"
+        );
+        let msg = serde_json::to_vec(&self.body).catch_()?;
+        verify_ed25519(&self.signature, &msg).catch("InvalidReceipt", "")?;
+        Ok(())
+    }
+}
+
+/// 回执密钥的公钥.
+pub fn receipt_pk(receipt_key: &[u8; 32]) -> Vec<u8> {
+    SigningKey::from_bytes(receipt_key)
+        .verifying_key()
+        .to_bytes()
+        .to_vec()
+}
+
+fn now_secs() -> Resultat<u64> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .catch_()?
+        .as_secs())
+}
+
+/// 未启用审批时立即返回 `None`; 否则把请求放入队列, 等到审批人决定或超时.
+/// 被拒绝时返回错误, 本方不进入 `sign_batch`.
+pub(crate) async fn await_approval(
+    session_id: &str,
+    i: usize,
+    algorithm: &str,
+    req: &SignRequest,
+    tasks: &[(Vec<u8>, String)],
+) -> Resultat<Option<(ApprovalReceipt, ApprovalConfig)>> {
+    let config = match installed_approval() {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+    let now = now_secs().catch_()?;
+    let info = PendingApproval {
+        request_id: format!("{}-{}", session_id, i),
+        session_id: session_id.to_owned(),
+        key_id: req.key_id.clone(),
+        summary: summarize(algorithm, req, tasks),
+        created_at: now,
+        expires_at: now + config.timeout_secs,
+    };
+    let (tx, rx) = oneshot::channel();
+    {
+        let mut pending = PENDING.lock().ok().ifnone("PoisonedLock", "pending")?;
+        assert_throw!(
+            !pending.contains_key(&info.request_id),
+            format!("request {} is already pending", &info.request_id)
+        );
+        pending.insert(
+            info.request_id.clone(),
+            Pending {
+                info: info.clone(),
+                tx,
+            },
+        );
+    }
+
+    let decision = match tokio::time::timeout(Duration::from_secs(config.timeout_secs), rx).await {
+        Ok(decision) => decision.catch("ApprovalAborted", &info.request_id)?,
+        Err(_) => {
+            if let Ok(mut pending) = PENDING.lock() {
+                pending.remove(&info.request_id);
+            }
+            Decision::Expired
+        }
+    };
+    let approved = match &decision {
+        Decision::Approved { .. } => true,
+        Decision::Rejected { .. } => false,
+        Decision::Expired => config.approve_on_expiry,
+    };
+    assert_throw!(
+        approved,
+        format!(
+            "request {} is not approved: {:?}",
+            &info.request_id, &decision
+        )
+    );
+
+    let body = ReceiptBody {
+        request: info,
+        tasks_digest: tasks_digest(tasks),
+        decision,
+        decided_at: now_secs().catch_()?,
+    };
+    let receipt = ApprovalReceipt::sign(body, &config.receipt_key).catch_()?;
+    Ok(Some((receipt, config)))
+}
+
+/// 把回执与签名结果一起写入 `receipt_dir`.
+pub(crate) fn store_receipt(
+    config: &ApprovalConfig,
+    receipt: ApprovalReceipt,
+    signatures: &[Signature],
+) -> Resultat<()> {
+    let path = config
+        .receipt_dir
+        .join(format!("{}.json", &receipt.body.request.request_id));
+    let record = ApprovedSignatures {
+        receipt,
+        signatures: signatures.to_vec(),
+    };
+    let json = serde_json::to_vec_pretty(&record).catch_()?;
+    std::fs::write(&path, json).catch("IOError", format!("{}", path.display()))?;
+    Ok(())
+}
+
+pub fn load_receipt(path: &std::path::Path) -> Resultat<ApprovedSignatures> {
+    let json = std::fs::read(path).catch("IOError", format!("{}", path.display()))?;
+    let record = serde_json::from_slice(&json).catch("InvalidReceipt", "")?;
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use svarog_algo::schnorr_ed25519::KeystoreSchnorr;
+
+    use super::*;
+    use crate::keystore::MpcKeystore;
+
+    // RFC 8032 test vector 1.
+    #[test]
+    fn test_ed25519_sign() -> Resultat<()> {
+        let seed: [u8; 32] =
+            hex::decode("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60")
+                .catch_()?
+                .try_into()
+                .ok()
+                .ifnone_()?;
+        let sig = ed25519_sign(&seed, b"");
+        assert_throw!(
+            hex::encode(&sig.pk)
+                == "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+        assert_throw!(
+            hex::encode([sig.r, sig.s].concat())
+                == "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+        );
+        assert_throw!(sig.pk == receipt_pk(&seed));
+
+        let vk =
+            ed25519_dalek::VerifyingKey::from_bytes(&sig.pk.clone().try_into().ok().ifnone_()?)
+                .catch_()?;
+        let std_sig = ed25519_dalek::Signature::from_components(sig.r, sig.s);
+        vk.verify_strict(b"", &std_sig).catch_()?;
+        verify_ed25519(&sig, b"").catch_()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_approval_queue() -> Resultat<()> {
+        let dir = std::env::temp_dir().join(format!("svarog-approval-{}", std::process::id()));
+        let receipt_key = [5u8; 32];
+        install_approval(Some(ApprovalConfig {
+            timeout_secs: 30,
+            approve_on_expiry: false,
+            receipt_key,
+            receipt_dir: dir.clone(),
+        }))
+        .catch_()?;
+        let req = SignRequest {
+            key_id: "k".to_owned(),
+            cosigners: BTreeSet::from(["Alice".to_owned()]),
+            bip32_paths: vec!["m/1".to_owned()],
+        };
+        let tasks = vec![(vec![1u8; 32], "m/1".to_owned())];
+
+        let approver = tokio::spawn(async {
+            loop {
+                let pending = pending_approvals().unwrap();
+                if let Some(p) = pending.first() {
+                    approve(&p.request_id, "Carol").unwrap();
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        let (receipt, config) = await_approval("ses", 1, KeystoreSchnorr::ALGO, &req, &tasks)
+            .await
+            .catch_()?
+            .ifnone_()?;
+        approver.await.catch_()?;
+        receipt.verify(&receipt_pk(&receipt_key)).catch_()?;
+        assert_throw!(receipt.body.tasks_digest == tasks_digest(&tasks));
+
+        store_receipt(&config, receipt.clone(), &[]).catch_()?;
+        let record = load_receipt(&dir.join("ses-1.json")).catch_()?;
+        assert_throw!(record.receipt == receipt);
+
+        let mut forged = receipt;
+        forged.body.decision = Decision::Expired;
+        assert_throw!(forged.verify(&receipt_pk(&receipt_key)).is_err());
+
+        install_approval(None).catch_()?;
+        std::fs::remove_dir_all(&dir).catch_()?;
+        Ok(())
+    }
+}
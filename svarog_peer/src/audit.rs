@@ -0,0 +1,474 @@
+//! 审计日志. 每次Keygen, KeygenMnem, Sign, Reshare, Refresh, RootExport (无论成败) 在本地追加一条记录.
+//!
+//! 日志是JSON Lines文件, 每行一条 `AuditRecord`. 记录的 `hash` 是
+//! `SHA256(JSON(AuditContent))`, 而 `AuditContent.prev_hash` 是上一条记录的 `hash`,
+//! 第一条记录的 `prev_hash` 为全零. 修改, 删除或重排任何一条记录都会破坏哈希链,
+//! 可用 `verify_chain` 或 `svarog_audit_verify` 离线检查.
+//! 截断尾部的记录无法由哈希链本身发现, 需要与另行保存的最新哈希比对.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use erreur::*;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use svarog_grpc::SessionConfig;
+
+use crate::{
+    keystore::{key_id, share_fingerprint, MpcKeystore},
+    sig_encoding::to_compact,
+    structs::Signature,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Keygen,
+    KeygenMnem,
+    Sign,
+    Reshare,
+    Refresh,
+    RootExport,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TaskDigest {
+    /// 交给 `sign_batch` 的消息的SHA256, hex编码.
+    pub digest: String,
+    pub bip32_path: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Failure { error: String },
+}
+
+/// 一次操作的内容.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct AuditEvent {
+    pub operation: Option<Operation>,
+    pub algorithm: String,
+    pub session_id: String,
+    /// `SessionConfig` 的规范化SHA256, hex编码. 未取得会话配置时为空.
+    pub config_digest: String,
+    /// `config_digest` 的编码版本, 见 `CONFIG_DIGEST_VERSION`.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub config_digest_version: u32,
+    /// 出席的成员. Reshare时是provider.
+    pub participants: Vec<String>,
+    /// Reshare的consumer.
+    pub consumers: Vec<String>,
+    pub tasks: Vec<TaskDigest>,
+    /// Keygen, Reshare和Refresh得到的群公钥, hex编码.
+    pub group_pk: String,
+    /// 所涉及的群密钥的key id, 见 `keystore::key_id`. Sign为签名所用的, 其余为得到的.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub key_id: String,
+    /// 本方分片的指纹, 见 `keystore::share_fingerprint`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub share_fingerprint: String,
+    /// Sign得到的签名, `r || s` 的hex编码, 与 `tasks` 一一对应.
+    pub signatures: Vec<String>,
+    pub outcome: Option<Outcome>,
+}
+
+/// 参与哈希的部分.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditContent {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub prev_hash: String,
+    pub event: AuditEvent,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    #[serde(flatten)]
+    pub content: AuditContent,
+    pub hash: String,
+}
+
+fn is_zero(v: &u32) -> bool {
+    *v == 0
+}
+
+impl AuditContent {
+    pub fn hash(&self) -> Resultat<String> {
+        let json = serde_json::to_vec(self).catch_()?;
+        Ok(hex::encode(Sha256::digest(json)))
+    }
+}
+
+/// `config_digest` 的编码版本. 记录中缺省 (0) 表示早期按字段列举的JSON编码.
+pub const CONFIG_DIGEST_VERSION: u32 = 2;
+
+/// 整个 `SessionConfig` 的规范化protobuf编码的SHA256, 与map的遍历顺序无关.
+pub fn config_digest(cfg: &SessionConfig) -> Resultat<String> {
+    Ok(hex::encode(Sha256::digest(canonical_config(cfg))))
+}
+
+/// 先编码去掉map之后的配置, 再按键的顺序逐条追加map的条目. 拼接的protobuf编码等同于合并,
+/// 因此结果仍是该配置的合法编码, 新增的标量字段自动计入.
+fn canonical_config(cfg: &SessionConfig) -> Vec<u8> {
+    let mut rest = cfg.clone();
+    rest.players.clear();
+    rest.players_reshared.clear();
+    rest.mailbox_pks.clear();
+    let mut buf = rest.encode_to_vec();
+
+    let players: BTreeMap<&String, &bool> = cfg.players.iter().collect();
+    for (name, &att) in players {
+        let entry = SessionConfig {
+            players: HashMap::from([(name.clone(), att)]),
+            ..Default::default()
+        };
+        buf.extend(entry.encode_to_vec());
+    }
+    let players_reshared: BTreeMap<&String, &bool> = cfg.players_reshared.iter().collect();
+    for (name, &att) in players_reshared {
+        let entry = SessionConfig {
+            players_reshared: HashMap::from([(name.clone(), att)]),
+            ..Default::default()
+        };
+        buf.extend(entry.encode_to_vec());
+    }
+    let mailbox_pks: BTreeMap<&String, &Vec<u8>> = cfg.mailbox_pks.iter().collect();
+    for (name, pk) in mailbox_pks {
+        let entry = SessionConfig {
+            mailbox_pks: HashMap::from([(name.clone(), pk.clone())]),
+            ..Default::default()
+        };
+        buf.extend(entry.encode_to_vec());
+    }
+    buf
+}
+
+pub fn genesis_hash() -> String {
+    hex::encode([0u8; 32])
+}
+
+struct AuditLog {
+    path: PathBuf,
+    next_seq: u64,
+    last_hash: String,
+}
+
+static AUDIT_LOG: Mutex<Option<AuditLog>> = Mutex::new(None);
+
+/// 没能写入审计日志的记录, 见 `AuditEvent::finish_keystore`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnloggedEvent {
+    pub event: AuditEvent,
+    /// 写入失败的原因.
+    pub error: String,
+}
+
+static UNLOGGED: Mutex<Vec<UnloggedEvent>> = Mutex::new(Vec::new());
+
+/// 取出并清空没能写入审计日志的记录. 调用方应把它们告警给运维, 并在修复日志后另行补记.
+pub fn take_unlogged() -> Vec<UnloggedEvent> {
+    match UNLOGGED.lock() {
+        Ok(mut unlogged) => std::mem::take(&mut *unlogged),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// 检查日志文件的哈希链, 返回所有记录. 文件不存在视为空日志.
+pub fn verify_chain(path: &Path) -> Resultat<Vec<AuditRecord>> {
+    let mut records: Vec<AuditRecord> = Vec::new();
+    if !path.exists() {
+        return Ok(records);
+    }
+    let file = std::fs::File::open(path).catch("IOError", format!("{}", path.display()))?;
+    let mut prev_hash = genesis_hash();
+    for (k, line) in BufReader::new(file).lines().enumerate() {
+        let ctx = format!("line {}", k + 1);
+        let line = line.catch("IOError", &ctx)?;
+        let record: AuditRecord = serde_json::from_str(&line).catch("BrokenAuditLog", &ctx)?;
+        assert_throw!(
+            record.content.seq == k as u64,
+            format!("{}: seq is {}, expected {}", &ctx, record.content.seq, k)
+        );
+        assert_throw!(
+            record.content.prev_hash == prev_hash,
+            format!("{}: prev_hash does not match the previous record", &ctx)
+        );
+        let hash = record.content.hash().catch_()?;
+        assert_throw!(
+            record.hash == hash,
+            format!("{}: record hash mismatch, the record was modified", &ctx)
+        );
+        prev_hash = hash;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// 启用或关闭(`None`)审计日志. 已有的日志文件先经过 `verify_chain`, 新记录接在其后.
+pub fn install_audit_log(path: Option<PathBuf>) -> Resultat<()> {
+    let log = match path {
+        Some(path) => {
+            let records = verify_chain(&path).catch_()?;
+            Some(AuditLog {
+                next_seq: records.len() as u64,
+                last_hash: records
+                    .last()
+                    .map(|r| r.hash.clone())
+                    .unwrap_or_else(genesis_hash),
+                path,
+            })
+        }
+        None => None,
+    };
+    *AUDIT_LOG.lock().ok().ifnone("PoisonedLock", "audit log")? = log;
+    Ok(())
+}
+
+fn append(event: AuditEvent) -> Resultat<()> {
+    let mut guard = AUDIT_LOG.lock().ok().ifnone("PoisonedLock", "audit log")?;
+    let log = match guard.as_mut() {
+        Some(log) => log,
+        None => return Ok(()),
+    };
+    let content = AuditContent {
+        seq: log.next_seq,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .catch_()?
+            .as_secs(),
+        prev_hash: log.last_hash.clone(),
+        event,
+    };
+    let record = AuditRecord {
+        hash: content.hash().catch_()?,
+        content,
+    };
+    let mut line = serde_json::to_string(&record).catch_()?;
+    line.push('\n');
+    let ctx = format!("{}", log.path.display());
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log.path)
+        .catch("IOError", &ctx)?;
+    file.write_all(line.as_bytes()).catch("IOError", &ctx)?;
+    file.sync_data().catch("IOError", &ctx)?;
+    log.next_seq += 1;
+    log.last_hash = record.hash;
+    Ok(())
+}
+
+impl AuditEvent {
+    pub(crate) fn new(operation: Operation, algorithm: &str, session_id: &str) -> Self {
+        Self {
+            operation: Some(operation),
+            algorithm: algorithm.to_owned(),
+            session_id: session_id.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn set_config(&mut self, cfg: &SessionConfig) -> Resultat<()> {
+        let attending = |names: &std::collections::HashMap<String, bool>| {
+            let mut names: Vec<String> = names
+                .iter()
+                .filter(|(_, &att)| att)
+                .map(|(name, _)| name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        self.config_digest = config_digest(cfg).catch_()?;
+        self.config_digest_version = CONFIG_DIGEST_VERSION;
+        self.participants = attending(&cfg.players);
+        self.consumers = attending(&cfg.players_reshared);
+        Ok(())
+    }
+
+    pub(crate) fn set_tasks(&mut self, tasks: &[(Vec<u8>, String)]) {
+        self.tasks = tasks
+            .iter()
+            .map(|(message, bip32_path)| TaskDigest {
+                digest: hex::encode(Sha256::digest(message)),
+                bip32_path: bip32_path.clone(),
+            })
+            .collect();
+    }
+
+    pub(crate) fn set_signatures(&mut self, sigs: &[Signature]) {
+        self.signatures = sigs
+            .iter()
+            .map(|sig| hex::encode(to_compact(sig)))
+            .collect();
+    }
+
+    pub(crate) fn set_keystore<K: MpcKeystore>(&mut self, keystore: &K) {
+        self.key_id = key_id(keystore);
+        self.share_fingerprint = share_fingerprint(keystore);
+    }
+
+    pub(crate) fn set_new_keystore<K: MpcKeystore>(&mut self, keystore: &K) {
+        self.group_pk = hex::encode(keystore.group_pk());
+        self.set_keystore(keystore);
+    }
+
+    fn set_outcome<T>(&mut self, res: &Resultat<T>) {
+        self.outcome = Some(match res {
+            Ok(_) => Outcome::Success,
+            Err(e) => Outcome::Failure {
+                error: format!("{}", e),
+            },
+        });
+    }
+
+    /// 记录操作结果并原样返回. 审计日志写入失败时返回错误.
+    pub(crate) fn finish<T>(mut self, res: Resultat<T>) -> Resultat<T> {
+        self.set_outcome(&res);
+        append(self).catch("AuditLogFailure", "")?;
+        res
+    }
+
+    /// 得到新Keystore的操作 (Keygen, KeygenMnem, Reshare, Refresh) 用的 `finish`.
+    /// 审计日志写入失败时仍返回 `res`, 否则新的分片会随错误丢失, 而旧的分片可能已经作废;
+    /// 没写入的记录放进 `take_unlogged` 的队列.
+    pub(crate) fn finish_keystore<T>(mut self, res: Resultat<T>) -> Resultat<T> {
+        self.set_outcome(&res);
+        if let Err(e) = append(self.clone()) {
+            let unlogged = UnloggedEvent {
+                event: self,
+                error: format!("{}", e),
+            };
+            if let Ok(mut queue) = UNLOGGED.lock() {
+                queue.push(unlogged);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 审计日志是进程内的全局状态, 用到它的测试不能并行.
+    static LOG_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_hash_chain() -> Resultat<()> {
+        let _guard = LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let path = std::env::temp_dir().join(format!("svarog-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        install_audit_log(Some(path.clone())).catch_()?;
+        let mut event = AuditEvent::new(Operation::Sign, "elgamal_secp256k1", "ses");
+        event.set_tasks(&[(vec![1u8; 32], "m/0".to_owned())]);
+        event.finish(Ok(())).catch_()?;
+        let failed = || -> Resultat<()> {
+            assert_throw!(false, "boom");
+            Ok(())
+        };
+        assert_throw!(
+            AuditEvent::new(Operation::Keygen, "schnorr_ed25519", "ses2")
+                .finish(failed())
+                .is_err()
+        );
+
+        // 重新安装时接在已有记录之后.
+        install_audit_log(Some(path.clone())).catch_()?;
+        AuditEvent::new(Operation::Reshare, "schnorr_ed25519", "ses3")
+            .finish(Ok(()))
+            .catch_()?;
+        install_audit_log(None).catch_()?;
+        let records = verify_chain(&path).catch_()?;
+        assert_throw!(records.len() == 3);
+        assert_throw!(matches!(
+            records[1].content.event.outcome,
+            Some(Outcome::Failure { .. })
+        ));
+
+        let text = std::fs::read_to_string(&path).catch_()?;
+        std::fs::write(&path, text.replacen("ses2", "ses4", 1)).catch_()?;
+        assert_throw!(verify_chain(&path).is_err());
+        std::fs::remove_file(&path).catch_()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_unlogged_keystore() -> Resultat<()> {
+        use curve25519_dalek::{EdwardsPoint, Scalar};
+        use svarog_algo::schnorr_ed25519::KeystoreSchnorr;
+
+        use crate::structs::Keystore;
+
+        let _guard = LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("svarog-audit-gone-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).catch_()?;
+        install_audit_log(Some(dir.join("audit.jsonl"))).catch_()?;
+        // 日志所在的目录消失, 写入必然失败.
+        std::fs::remove_dir_all(&dir).catch_()?;
+        take_unlogged();
+
+        let xi = Scalar::from(7u64);
+        let keystore = Keystore {
+            share: KeystoreSchnorr {
+                i: 1,
+                xi,
+                vss_scheme: BTreeMap::from([(1, vec![EdwardsPoint::mul_base(&xi)])]),
+                chain_code: [0u8; 32],
+            },
+            threshold: 1,
+            players: BTreeMap::from([("Alice".to_owned(), 1)]),
+            allowed_paths: Vec::new(),
+            epoch: 0,
+            refreshed_at: 0,
+            pending: Vec::new(),
+        };
+        let mut event = AuditEvent::new(Operation::Refresh, "schnorr_ed25519", "ses");
+        event.set_new_keystore(&keystore);
+        let returned = event.finish_keystore(Ok(keystore.clone())).catch_()?;
+        assert_throw!(share_fingerprint(&returned) == share_fingerprint(&keystore));
+
+        let unlogged = take_unlogged();
+        assert_throw!(unlogged.len() == 1);
+        assert_throw!(unlogged[0].event.session_id == "ses");
+        assert_throw!(unlogged[0].event.outcome == Some(Outcome::Success));
+        assert_throw!(unlogged[0].error.contains("IOError"));
+
+        // 其余操作仍因写入失败而报错.
+        assert_throw!(AuditEvent::new(Operation::Sign, "schnorr_ed25519", "ses2")
+            .finish(Ok(()))
+            .is_err());
+        install_audit_log(None).catch_()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_digest() -> Resultat<()> {
+        let names: Vec<String> = (0..32).map(|k| format!("player{}", k)).collect();
+        let cfg = |names: &mut dyn Iterator<Item = &String>| SessionConfig {
+            sesman_url: "http://127.0.0.1:2000".to_owned(),
+            threshold: 2,
+            players: names.map(|name| (name.clone(), true)).collect(),
+            ..Default::default()
+        };
+        let forward = cfg(&mut names.iter());
+        let backward = cfg(&mut names.iter().rev());
+        assert_throw!(config_digest(&forward)? == config_digest(&backward)?);
+
+        // 每个字段都计入摘要.
+        let mut other = forward.clone();
+        other.sesman_url = "http://127.0.0.1:2001".to_owned();
+        assert_throw!(config_digest(&forward)? != config_digest(&other)?);
+
+        let canonical = SessionConfig::decode(canonical_config(&backward).as_slice()).catch_()?;
+        assert_throw!(canonical == forward);
+        Ok(())
+    }
+}
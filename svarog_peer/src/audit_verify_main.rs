@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use clap::{Arg, ArgAction, Command};
+use erreur::*;
+use svarog_peer::audit::{genesis_hash, verify_chain};
+
+fn main() -> Resultat<()> {
+    // Parse args
+    let matches = Command::new("svarog_audit_verify")
+        .about("Check the hash chain of a svarog_peer audit log offline")
+        .arg(Arg::new("log").required(true).action(ArgAction::Set))
+        .arg(
+            Arg::new("head")
+                .long("head")
+                .help("expected hash of the last record, detects truncation")
+                .action(ArgAction::Set),
+        )
+        .get_matches();
+    let path: PathBuf = matches.get_one::<String>("log").ifnone_()?.into();
+    let head: Option<&String> = matches.get_one::<String>("head");
+
+    let records = verify_chain(&path).catch("BrokenAuditLog", format!("{}", path.display()))?;
+    let last_hash = records
+        .last()
+        .map(|r| r.hash.clone())
+        .unwrap_or_else(genesis_hash);
+    if let Some(head) = head {
+        assert_throw!(
+            head == &last_hash,
+            format!("last record hash is {}, expected {}", &last_hash, head)
+        );
+    }
+    println!("{} records, chain intact", records.len());
+    println!("head {}", &last_hash);
+    Ok(())
+}
@@ -0,0 +1,219 @@
+//! BIP32公钥派生. MPC各方都不持有完整私钥, 因此只支持非硬化派生.
+use erreur::*;
+use hmac::{Hmac, Mac};
+use k256::elliptic_curve::PrimeField;
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256, Sha512};
+use svarog_algo::{
+    elgamal_secp256k1::{KeystoreElgamal, ProjectivePoint, Scalar},
+    schnorr_ed25519::KeystoreSchnorr,
+};
+use zeroize::Zeroizing;
+
+use crate::keystore::MpcKeystore;
+
+pub const HARDENED: u32 = 0x8000_0000;
+pub const XPUB_MAINNET: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+pub const XPUB_TESTNET: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+
+/// 解析形如 `m/1/2/3` 的路径. 空串和 `m` 都表示根节点.
+pub fn parse_path(path: &str) -> Resultat<Vec<u32>> {
+    let path = path.trim();
+    let mut segs = path.split('/');
+    let head = segs.next().unwrap_or_default();
+    assert_throw!(
+        head.is_empty() || head == "m",
+        format!("bip32 path {} should start with m", path)
+    );
+    let mut res = Vec::new();
+    for seg in segs {
+        assert_throw!(
+            !seg.ends_with('\'') && !seg.ends_with('h') && !seg.ends_with('H'),
+            format!("hardened segment {} is not supported by MPC keys", seg)
+        );
+        let idx: u32 = seg.parse().catch(
+            "InvalidBip32Path",
+            format!("bad segment {} in {}", seg, path),
+        )?;
+        assert_throw!(
+            idx < HARDENED,
+            format!("segment {} is in the hardened range", seg)
+        );
+        res.push(idx);
+    }
+    Ok(res)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExtendedPk {
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+    pub child_number: u32,
+    pub chain_code: [u8; 32],
+    pub pk: ProjectivePoint,
+}
+
+impl ExtendedPk {
+    pub fn root(pk: ProjectivePoint, chain_code: [u8; 32]) -> Self {
+        Self {
+            depth: 0,
+            parent_fingerprint: [0u8; 4],
+            child_number: 0,
+            chain_code,
+            pk,
+        }
+    }
+
+    pub fn from_keystore(keystore: &KeystoreElgamal) -> Resultat<Self> {
+        let pk = ProjectivePoint::from33bytes(&keystore.group_pk()).catch_()?;
+        Ok(Self::root(pk, keystore.chain_code()))
+    }
+
+    pub fn derive_child(&self, index: u32) -> Resultat<Self> {
+        assert_throw!(
+            index < HARDENED,
+            "hardened derivation needs the private key"
+        );
+        let mut mac = Hmac::<Sha512>::new_from_slice(&self.chain_code).catch_()?;
+        mac.update(&self.pk.to33bytes());
+        mac.update(&index.to_be_bytes());
+        let i = mac.finalize().into_bytes();
+
+        let il: [u8; 32] = i[..32].try_into().catch_()?;
+        let il: Option<Scalar> = Scalar::from_repr(il.into()).into();
+        let il = il.ifnone("InvalidChild", format!("IL >= n at index {}", index))?;
+        let pk = ProjectivePoint::GENERATOR * il + self.pk;
+        assert_throw!(
+            pk != ProjectivePoint::IDENTITY,
+            format!("child key at index {} is the point at infinity", index)
+        );
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&i[32..]);
+
+        Ok(Self {
+            depth: self
+                .depth
+                .checked_add(1)
+                .ifnone("", "bip32 path too deep")?,
+            parent_fingerprint: fingerprint(&self.pk),
+            child_number: index,
+            chain_code,
+            pk,
+        })
+    }
+
+    pub fn derive_path(&self, path: &[u32]) -> Resultat<Self> {
+        let mut node = *self;
+        for &index in path {
+            node = node.derive_child(index).catch_()?;
+        }
+        Ok(node)
+    }
+
+    /// 标准BIP32序列化, 78字节经base58check编码.
+    pub fn to_xpub(&self, version: [u8; 4]) -> String {
+        let mut buf = Vec::with_capacity(78);
+        buf.extend_from_slice(&version);
+        buf.push(self.depth);
+        buf.extend_from_slice(&self.parent_fingerprint);
+        buf.extend_from_slice(&self.child_number.to_be_bytes());
+        buf.extend_from_slice(&self.chain_code);
+        buf.extend_from_slice(&self.pk.to33bytes());
+        base58check(&buf)
+    }
+}
+
+pub fn derive_pk(keystore: &KeystoreElgamal, bip32_path: &str) -> Resultat<ProjectivePoint> {
+    let path = parse_path(bip32_path).catch_()?;
+    let node = ExtendedPk::from_keystore(keystore).catch_()?;
+    let node = node.derive_path(&path).catch_()?;
+    Ok(node.pk)
+}
+
+/// ed25519的非硬化派生, 与secp256k1同构:
+/// `I = HMAC-SHA512(c, A || index)`, 子公钥为 `A + (I[..32] mod l) * B`, 子链码为 `I[32..]`.
+/// 必须与svarog_algo签名时的派生一致, 集成测试 `test_keygen_sign` 用签名返回的公钥逐路径核对.
+pub fn derive_pk_ed25519(
+    keystore: &KeystoreSchnorr,
+    bip32_path: &str,
+) -> Resultat<curve25519_dalek::EdwardsPoint> {
+    use curve25519_dalek::{edwards::CompressedEdwardsY, EdwardsPoint};
+
+    let path = parse_path(bip32_path).catch_()?;
+    let pk = CompressedEdwardsY::from_slice(&keystore.group_pk()).catch_()?;
+    let mut pk = pk
+        .decompress()
+        .ifnone("InvalidKeystore", "group pk is not a valid ed25519 point")?;
+    let mut chain_code = keystore.chain_code();
+    for index in path {
+        let mut mac = Hmac::<Sha512>::new_from_slice(&chain_code).catch_()?;
+        mac.update(pk.compress().as_bytes());
+        mac.update(&index.to_be_bytes());
+        let i = mac.finalize().into_bytes();
+        let il = curve25519_dalek::Scalar::from_bytes_mod_order(i[..32].try_into().catch_()?);
+        pk += EdwardsPoint::mul_base(&il);
+        chain_code.copy_from_slice(&i[32..]);
+    }
+    Ok(pk)
+}
+
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    let sha = Sha256::digest(data);
+    Ripemd160::digest(sha).into()
+}
+
+pub fn fingerprint(pk: &ProjectivePoint) -> [u8; 4] {
+    let h = hash160(&pk.to33bytes());
+    [h[0], h[1], h[2], h[3]]
+}
+
+pub fn base58check(payload: &[u8]) -> String {
+    let checksum = Sha256::digest(Sha256::digest(payload));
+    // WIF和xprv的载荷含私钥.
+    let mut buf = Zeroizing::new(payload.to_vec());
+    buf.extend_from_slice(&checksum[..4]);
+    bs58::encode(&buf[..]).into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BIP32 test vector 1, chain m/0H/1.
+    #[test]
+    fn test_derive_non_hardened() -> Resultat<()> {
+        let pk = hex::decode("035a784662a4a20a65bf6aab9ae98a6c068a81c52e4b032c0fb5400c706cfccc56")
+            .catch_()?;
+        let chain_code =
+            hex::decode("47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141")
+                .catch_()?;
+        let mut node = ExtendedPk::root(
+            ProjectivePoint::from33bytes(&pk).catch_()?,
+            chain_code.try_into().ok().ifnone_()?,
+        );
+        node.depth = 1;
+        node.parent_fingerprint = [0x34, 0x42, 0x19, 0x3e];
+        node.child_number = HARDENED;
+
+        let child = node.derive_child(1).catch_()?;
+        assert_throw!(
+            hex::encode(child.pk.to33bytes())
+                == "03501e454bf00751f24b1b489aa925215d66af2234e3891c3b21a52bedb3cd711c"
+        );
+        assert_throw!(
+            child.to_xpub(XPUB_MAINNET)
+                == "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_path() -> Resultat<()> {
+        assert_throw!(parse_path("m").catch_()?.is_empty());
+        assert_throw!(parse_path("m/1/2/3").catch_()? == vec![1, 2, 3]);
+        assert_throw!(parse_path("m/44'/0").is_err());
+        assert_throw!(parse_path("m/2147483648").is_err());
+        assert_throw!(parse_path("1/2").is_err());
+        Ok(())
+    }
+}
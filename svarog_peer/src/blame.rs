@@ -0,0 +1,165 @@
+//! 可归责的中止. 会话失败时找出造成失败的参与方, 以结构化的错误返回, 并在sesman上留下归责记录.
+//!
+//! 目前能够归责的失败:
+//! 1. 等待某一方的消息超时, 见 `SvarogChannel::missing_parties`.
+//! 2. 向缺席成员投递分片时, 某一方的分量无法解密, 或者其投递与多数helper的不一致.
+//!    后者由缺席成员取回分片时发现, 只在本地返回.
+//!
+//! 只有以上两类失败会被归责. svarog_algo内部的校验失败 (零知识证明, VSS承诺, 签名分量等)
+//! 不带参与方信息, 按原样返回, 不是 `IdentifiableAbort`; 把这些错误对应到参与方需要
+//! svarog_algo在错误中给出序号, 留作后续的需求, 不在本模块的范围内.
+//! 可归责的错误标题为 `IdentifiableAbort`, 用 `abort_of` 从错误中取出 `Abort`.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use erreur::*;
+use serde::{Deserialize, Serialize};
+use svarog_sesman::SvarogChannel;
+
+use crate::ses_members;
+
+pub const ABORT_TITLE: &str = "IdentifiableAbort";
+const ABORT_MARKER: &str = "abort=";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Culprit {
+    /// 会话中的成员序号.
+    pub index: usize,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Abort {
+    pub session_id: String,
+    pub culprits: Vec<Culprit>,
+    pub reason: String,
+}
+
+impl Abort {
+    /// `parties` 是序号到名称的映射; 不在其中的序号名称为空.
+    pub(crate) fn new(
+        session_id: &str,
+        indices: &BTreeSet<usize>,
+        parties: &BTreeMap<usize, Vec<String>>,
+        reason: &str,
+    ) -> Self {
+        let mut culprits = Vec::new();
+        for &index in indices.iter() {
+            match parties.get(&index) {
+                Some(names) => culprits.extend(names.iter().map(|name| Culprit {
+                    index,
+                    name: name.clone(),
+                })),
+                None => culprits.push(Culprit {
+                    index,
+                    name: String::new(),
+                }),
+            }
+        }
+        Self {
+            session_id: session_id.to_owned(),
+            culprits,
+            reason: reason.to_owned(),
+        }
+    }
+
+    /// 作为错误返回.
+    pub(crate) fn throw<T>(&self) -> Resultat<T> {
+        let json = serde_json::to_string(self).catch_()?;
+        None.ifnone(ABORT_TITLE, format!("{}{}", ABORT_MARKER, json))
+    }
+}
+
+/// 从错误 (包括被 `catch` 包装过的) 中取出 `Abort`.
+pub fn abort_of(err: &Erreur) -> Option<Abort> {
+    let text = format!("{}", err);
+    let pos = text.find(ABORT_MARKER)?;
+    serde_json::Deserializer::from_str(&text[pos + ABORT_MARKER.len()..])
+        .into_iter::<Abort>()
+        .next()?
+        .ok()
+}
+
+/// 出席成员的序号到名称的映射. Reshare时合并provider和consumer两份名单,
+/// 同一序号可能对应两个名称.
+pub(crate) fn session_parties(names: &[&HashMap<String, bool>]) -> BTreeMap<usize, Vec<String>> {
+    let mut parties: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for names in names.iter() {
+        for (name, j) in ses_members(names) {
+            let entry = parties.entry(j).or_default();
+            if names.get(&name) == Some(&true) && !entry.contains(&name) {
+                entry.push(name);
+            }
+        }
+    }
+    parties.retain(|_, names| !names.is_empty());
+    parties
+}
+
+/// 会话失败时归责: 错误本身是 `Abort`, 或者有参与方的消息未到. 归责结果报告给sesman,
+/// 报告失败不影响返回的错误.
+pub(crate) async fn attribute<T>(
+    res: Resultat<T>,
+    chan: &mut SvarogChannel,
+    parties: &BTreeMap<usize, Vec<String>>,
+    reporter: &str,
+) -> Resultat<T> {
+    let err = match res {
+        Ok(v) => return Ok(v),
+        Err(err) => err,
+    };
+    let (abort, res) = match abort_of(&err) {
+        Some(abort) => (abort, Err(err)),
+        None => {
+            let missing = chan.missing_parties();
+            if missing.is_empty() {
+                return Err(err);
+            }
+            let reason = format!("timeout waiting for messages: {}", err);
+            let abort = Abort::new(chan.sid(), &missing, parties, &reason);
+            let res = abort.throw();
+            (abort, res)
+        }
+    };
+    for culprit in abort.culprits.iter() {
+        let _ = chan
+            .report_blame(reporter, culprit.index, &culprit.name, &abort.reason)
+            .await;
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abort_roundtrip() -> Resultat<()> {
+        let players = HashMap::from([
+            ("Alice".to_owned(), true),
+            ("Bob".to_owned(), false),
+            ("Charlie".to_owned(), true),
+        ]);
+        let consumers = HashMap::from([("Alice".to_owned(), true), ("David".to_owned(), true)]);
+        let parties = session_parties(&[&players, &consumers]);
+        assert_throw!(parties.get(&1) == Some(&vec!["Alice".to_owned()]));
+        assert_throw!(parties.get(&2) == Some(&vec!["David".to_owned()]));
+        assert_throw!(parties.get(&3) == Some(&vec!["Charlie".to_owned()]));
+
+        let abort = Abort::new("ses", &[2, 4].into(), &parties, "timeout");
+        assert_throw!(abort.culprits.len() == 2);
+        assert_throw!(abort.culprits[0].name == "David");
+        let wrapped = || -> Resultat<()> {
+            abort.throw::<()>().catch("SignFailed", "")?;
+            Ok(())
+        };
+        let err = wrapped().err().ifnone_()?;
+        assert_throw!(abort_of(&err) == Some(abort.clone()));
+        let plain = || -> Resultat<()> {
+            assert_throw!(false, "boom");
+            Ok(())
+        };
+        let err = plain().err().ifnone_()?;
+        assert_throw!(abort_of(&err).is_none());
+        Ok(())
+    }
+}
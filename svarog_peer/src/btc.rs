@@ -1,36 +1,31 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 use erreur::*;
 use svarog_algo::elgamal_secp256k1::{
     keygen, keygen_mnem_consumer, keygen_mnem_provider, reshare_consumer, reshare_provider,
-    sign_batch, KeystoreElgamal,
+    sign_batch, KeystoreElgamal, ProjectivePoint,
 };
 use svarog_sesman::SvarogChannel;
 
 use crate::{
-    ses_arch,
-    structs::{Mnemonics, SignTask, Signature},
+    flows::{
+        audited_export_root, audited_keygen, audited_keygen_mnem, audited_refresh, audited_reshare,
+        decode_vss, load_opt, pickup_keystore, save_opt,
+    },
+    holders::audited_sign,
+    paillier::generate_paillier_key,
+    recovery::{elgamal_key, RecoveredKey},
+    structs::{Keystore, Mnemonics, SignTask, Signature},
+    vault::Vault,
+    verify::{normalize_s, verify_batch},
 };
 
 pub async fn biz_keygen(
     sesman_url: String,
     session_id: String,
     member_name: String,
-) -> Resultat<KeystoreElgamal> {
-    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
-    let https = sesman_url.starts_with("https://");
-
-    let (chan, cfg) = SvarogChannel::use_session(&session_id, &sesman_url, https)
-        .await
-        .catch_()?;
-    let t = cfg.threshold as usize;
-    let (i, players) = ses_arch(&member_name, &cfg.players);
-    assert_throw!(
-        players.len() == cfg.players.len(),
-        "all keygen members should attend"
-    );
-    let keystore = impl_keygen(chan, i, t, players).await.catch_()?;
-    Ok(keystore)
+) -> Resultat<Keystore<KeystoreElgamal>> {
+    audited_keygen(sesman_url, session_id, member_name, impl_keygen).await
 }
 
 pub async fn biz_keygen_mnem(
@@ -38,71 +33,189 @@ pub async fn biz_keygen_mnem(
     session_id: String,
     member_name: String,
     mnemonics: Option<Mnemonics>,
-) -> Resultat<Option<KeystoreElgamal>> {
-    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
-    let https = sesman_url.starts_with("https://");
+) -> Resultat<Option<Keystore<KeystoreElgamal>>> {
+    audited_keygen_mnem(
+        sesman_url,
+        session_id,
+        member_name,
+        mnemonics,
+        impl_keygen_mnem,
+    )
+    .await
+}
+
+pub async fn biz_sign(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<KeystoreElgamal>,
+    tasks: Vec<SignTask>,
+) -> Resultat<Vec<Signature>> {
+    audited_sign(sesman_url, session_id, keystore, tasks, impl_sign).await
+}
 
-    let (chan, cfg) = SvarogChannel::use_session(&session_id, &sesman_url, https)
+pub async fn biz_reshare(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    keystore: Option<Keystore<KeystoreElgamal>>,
+) -> Resultat<Option<Keystore<KeystoreElgamal>>> {
+    audited_reshare(sesman_url, session_id, member_name, keystore, impl_reshare).await
+}
+
+/// 成员和门限不变地刷新分片. 会话配置用 `refresh_config` 构造, 全体成员都要出席.
+pub async fn biz_refresh(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    keystore: Keystore<KeystoreElgamal>,
+) -> Resultat<Keystore<KeystoreElgamal>> {
+    audited_refresh(sesman_url, session_id, member_name, keystore, impl_reshare).await
+}
+
+/// 取回Reshare时因缺席而错过的分片. `mailbox_sk` 与会话配置中本方的邮箱公钥对应.
+/// 本方的Paillier密钥在本地生成, 分片不进入任何网络协议.
+///
+/// 限制: 其他成员的 `paillier_n_dict` 中没有本方的新模数, 要到一次全体成员出席的Refresh
+/// 之后才有. 在此之前本方不能参与secp256k1签名 (`pending` 中的成员不会报到), 取回后应尽快安排Refresh.
+pub async fn biz_reshare_pickup(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    mailbox_sk: [u8; 32],
+) -> Resultat<Keystore<KeystoreElgamal>> {
+    pickup_keystore(
+        sesman_url,
+        session_id,
+        member_name,
+        mailbox_sk,
+        |template, xi| {
+            let vss_scheme =
+                decode_vss(template, |com| ProjectivePoint::from33bytes(com).ok()).catch_()?;
+            let mut paillier_n_dict: BTreeMap<usize, _> =
+                serde_pickle::from_slice(&template.extra, Default::default()).catch_()?;
+            let (paillier_key, n) = generate_paillier_key().catch_()?;
+            paillier_n_dict.insert(template.i, n);
+            Ok(KeystoreElgamal {
+                i: template.i,
+                ui: xi,
+                xi,
+                vss_scheme,
+                paillier_key,
+                paillier_n_dict,
+                chain_code: template.chain_code,
+            })
+        },
+    )
+    .await
+}
+
+/// 根私钥导出仪式, 见 `root_export`. 只有 `SessionConfig.exporter` 得到结果, 其余持有者得到 `None`. `testnet` 决定WIF和xprv的编码.
+pub async fn biz_export_root(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<KeystoreElgamal>,
+    testnet: bool,
+) -> Resultat<Option<RecoveredKey>> {
+    audited_export_root(
+        sesman_url,
+        session_id,
+        keystore,
+        |keystore, secret, holders| elgamal_key(keystore, secret, holders, testnet),
+    )
+    .await
+}
+
+pub async fn biz_keygen_vault(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    vault: Vault,
+) -> Resultat<String> {
+    let keystore = biz_keygen(sesman_url, session_id, member_name.clone())
         .await
         .catch_()?;
-    let t = cfg.threshold as usize;
-    let (i, players) = ses_arch(&member_name, &cfg.players);
-    assert_throw!(
-        players.len() == cfg.players.len(),
-        "all keygen members should attend"
-    );
-    let keystore = impl_keygen_mnem(chan, i, t, players, mnemonics)
+    let key_id = vault.save(&keystore, &member_name).catch_()?;
+    Ok(key_id)
+}
+
+pub async fn biz_keygen_mnem_vault(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    mnemonics: Option<Mnemonics>,
+    vault: Vault,
+) -> Resultat<Option<String>> {
+    let keystore = biz_keygen_mnem(sesman_url, session_id, member_name.clone(), mnemonics)
         .await
         .catch_()?;
-    Ok(keystore)
+    save_opt(&vault, keystore, &member_name)
 }
 
-pub async fn biz_sign(
+pub async fn biz_sign_vault(
     sesman_url: String,
     session_id: String,
-    keystore: KeystoreElgamal,
+    key_id: String,
+    vault: Vault,
     tasks: Vec<SignTask>,
 ) -> Resultat<Vec<Signature>> {
-    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
-    let https = sesman_url.starts_with("https://");
+    let keystore: Keystore<KeystoreElgamal> = vault.load(&key_id).catch_()?;
+    biz_sign(sesman_url, session_id, keystore, tasks).await
+}
 
-    let (chan, cfg) = SvarogChannel::use_session(&session_id, &sesman_url, https)
+/// `key_id`为空表示本方不是provider. 新的Keystore与旧的公钥相同, 因此会覆盖保管库中的旧Keystore.
+pub async fn biz_reshare_vault(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    key_id: Option<String>,
+    vault: Vault,
+) -> Resultat<Option<String>> {
+    let keystore = load_opt(&vault, key_id.as_deref()).catch_()?;
+    let keystore = biz_reshare(sesman_url, session_id, member_name.clone(), keystore)
         .await
         .catch_()?;
-    let (_, signers) = ses_arch("", &cfg.players);
-    let i = keystore.i as usize;
-    assert_throw!(signers.contains(&i), "signer not in the session");
-    let sigs = impl_sign(chan, keystore, signers, tasks).await.catch_()?;
-    Ok(sigs)
+    save_opt(&vault, keystore, &member_name)
 }
 
-pub async fn biz_reshare(
+/// 刷新后的Keystore覆盖保管库中的旧Keystore.
+pub async fn biz_refresh_vault(
     sesman_url: String,
     session_id: String,
     member_name: String,
-    keystore: Option<KeystoreElgamal>,
-) -> Resultat<Option<KeystoreElgamal>> {
-    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
-    let https = sesman_url.starts_with("https://");
-
-    let (chan, cfg) = SvarogChannel::use_session(&session_id, &sesman_url, https)
+    key_id: String,
+    vault: Vault,
+) -> Resultat<String> {
+    let keystore: Keystore<KeystoreElgamal> = vault.load(&key_id).catch_()?;
+    let keystore = biz_refresh(sesman_url, session_id, member_name.clone(), keystore)
         .await
         .catch_()?;
-    let t = cfg.threshold as usize;
-    let (_, providers) = ses_arch("", &cfg.players);
-    if let Some(keystore) = &keystore {
-        let i0 = keystore.i as usize;
-        assert_throw!(providers.contains(&i0), "provider not in the session");
-    }
-    let (i, consumers) = ses_arch(&member_name, &cfg.players_reshared);
-    assert_throw!(
-        consumers.len() == cfg.players_reshared.len(),
-        "all keygen members should attend"
-    );
-    let keystore = impl_reshare(chan, keystore, i, t, providers, consumers)
+    let key_id = vault.save(&keystore, &member_name).catch_()?;
+    Ok(key_id)
+}
+
+pub async fn biz_reshare_pickup_vault(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    mailbox_sk: [u8; 32],
+    vault: Vault,
+) -> Resultat<String> {
+    let keystore = biz_reshare_pickup(sesman_url, session_id, member_name.clone(), mailbox_sk)
         .await
         .catch_()?;
-    Ok(keystore)
+    let key_id = vault.save(&keystore, &member_name).catch_()?;
+    Ok(key_id)
+}
+
+pub async fn biz_export_root_vault(
+    sesman_url: String,
+    session_id: String,
+    key_id: String,
+    vault: Vault,
+    testnet: bool,
+) -> Resultat<Option<RecoveredKey>> {
+    let keystore: Keystore<KeystoreElgamal> = vault.load(&key_id).catch_()?;
+    biz_export_root(sesman_url, session_id, keystore, testnet).await
 }
 
 async fn impl_keygen(
@@ -122,9 +235,13 @@ async fn impl_keygen_mnem(
     players: BTreeSet<usize>,
     mnem: Option<Mnemonics>,
 ) -> Resultat<Option<KeystoreElgamal>> {
-    let provider_thread = if let Some(mnem) = mnem {
-        let future: _ =
-            keygen_mnem_provider(chan.clone(), players.clone(), mnem.phrases, mnem.password);
+    let provider_thread = if let Some(mut mnem) = mnem {
+        let future: _ = keygen_mnem_provider(
+            chan.clone(),
+            players.clone(),
+            std::mem::take(&mut mnem.phrases),
+            std::mem::take(&mut mnem.password),
+        );
         let handle: _ = tokio::spawn(future);
         Some(handle)
     } else {
@@ -152,30 +269,30 @@ async fn impl_sign(
     chan: SvarogChannel,
     keystore: KeystoreElgamal,
     signers: BTreeSet<usize>,
-    tasks: Vec<SignTask>,
+    tasks: Vec<(Vec<u8>, String)>,
 ) -> Resultat<Vec<Signature>> {
     assert_throw!(tasks.len() >= 1);
     assert_throw!(signers.len() >= 1);
     assert_throw!(signers.contains(&keystore.i));
     let res = {
-        let tasks = tasks
-            .into_iter()
-            .map(|task| (task.message, task.bip32_path))
-            .collect();
-        let sigs = sign_batch(chan, signers, keystore, tasks).await.catch_()?;
+        let sigs = sign_batch(chan, signers, keystore.clone(), tasks.clone())
+            .await
+            .catch_()?;
         let mut res = Vec::new();
         for sig in sigs.into_iter() {
             let (r, v) = sig.eval_rv();
-            let sig = Signature {
+            let mut sig = Signature {
                 r,
                 s: *sig.s.to_bytes().as_ref(),
                 v,
                 pk: sig.pk.to33bytes().to_vec(),
             };
+            normalize_s(&mut sig).catch_()?;
             res.push(sig);
         }
         res
     };
+    verify_batch(&keystore, &tasks, &res).catch_()?;
     Ok(res)
 }
 
@@ -0,0 +1,175 @@
+//! 从MPC Keystore非交互地派生比特币地址和账户xpub, 无需会话.
+use erreur::*;
+use serde::{Deserialize, Serialize};
+use svarog_algo::elgamal_secp256k1::KeystoreElgamal;
+
+use crate::bip32::{base58check, hash160, parse_path, ExtendedPk, XPUB_MAINNET, XPUB_TESTNET};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressKind {
+    P2pkh,
+    P2wpkh,
+    P2shP2wpkh,
+}
+
+impl Network {
+    fn p2pkh_version(self) -> u8 {
+        match self {
+            Network::Mainnet => 0x00,
+            Network::Testnet | Network::Regtest => 0x6f,
+        }
+    }
+
+    fn p2sh_version(self) -> u8 {
+        match self {
+            Network::Mainnet => 0x05,
+            Network::Testnet | Network::Regtest => 0xc4,
+        }
+    }
+
+    fn bech32_hrp(self) -> &'static str {
+        match self {
+            Network::Mainnet => "bc",
+            Network::Testnet => "tb",
+            Network::Regtest => "bcrt",
+        }
+    }
+
+    fn xpub_version(self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => XPUB_MAINNET,
+            Network::Testnet | Network::Regtest => XPUB_TESTNET,
+        }
+    }
+}
+
+pub fn derive_address(
+    keystore: &KeystoreElgamal,
+    bip32_path: &str,
+    network: Network,
+    kind: AddressKind,
+) -> Resultat<String> {
+    let path = parse_path(bip32_path).catch_()?;
+    let node = ExtendedPk::from_keystore(keystore).catch_()?;
+    let node = node.derive_path(&path).catch_()?;
+    let pk = node.pk.to33bytes();
+    let addr = match kind {
+        AddressKind::P2pkh => p2pkh_address(&pk, network),
+        AddressKind::P2wpkh => p2wpkh_address(&pk, network),
+        AddressKind::P2shP2wpkh => p2sh_p2wpkh_address(&pk, network),
+    };
+    Ok(addr)
+}
+
+/// 账户级xpub. 由于只能非硬化派生, `account_path` 不能含硬化段, 例如 `m/0`.
+pub fn account_xpub(
+    keystore: &KeystoreElgamal,
+    account_path: &str,
+    network: Network,
+) -> Resultat<String> {
+    let path = parse_path(account_path).catch_()?;
+    let node = ExtendedPk::from_keystore(keystore).catch_()?;
+    let node = node.derive_path(&path).catch_()?;
+    Ok(node.to_xpub(network.xpub_version()))
+}
+
+pub fn p2pkh_address(pk: &[u8; 33], network: Network) -> String {
+    let mut payload = vec![network.p2pkh_version()];
+    payload.extend_from_slice(&hash160(pk));
+    base58check(&payload)
+}
+
+pub fn p2wpkh_address(pk: &[u8; 33], network: Network) -> String {
+    segwit_v0_address(network.bech32_hrp(), &hash160(pk))
+}
+
+pub fn p2sh_p2wpkh_address(pk: &[u8; 33], network: Network) -> String {
+    let mut redeem_script = vec![0x00, 0x14];
+    redeem_script.extend_from_slice(&hash160(pk));
+    let mut payload = vec![network.p2sh_version()];
+    payload.extend_from_slice(&hash160(&redeem_script));
+    base58check(&payload)
+}
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GEN: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut chk: u32 = 1;
+    for &v in values {
+        let b = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ (v as u32);
+        for (k, g) in GEN.iter().enumerate() {
+            if (b >> k) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+/// BIP173的bech32编码, 仅用于见证版本0.
+fn segwit_v0_address(hrp: &str, program: &[u8]) -> String {
+    let mut data = vec![0u8]; // witness version
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for &b in program {
+        acc = (acc << 8) | b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            data.push(((acc >> bits) & 31) as u8);
+        }
+    }
+    if bits > 0 {
+        data.push(((acc << (5 - bits)) & 31) as u8);
+    }
+
+    let mut values: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|c| c & 31));
+    values.extend_from_slice(&data);
+    values.extend_from_slice(&[0u8; 6]);
+    let polymod = bech32_polymod(&values) ^ 1;
+
+    let mut addr = format!("{}1", hrp);
+    for &d in data.iter() {
+        addr.push(BECH32_CHARSET[d as usize] as char);
+    }
+    for k in 0..6 {
+        let d = (polymod >> (5 * (5 - k))) & 31;
+        addr.push(BECH32_CHARSET[d as usize] as char);
+    }
+    addr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_addresses_of_generator() -> Resultat<()> {
+        // 私钥为1的公钥.
+        let pk = hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .catch_()?;
+        let pk: [u8; 33] = pk.try_into().ok().ifnone_()?;
+        assert_throw!(p2pkh_address(&pk, Network::Mainnet) == "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH");
+        assert_throw!(
+            p2wpkh_address(&pk, Network::Mainnet) == "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+        assert_throw!(
+            p2wpkh_address(&pk, Network::Testnet) == "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"
+        );
+        assert_throw!(
+            p2sh_p2wpkh_address(&pk, Network::Mainnet) == "3JvL6Ymt8MVWiCNHC7oWU6nLeHNJKLZGLN"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+//! EIP-712结构化数据的哈希, 与`eth_signTypedData_v4`一致.
+use std::collections::{BTreeMap, BTreeSet};
+
+use erreur::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::eth::keccak256;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TypedField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    pub types: BTreeMap<String, Vec<TypedField>>,
+    pub primary_type: String,
+    pub domain: Value,
+    pub message: Value,
+}
+
+const DOMAIN_TYPE: &str = "EIP712Domain";
+
+impl TypedData {
+    pub fn from_json(json: &str) -> Resultat<Self> {
+        let data: Self = serde_json::from_str(json).catch("InvalidTypedData", "")?;
+        assert_throw!(
+            data.types.contains_key(DOMAIN_TYPE),
+            "typed data has no EIP712Domain type"
+        );
+        assert_throw!(
+            data.types.contains_key(&data.primary_type),
+            format!("primary type {} is not defined", &data.primary_type)
+        );
+        Ok(data)
+    }
+
+    pub fn domain_separator(&self) -> Resultat<[u8; 32]> {
+        self.hash_struct(DOMAIN_TYPE, &self.domain)
+    }
+
+    /// `keccak256(0x19 0x01 || domainSeparator || hashStruct(message))`
+    pub fn signing_hash(&self) -> Resultat<[u8; 32]> {
+        let mut buf = vec![0x19, 0x01];
+        buf.extend(self.domain_separator().catch_()?);
+        if self.primary_type != DOMAIN_TYPE {
+            buf.extend(
+                self.hash_struct(&self.primary_type, &self.message)
+                    .catch_()?,
+            );
+        }
+        Ok(keccak256(&buf))
+    }
+
+    pub fn encode_type(&self, ty: &str) -> Resultat<String> {
+        let mut deps = BTreeSet::new();
+        self.collect_deps(ty, &mut deps).catch_()?;
+        deps.remove(ty);
+        let mut res = String::new();
+        for name in std::iter::once(ty).chain(deps.iter().map(|s| s.as_str())) {
+            let fields = &self.types[name];
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|f| format!("{} {}", &f.ty, &f.name))
+                .collect();
+            res.push_str(&format!("{}({})", name, fields.join(",")));
+        }
+        Ok(res)
+    }
+
+    fn collect_deps(&self, ty: &str, deps: &mut BTreeSet<String>) -> Resultat<()> {
+        if deps.contains(ty) {
+            return Ok(());
+        }
+        let fields = self
+            .types
+            .get(ty)
+            .ifnone("InvalidTypedData", format!("type {} is not defined", ty))?;
+        deps.insert(ty.to_owned());
+        for field in fields {
+            let base = base_type(&field.ty);
+            if self.types.contains_key(base) {
+                self.collect_deps(base, deps).catch_()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn hash_struct(&self, ty: &str, value: &Value) -> Resultat<[u8; 32]> {
+        let obj = value.as_object().ifnone(
+            "InvalidTypedData",
+            format!("value of {} is not an object", ty),
+        )?;
+        let mut buf = keccak256(self.encode_type(ty).catch_()?.as_bytes()).to_vec();
+        for field in self.types[ty].iter() {
+            let v = obj.get(&field.name).ifnone(
+                "InvalidTypedData",
+                format!("field {}.{} is missing", ty, &field.name),
+            )?;
+            buf.extend(
+                self.encode_field(&field.ty, v)
+                    .catch("InvalidTypedData", format!("field {}.{}", ty, &field.name))?,
+            );
+        }
+        Ok(keccak256(&buf))
+    }
+
+    fn encode_field(&self, ty: &str, value: &Value) -> Resultat<[u8; 32]> {
+        if let Some(item_ty) = array_item_type(ty) {
+            let items = value
+                .as_array()
+                .ifnone("", format!("value of {} is not an array", ty))?;
+            let mut buf = Vec::new();
+            for item in items {
+                buf.extend(self.encode_field(item_ty, item).catch_()?);
+            }
+            return Ok(keccak256(&buf));
+        }
+        if self.types.contains_key(ty) {
+            return self.hash_struct(ty, value);
+        }
+
+        let mut res = [0u8; 32];
+        match ty {
+            "string" => {
+                let s = value.as_str().ifnone("", "expect a string")?;
+                res = keccak256(s.as_bytes());
+            }
+            "bytes" => {
+                res = keccak256(&parse_hex(value).catch_()?);
+            }
+            "bool" => {
+                let b = value.as_bool().ifnone("", "expect a bool")?;
+                res[31] = b as u8;
+            }
+            "address" => {
+                let addr = parse_hex(value).catch_()?;
+                assert_throw!(addr.len() == 20, "address should have 20 bytes");
+                res[12..].copy_from_slice(&addr);
+            }
+            _ if ty.starts_with("bytes") => {
+                let n: usize = ty[5..].parse().catch("", format!("unknown type {}", ty))?;
+                let bytes = parse_hex(value).catch_()?;
+                assert_throw!(
+                    (1..=32).contains(&n) && bytes.len() == n,
+                    format!("{} expects {} bytes", ty, n)
+                );
+                res[..n].copy_from_slice(&bytes);
+            }
+            _ if ty.starts_with("uint") || ty.starts_with("int") => {
+                let signed = ty.starts_with("int");
+                let bits = &ty[if signed { 3 } else { 4 }..];
+                let bits: usize = if bits.is_empty() {
+                    256
+                } else {
+                    bits.parse().catch("", format!("unknown type {}", ty))?
+                };
+                assert_throw!(
+                    (8..=256).step_by(8).any(|b| b == bits),
+                    format!("unknown type {}", ty)
+                );
+                res = parse_int(value, bits, signed).catch_()?;
+            }
+            _ => assert_throw!(false, format!("unknown type {}", ty)),
+        }
+        Ok(res)
+    }
+}
+
+fn array_item_type(ty: &str) -> Option<&str> {
+    if ty.ends_with(']') {
+        ty.rfind('[').map(|pos| &ty[..pos])
+    } else {
+        None
+    }
+}
+
+fn base_type(ty: &str) -> &str {
+    ty.find('[').map_or(ty, |pos| &ty[..pos])
+}
+
+fn parse_hex(value: &Value) -> Resultat<Vec<u8>> {
+    let s = value.as_str().ifnone("", "expect a hex string")?;
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    hex::decode(s).catch_()
+}
+
+/// 解析十进制或`0x`前缀的十六进制整数, 编码为32字节大端补码.
+fn parse_int(value: &Value, bits: usize, signed: bool) -> Resultat<[u8; 32]> {
+    let s = match value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        _ => String::new(),
+    };
+    assert_throw!(!s.is_empty(), "expect an integer");
+    let (neg, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.as_str()),
+    };
+    assert_throw!(!neg || signed, "negative value for an unsigned type");
+
+    let mut res = [0u8; 32];
+    let (radix, digits) = match digits.strip_prefix("0x") {
+        Some(hex) => (16u32, hex),
+        None => (10u32, digits),
+    };
+    assert_throw!(!digits.is_empty(), "empty integer");
+    for c in digits.chars() {
+        let d = c
+            .to_digit(radix)
+            .ifnone("", format!("bad digit {} in integer", c))?;
+        let mut carry = d;
+        for byte in res.iter_mut().rev() {
+            let x = (*byte as u32) * radix + carry;
+            *byte = x as u8;
+            carry = x >> 8;
+        }
+        assert_throw!(carry == 0, "integer overflows 256 bits");
+    }
+
+    // 绝对值的上界: 无符号为 2^bits, 有符号为 2^(bits-1), 负数可取到边界.
+    let limit_bits = if signed { bits - 1 } else { bits };
+    let leading = res.iter().take_while(|&&b| b == 0).count() * 8
+        + res
+            .iter()
+            .find(|&&b| b != 0)
+            .map_or(0, |b| b.leading_zeros() as usize);
+    let value_bits = 256 - leading;
+    let is_pow2 = value_bits > 0 && {
+        let mut one_bit = [0u8; 32];
+        one_bit[31 - (value_bits - 1) / 8] = 1 << ((value_bits - 1) % 8);
+        one_bit == res
+    };
+    assert_throw!(
+        value_bits <= limit_bits || (neg && is_pow2 && value_bits == limit_bits + 1),
+        format!("integer out of range for {} bits", bits)
+    );
+
+    if neg {
+        let mut carry = 1u16;
+        for byte in res.iter_mut().rev() {
+            let x = (!*byte) as u16 + carry;
+            *byte = x as u8;
+            carry = x >> 8;
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // EIP-712 规范中的示例.
+    #[test]
+    fn test_mail_example() -> Resultat<()> {
+        let json = r#"{
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "version", "type": "string"},
+                    {"name": "chainId", "type": "uint256"},
+                    {"name": "verifyingContract", "type": "address"}
+                ],
+                "Person": [
+                    {"name": "name", "type": "string"},
+                    {"name": "wallet", "type": "address"}
+                ],
+                "Mail": [
+                    {"name": "from", "type": "Person"},
+                    {"name": "to", "type": "Person"},
+                    {"name": "contents", "type": "string"}
+                ]
+            },
+            "primaryType": "Mail",
+            "domain": {
+                "name": "Ether Mail",
+                "version": "1",
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+            },
+            "message": {
+                "from": {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+                "to": {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"},
+                "contents": "Hello, Bob!"
+            }
+        }"#;
+        let data = TypedData::from_json(json).catch_()?;
+        assert_throw!(
+            data.encode_type("Mail").catch_()?
+                == "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_throw!(
+            hex::encode(data.domain_separator().catch_()?)
+                == "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+        );
+        assert_throw!(
+            hex::encode(data.signing_hash().catch_()?)
+                == "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_int() -> Resultat<()> {
+        let minus_one = parse_int(&Value::from(-1), 256, true).catch_()?;
+        assert_throw!(minus_one == [0xff; 32]);
+        let x = parse_int(&Value::from("0x0100"), 16, false).catch_()?;
+        assert_throw!(x[30] == 1 && x[31] == 0);
+        assert_throw!(parse_int(&Value::from(256), 8, false).is_err());
+        assert_throw!(parse_int(&Value::from(-128), 8, true).is_ok());
+        assert_throw!(parse_int(&Value::from(128), 8, true).is_err());
+        assert_throw!(parse_int(&Value::from(-1), 8, false).is_err());
+        Ok(())
+    }
+}
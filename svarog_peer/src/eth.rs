@@ -0,0 +1,403 @@
+//! 模块职责:
+//! 1. 从Keystore派生EIP-55校验和地址.
+//! 2. 构造Legacy(EIP-155), EIP-2930, EIP-1559交易, 计算签名哈希, 经`sign_batch`签名后组装原始交易.
+//! 3. EIP-191 `personal_sign` 和 EIP-712 `eth_signTypedData_v4`, 返回65字节的 `r || s || v`.
+//!
+//! 以太坊与比特币同用secp256k1, 因此keygen, reshare等操作直接沿用`btc`模块.
+use erreur::*;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use svarog_algo::elgamal_secp256k1::{KeystoreElgamal, ProjectivePoint};
+
+pub use crate::btc::{
+    biz_export_root, biz_export_root_vault, biz_keygen, biz_keygen_mnem, biz_keygen_mnem_vault,
+    biz_keygen_vault, biz_refresh, biz_refresh_vault, biz_reshare, biz_reshare_pickup,
+    biz_reshare_pickup_vault, biz_reshare_vault, biz_sign, biz_sign_vault,
+};
+use crate::{
+    bip32::derive_pk,
+    eip712::TypedData,
+    sig_encoding::{to_eth_rsv, EthV},
+    structs::{Keystore, MessageKind, SignTask, Signature},
+};
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+pub fn pk_to_address(pk: &ProjectivePoint) -> [u8; 20] {
+    let pk = pk.to_affine().to_encoded_point(false);
+    let hash = keccak256(&pk.as_bytes()[1..]);
+    let mut addr = [0u8; 20];
+    addr.copy_from_slice(&hash[12..]);
+    addr
+}
+
+/// EIP-55校验和编码, 带`0x`前缀.
+pub fn checksum_address(addr: &[u8; 20]) -> String {
+    let lower = hex::encode(addr);
+    let hash = keccak256(lower.as_bytes());
+    let mut res = String::from("0x");
+    for (k, c) in lower.chars().enumerate() {
+        let nibble = (hash[k / 2] >> (4 * (1 - k % 2))) & 0x0f;
+        if c.is_ascii_alphabetic() && nibble >= 8 {
+            res.push(c.to_ascii_uppercase());
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+pub fn derive_address(keystore: &KeystoreElgamal, bip32_path: &str) -> Resultat<String> {
+    let pk = derive_pk(keystore, bip32_path).catch_()?;
+    Ok(checksum_address(&pk_to_address(&pk)))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AccessListItem {
+    pub address: [u8; 20],
+    pub storage_keys: Vec<[u8; 32]>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LegacyTx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub gas_price: u128,
+    pub gas_limit: u64,
+    /// `None` 表示创建合约.
+    pub to: Option<[u8; 20]>,
+    pub value: u128,
+    pub data: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Eip2930Tx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub gas_price: u128,
+    pub gas_limit: u64,
+    pub to: Option<[u8; 20]>,
+    pub value: u128,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Eip1559Tx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub max_priority_fee_per_gas: u128,
+    pub max_fee_per_gas: u128,
+    pub gas_limit: u64,
+    pub to: Option<[u8; 20]>,
+    pub value: u128,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum EthTx {
+    Legacy(LegacyTx),
+    Eip2930(Eip2930Tx),
+    Eip1559(Eip1559Tx),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SignedTx {
+    /// 可直接用于`eth_sendRawTransaction`的原始交易.
+    pub raw: Vec<u8>,
+    pub tx_hash: [u8; 32],
+    pub signature: Signature,
+}
+
+impl EthTx {
+    pub fn chain_id(&self) -> u64 {
+        match self {
+            EthTx::Legacy(tx) => tx.chain_id,
+            EthTx::Eip2930(tx) => tx.chain_id,
+            EthTx::Eip1559(tx) => tx.chain_id,
+        }
+    }
+
+    /// 交易字段的RLP编码, 不含签名.
+    fn rlp_fields(&self) -> Vec<Vec<u8>> {
+        match self {
+            EthTx::Legacy(tx) => vec![
+                rlp_uint(tx.nonce as u128),
+                rlp_uint(tx.gas_price),
+                rlp_uint(tx.gas_limit as u128),
+                rlp_to(&tx.to),
+                rlp_uint(tx.value),
+                rlp_bytes(&tx.data),
+            ],
+            EthTx::Eip2930(tx) => vec![
+                rlp_uint(tx.chain_id as u128),
+                rlp_uint(tx.nonce as u128),
+                rlp_uint(tx.gas_price),
+                rlp_uint(tx.gas_limit as u128),
+                rlp_to(&tx.to),
+                rlp_uint(tx.value),
+                rlp_bytes(&tx.data),
+                rlp_access_list(&tx.access_list),
+            ],
+            EthTx::Eip1559(tx) => vec![
+                rlp_uint(tx.chain_id as u128),
+                rlp_uint(tx.nonce as u128),
+                rlp_uint(tx.max_priority_fee_per_gas),
+                rlp_uint(tx.max_fee_per_gas),
+                rlp_uint(tx.gas_limit as u128),
+                rlp_to(&tx.to),
+                rlp_uint(tx.value),
+                rlp_bytes(&tx.data),
+                rlp_access_list(&tx.access_list),
+            ],
+        }
+    }
+
+    fn type_byte(&self) -> Option<u8> {
+        match self {
+            EthTx::Legacy(_) => None,
+            EthTx::Eip2930(_) => Some(0x01),
+            EthTx::Eip1559(_) => Some(0x02),
+        }
+    }
+
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut fields = self.rlp_fields();
+        if let EthTx::Legacy(tx) = self {
+            // EIP-155
+            fields.push(rlp_uint(tx.chain_id as u128));
+            fields.push(rlp_uint(0));
+            fields.push(rlp_uint(0));
+        }
+        let mut payload: Vec<u8> = self.type_byte().into_iter().collect();
+        payload.extend(rlp_list(&fields));
+        payload
+    }
+
+    pub fn signing_hash(&self) -> [u8; 32] {
+        keccak256(&self.signing_payload())
+    }
+
+    /// 用签名组装原始交易. `sig.v` 是恢复位, 取0或1.
+    pub fn assemble(&self, sig: &Signature) -> Resultat<SignedTx> {
+        assert_throw!(sig.v <= 1, format!("unexpected recovery id {}", sig.v));
+        let mut fields = self.rlp_fields();
+        let v = match self {
+            EthTx::Legacy(tx) => (tx.chain_id as u128) * 2 + 35 + sig.v as u128,
+            _ => sig.v as u128,
+        };
+        fields.push(rlp_uint(v));
+        fields.push(rlp_bytes(strip_zeros(&sig.r)));
+        fields.push(rlp_bytes(strip_zeros(&sig.s)));
+        let mut raw: Vec<u8> = self.type_byte().into_iter().collect();
+        raw.extend(rlp_list(&fields));
+        Ok(SignedTx {
+            tx_hash: keccak256(&raw),
+            raw,
+            signature: sig.clone(),
+        })
+    }
+}
+
+/// 一场会话里签多笔交易, 每笔交易配一个bip32路径.
+pub async fn biz_sign_tx(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<KeystoreElgamal>,
+    txs: Vec<(EthTx, String)>,
+) -> Resultat<Vec<SignedTx>> {
+    let tasks = txs
+        .iter()
+        .map(|(tx, bip32_path)| SignTask {
+            message: tx.signing_hash().to_vec(),
+            bip32_path: bip32_path.clone(),
+            kind: MessageKind::Prehashed32,
+        })
+        .collect();
+    let sigs = biz_sign(sesman_url, session_id, keystore, tasks)
+        .await
+        .catch_()?;
+    assert_throw!(sigs.len() == txs.len());
+
+    let mut res = Vec::new();
+    for ((tx, _), sig) in txs.iter().zip(sigs.iter()) {
+        res.push(tx.assemble(sig).catch_()?);
+    }
+    Ok(res)
+}
+
+/// EIP-191 版本0x45, 即`personal_sign`所签的哈希.
+pub fn personal_message_hash(message: &[u8]) -> [u8; 32] {
+    let mut buf = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    buf.extend_from_slice(message);
+    keccak256(&buf)
+}
+
+/// 65字节 `r || s || v`, 其中 `v` 为27或28, 可被ecrecover验证.
+pub fn to_rsv(sig: &Signature) -> Resultat<[u8; 65]> {
+    let rsv = to_eth_rsv(sig, EthV::Legacy).catch_()?;
+    let res: [u8; 65] = rsv.try_into().ok().ifnone_()?;
+    Ok(res)
+}
+
+/// 对每条消息做`personal_sign`, 每条消息配一个bip32路径.
+pub async fn biz_personal_sign(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<KeystoreElgamal>,
+    messages: Vec<(Vec<u8>, String)>,
+) -> Resultat<Vec<[u8; 65]>> {
+    let digests = messages
+        .iter()
+        .map(|(msg, bip32_path)| (personal_message_hash(msg), bip32_path.clone()))
+        .collect();
+    sign_digests(sesman_url, session_id, keystore, digests).await
+}
+
+/// 对每份EIP-712 JSON做`eth_signTypedData_v4`, 每份配一个bip32路径.
+/// JSON在会话开始前解析和编码, 格式错误时不会加入会话.
+pub async fn biz_sign_typed_data(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<KeystoreElgamal>,
+    typed_data: Vec<(String, String)>,
+) -> Resultat<Vec<[u8; 65]>> {
+    let mut digests = Vec::new();
+    for (k, (json, bip32_path)) in typed_data.iter().enumerate() {
+        let digest = TypedData::from_json(json)
+            .and_then(|data| data.signing_hash())
+            .catch("InvalidTypedData", format!("typed data #{}", k))?;
+        digests.push((digest, bip32_path.clone()));
+    }
+    sign_digests(sesman_url, session_id, keystore, digests).await
+}
+
+async fn sign_digests(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<KeystoreElgamal>,
+    digests: Vec<([u8; 32], String)>,
+) -> Resultat<Vec<[u8; 65]>> {
+    let n = digests.len();
+    let tasks = digests
+        .into_iter()
+        .map(|(digest, bip32_path)| SignTask {
+            message: digest.to_vec(),
+            bip32_path,
+            kind: MessageKind::Prehashed32,
+        })
+        .collect();
+    let sigs = biz_sign(sesman_url, session_id, keystore, tasks)
+        .await
+        .catch_()?;
+    assert_throw!(sigs.len() == n);
+    let mut res = Vec::new();
+    for sig in sigs.iter() {
+        res.push(to_rsv(sig).catch_()?);
+    }
+    Ok(res)
+}
+
+fn strip_zeros(bytes: &[u8]) -> &[u8] {
+    let pos = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    &bytes[pos..]
+}
+
+fn rlp_length_prefix(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        vec![offset + len as u8]
+    } else {
+        let len_bytes = len.to_be_bytes();
+        let len_bytes = strip_zeros(&len_bytes);
+        let mut res = vec![offset + 55 + len_bytes.len() as u8];
+        res.extend_from_slice(len_bytes);
+        res
+    }
+}
+
+fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut res = rlp_length_prefix(bytes.len(), 0x80);
+    res.extend_from_slice(bytes);
+    res
+}
+
+fn rlp_uint(x: u128) -> Vec<u8> {
+    rlp_bytes(strip_zeros(&x.to_be_bytes()))
+}
+
+fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let body: Vec<u8> = items.concat();
+    let mut res = rlp_length_prefix(body.len(), 0xc0);
+    res.extend(body);
+    res
+}
+
+fn rlp_to(to: &Option<[u8; 20]>) -> Vec<u8> {
+    match to {
+        Some(addr) => rlp_bytes(addr),
+        None => rlp_bytes(&[]),
+    }
+}
+
+fn rlp_access_list(access_list: &[AccessListItem]) -> Vec<u8> {
+    let items: Vec<Vec<u8>> = access_list
+        .iter()
+        .map(|item| {
+            let keys: Vec<Vec<u8>> = item.storage_keys.iter().map(|k| rlp_bytes(k)).collect();
+            rlp_list(&[rlp_bytes(&item.address), rlp_list(&keys)])
+        })
+        .collect();
+    rlp_list(&items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_personal_message_hash() -> Resultat<()> {
+        assert_throw!(
+            hex::encode(personal_message_hash(b"hello world"))
+                == "d9eba16ed0ecae432b71fe008c98cc872bb4cc214d3220a36f365326cf807d68"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_address() -> Resultat<()> {
+        let pk = ProjectivePoint::GENERATOR;
+        let addr = checksum_address(&pk_to_address(&pk));
+        assert_throw!(addr == "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf");
+        Ok(())
+    }
+
+    // EIP-155 规范中的示例交易.
+    #[test]
+    fn test_eip155_signing_hash() -> Resultat<()> {
+        let tx = EthTx::Legacy(LegacyTx {
+            chain_id: 1,
+            nonce: 9,
+            gas_price: 20_000_000_000,
+            gas_limit: 21000,
+            to: Some([0x35; 20]),
+            value: 1_000_000_000_000_000_000,
+            data: vec![],
+        });
+        assert_throw!(
+            hex::encode(tx.signing_payload())
+                == "ec098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a764000080018080"
+        );
+        assert_throw!(
+            hex::encode(tx.signing_hash())
+                == "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+//! Keystore导出格式, 版本1.
+//!
+//! 文件由两部分组成:
+//! 1. 首行为魔数和版本号 `SVAROGKS/1`, 以`\n`结尾.
+//! 2. 其余部分为UTF-8的JSON对象, 字段如下.
+//!
+//! | 字段            | 含义                                         |
+//! |-----------------|----------------------------------------------|
+//! | `algorithm`     | `elgamal_secp256k1` 或 `schnorr_ed25519`     |
+//! | `i`             | 本方的成员序号, 从1开始                      |
+//! | `threshold`     | 签名所需的最少人数                           |
+//! | `players`       | 成员序号到成员名称的映射                     |
+//! | `group_pk`      | 群公钥压缩编码的hex                          |
+//! | `created_at`    | 导出时间, Unix秒                             |
+//! | `share`         | Keystore经serde_pickle序列化后的hex          |
+//! | `allowed_paths` | 允许签名的bip32路径前缀, 可省略              |
+//! | `epoch`         | 分片的代数, 可省略                           |
+//! | `refreshed_at`  | 分片生成的时刻, Unix秒, 可省略               |
+//! | `pending`       | 尚未取回分片的成员名称, 可省略               |
+//!
+//! 导入时校验魔数, 版本, 算法, 成员序号, 门限和群公钥, 并用VSS承诺验证分片.
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use erreur::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{keystore::MpcKeystore, structs::Keystore};
+
+pub const EXPORT_MAGIC: &str = "SVAROGKS";
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct ExportBody {
+    algorithm: String,
+    i: usize,
+    threshold: usize,
+    players: BTreeMap<usize, String>,
+    group_pk: String,
+    created_at: u64,
+    share: String,
+    #[serde(default)]
+    allowed_paths: Vec<String>,
+    #[serde(default)]
+    epoch: u64,
+    #[serde(default)]
+    refreshed_at: u64,
+    #[serde(default)]
+    pending: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct ImportedKeystore<K> {
+    pub keystore: Keystore<K>,
+    pub created_at: u64,
+}
+
+pub fn export_keystore<K: MpcKeystore>(keystore: &Keystore<K>) -> Resultat<Vec<u8>> {
+    let i = keystore.party_index();
+    let players: BTreeMap<usize, String> = keystore
+        .players
+        .iter()
+        .map(|(name, &j)| (j, name.clone()))
+        .collect();
+    assert_throw!(
+        players.len() == keystore.players.len(),
+        "duplicate party index in the players map"
+    );
+    assert_throw!(
+        players.contains_key(&i),
+        format!("party {} has no name in the players map", i)
+    );
+    let share = serde_pickle::to_vec(&keystore.share, Default::default()).catch_()?;
+    let body = ExportBody {
+        algorithm: K::ALGO.to_owned(),
+        i,
+        threshold: keystore.threshold,
+        players,
+        group_pk: hex::encode(keystore.group_pk()),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .catch_()?
+            .as_secs(),
+        share: hex::encode(share),
+        allowed_paths: keystore.allowed_paths.clone(),
+        epoch: keystore.epoch,
+        refreshed_at: keystore.refreshed_at,
+        pending: keystore.pending.clone(),
+    };
+
+    let mut out = format!("{}/{}\n", EXPORT_MAGIC, EXPORT_VERSION).into_bytes();
+    out.extend(serde_json::to_vec_pretty(&body).catch_()?);
+    Ok(out)
+}
+
+/// 导出文件中的算法标签. 只校验魔数和版本号.
+pub fn export_algorithm(data: &[u8]) -> Resultat<String> {
+    let body = read_body(data).catch_()?;
+    Ok(body.algorithm)
+}
+
+fn read_body(data: &[u8]) -> Resultat<ExportBody> {
+    let pos = data
+        .iter()
+        .position(|&b| b == b'\n')
+        .ifnone("InvalidExport", "missing header line")?;
+    let header = std::str::from_utf8(&data[..pos]).catch_()?;
+    let (magic, version) = header
+        .split_once('/')
+        .ifnone("InvalidExport", "malformed header")?;
+    assert_throw!(magic == EXPORT_MAGIC, "not a svarog keystore export");
+    let version: u32 = version.parse().catch_()?;
+    assert_throw!(
+        version == EXPORT_VERSION,
+        format!("unsupported export version {}", version)
+    );
+
+    let body: ExportBody = serde_json::from_slice(&data[pos + 1..]).catch_()?;
+    Ok(body)
+}
+
+/// 读出Keystore和文件中记录的群公钥, 不做一致性校验.
+pub(crate) fn read_export<K: MpcKeystore>(data: &[u8]) -> Resultat<(ImportedKeystore<K>, String)> {
+    let body = read_body(data).catch_()?;
+    assert_throw!(
+        body.algorithm == K::ALGO,
+        format!("export is {}, not {}", &body.algorithm, K::ALGO)
+    );
+    let share = hex::decode(&body.share).catch_()?;
+    let share: K = serde_pickle::from_slice(&share, Default::default()).catch_()?;
+    assert_throw!(share.party_index() == body.i, "party index mismatch");
+
+    let imported = ImportedKeystore {
+        keystore: Keystore {
+            share,
+            threshold: body.threshold,
+            players: body
+                .players
+                .into_iter()
+                .map(|(j, name)| (name, j))
+                .collect(),
+            allowed_paths: body.allowed_paths,
+            epoch: body.epoch,
+            refreshed_at: body.refreshed_at,
+            pending: body.pending,
+        },
+        created_at: body.created_at,
+    };
+    Ok((imported, body.group_pk))
+}
+
+pub fn import_keystore<K: MpcKeystore>(data: &[u8]) -> Resultat<ImportedKeystore<K>> {
+    let (imported, group_pk) = read_export::<K>(data).catch_()?;
+    let keystore = &imported.keystore;
+    let i = keystore.party_index();
+    assert_throw!(
+        keystore.share.threshold() == keystore.threshold,
+        "threshold mismatch"
+    );
+    assert_throw!(
+        hex::encode(keystore.group_pk()) == group_pk,
+        "group public key mismatch"
+    );
+    assert_throw!(
+        keystore.players.values().any(|&j| j == i),
+        format!("party {} has no name in the players map", i)
+    );
+    assert_throw!(
+        keystore.verify_share(),
+        "share is inconsistent with the VSS commitments"
+    );
+    Ok(imported)
+}
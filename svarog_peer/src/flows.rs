@@ -0,0 +1,400 @@
+//! Keygen, Reshare, Refresh, 取回分片和根私钥导出的会话流程, 两种曲线共用.
+//! 连接会话, 准备轮, 核对分片代数, 失败归因和审计日志都在这里;
+//! 算法相关的协议部分由 `btc` 和 `solana` 以 `impl_*` 函数的形式传入. Sign的流程见 `holders`.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+};
+
+use erreur::*;
+use svarog_sesman::SvarogChannel;
+
+use crate::{
+    audit::{AuditEvent, Operation},
+    blame::{attribute, session_parties},
+    check_members,
+    keystore::MpcKeystore,
+    mailbox::{absent_consumers, collect_delivery, deliver_pending, Deliverable, ShareTemplate},
+    mnemonic::normalize_mnemonics,
+    ready::{ready_round, required_parties},
+    recovery::RecoveredKey,
+    refresh::{check_refresh_config, exchange_epoch, now_secs},
+    root_export::export_root_in_session,
+    ses_arch, ses_members,
+    structs::{Keystore, Mnemonics, SessionConfig},
+    vault::Vault,
+};
+
+/// Keygen的会话流程, `impl_keygen(chan, i, t, players)` 是算法相关的协议部分.
+pub(crate) async fn audited_keygen<K, F, Fut>(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    impl_keygen: F,
+) -> Resultat<Keystore<K>>
+where
+    K: MpcKeystore,
+    F: FnOnce(SvarogChannel, usize, usize, BTreeSet<usize>) -> Fut,
+    Fut: Future<Output = Resultat<K>>,
+{
+    let mut audit = AuditEvent::new(Operation::Keygen, K::ALGO, &session_id);
+    let res = keygen_in_session(
+        &sesman_url,
+        &session_id,
+        &member_name,
+        &mut audit,
+        |chan, i, t, players| async move { impl_keygen(chan, i, t, players).await.map(Some) },
+    )
+    .await;
+    let res = res.and_then(|keystore| keystore.ifnone("KeygenFailed", "no share"));
+    if let Ok(keystore) = &res {
+        audit.set_new_keystore(keystore);
+    }
+    audit.finish_keystore(res)
+}
+
+/// 助记词Keygen的会话流程, `impl_keygen_mnem(chan, i, t, players, mnemonics)` 是算法相关的协议部分.
+pub(crate) async fn audited_keygen_mnem<K, F, Fut>(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    mnemonics: Option<Mnemonics>,
+    impl_keygen_mnem: F,
+) -> Resultat<Option<Keystore<K>>>
+where
+    K: MpcKeystore,
+    F: FnOnce(SvarogChannel, usize, usize, BTreeSet<usize>, Option<Mnemonics>) -> Fut,
+    Fut: Future<Output = Resultat<Option<K>>>,
+{
+    let mut audit = AuditEvent::new(Operation::KeygenMnem, K::ALGO, &session_id);
+    let res = async {
+        // 在连接会话之前校验, 输错的助记词不会进入keygen.
+        let mnemonics = match mnemonics {
+            Some(mnem) => Some(normalize_mnemonics(&mnem).catch_()?),
+            None => None,
+        };
+        keygen_in_session(
+            &sesman_url,
+            &session_id,
+            &member_name,
+            &mut audit,
+            |chan, i, t, players| impl_keygen_mnem(chan, i, t, players, mnemonics),
+        )
+        .await
+    }
+    .await;
+    if let Ok(Some(keystore)) = &res {
+        audit.set_new_keystore(keystore);
+    }
+    audit.finish_keystore(res)
+}
+
+async fn keygen_in_session<K, F, Fut>(
+    sesman_url: &str,
+    session_id: &str,
+    member_name: &str,
+    audit: &mut AuditEvent,
+    impl_keygen: F,
+) -> Resultat<Option<Keystore<K>>>
+where
+    K: MpcKeystore,
+    F: FnOnce(SvarogChannel, usize, usize, BTreeSet<usize>) -> Fut,
+    Fut: Future<Output = Resultat<Option<K>>>,
+{
+    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
+    let https = sesman_url.starts_with("https://");
+
+    let (mut chan, cfg) = SvarogChannel::use_session(session_id, sesman_url, https)
+        .await
+        .catch_()?;
+    audit.set_config(&cfg).catch_()?;
+    let t = cfg.threshold as usize;
+    let (i, players) = ses_arch(member_name, &cfg.players);
+    assert_throw!(
+        players.len() == cfg.players.len(),
+        "all keygen members should attend"
+    );
+    let required = required_parties(&cfg);
+    ready_round(&mut chan, &cfg, member_name, &required, K::ALGO)
+        .await
+        .catch_()?;
+    let mut watch = chan.clone();
+    let keystore = impl_keygen(chan, i, t, players).await;
+    let parties = session_parties(&[&cfg.players]);
+    let keystore = attribute(keystore, &mut watch, &parties, member_name)
+        .await
+        .catch_()?;
+    let refreshed_at = now_secs().catch_()?;
+    let keystore = keystore.map(|share| Keystore {
+        share,
+        threshold: t,
+        players: ses_members(&cfg.players),
+        allowed_paths: Vec::new(),
+        epoch: 0,
+        refreshed_at,
+        pending: Vec::new(),
+    });
+    Ok(keystore)
+}
+
+/// Reshare的会话流程, `impl_reshare(chan, share, i, t, providers, consumers)` 是算法相关的协议部分.
+pub(crate) async fn audited_reshare<K, F, Fut>(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    keystore: Option<Keystore<K>>,
+    impl_reshare: F,
+) -> Resultat<Option<Keystore<K>>>
+where
+    K: Deliverable,
+    F: FnOnce(SvarogChannel, Option<K>, usize, usize, BTreeSet<usize>, BTreeSet<usize>) -> Fut,
+    Fut: Future<Output = Resultat<Option<K>>>,
+{
+    let mut audit = AuditEvent::new(Operation::Reshare, K::ALGO, &session_id);
+    let res = reshare_session(
+        &sesman_url,
+        &session_id,
+        &member_name,
+        keystore,
+        false,
+        &mut audit,
+        impl_reshare,
+    )
+    .await;
+    if let Ok(Some(keystore)) = &res {
+        audit.set_new_keystore(keystore);
+    }
+    audit.finish_keystore(res)
+}
+
+/// Refresh的会话流程, 经由Reshare的协议部分. 会话配置用 `refresh_config` 构造, 全体成员都要出席.
+pub(crate) async fn audited_refresh<K, F, Fut>(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    keystore: Keystore<K>,
+    impl_reshare: F,
+) -> Resultat<Keystore<K>>
+where
+    K: Deliverable,
+    F: FnOnce(SvarogChannel, Option<K>, usize, usize, BTreeSet<usize>, BTreeSet<usize>) -> Fut,
+    Fut: Future<Output = Resultat<Option<K>>>,
+{
+    let mut audit = AuditEvent::new(Operation::Refresh, K::ALGO, &session_id);
+    let res = reshare_session(
+        &sesman_url,
+        &session_id,
+        &member_name,
+        Some(keystore),
+        true,
+        &mut audit,
+        impl_reshare,
+    )
+    .await;
+    let res = res.and_then(|keystore| keystore.ifnone("RefreshFailed", "no new share"));
+    if let Ok(keystore) = &res {
+        audit.set_new_keystore(keystore);
+    }
+    audit.finish_keystore(res)
+}
+
+/// 连接会话, 运行Reshare并把失败归因到成员. `refresh` 为真时先检查会话配置是否符合Refresh.
+async fn reshare_session<K, F, Fut>(
+    sesman_url: &str,
+    session_id: &str,
+    member_name: &str,
+    keystore: Option<Keystore<K>>,
+    refresh: bool,
+    audit: &mut AuditEvent,
+    impl_reshare: F,
+) -> Resultat<Option<Keystore<K>>>
+where
+    K: Deliverable,
+    F: FnOnce(SvarogChannel, Option<K>, usize, usize, BTreeSet<usize>, BTreeSet<usize>) -> Fut,
+    Fut: Future<Output = Resultat<Option<K>>>,
+{
+    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
+    let https = sesman_url.starts_with("https://");
+
+    let (chan, cfg) = SvarogChannel::use_session(session_id, sesman_url, https)
+        .await
+        .catch_()?;
+    audit.set_config(&cfg).catch_()?;
+    if refresh {
+        let keystore = keystore.as_ref().ifnone("RefreshFailed", "no keystore")?;
+        check_refresh_config(keystore, &cfg).catch_()?;
+    }
+    let mut watch = chan.clone();
+    let keystore = reshare_in_session(chan, &cfg, member_name, keystore, impl_reshare).await;
+    let parties = session_parties(&[&cfg.players, &cfg.players_reshared]);
+    attribute(keystore, &mut watch, &parties, member_name)
+        .await
+        .catch_()
+}
+
+/// Reshare的协议部分, Refresh也经由这里.
+async fn reshare_in_session<K, F, Fut>(
+    mut chan: SvarogChannel,
+    cfg: &SessionConfig,
+    member_name: &str,
+    keystore: Option<Keystore<K>>,
+    impl_reshare: F,
+) -> Resultat<Option<Keystore<K>>>
+where
+    K: Deliverable,
+    F: FnOnce(SvarogChannel, Option<K>, usize, usize, BTreeSet<usize>, BTreeSet<usize>) -> Fut,
+    Fut: Future<Output = Resultat<Option<K>>>,
+{
+    let t = cfg.threshold as usize;
+    let (_, providers) = ses_arch("", &cfg.players);
+    if let Some(keystore) = &keystore {
+        check_members(&keystore.players, &cfg.players).catch_()?;
+        let i0 = keystore.party_index();
+        assert_throw!(providers.contains(&i0), "provider not in the session");
+        if let Some(&j) = keystore.players.get(member_name) {
+            assert_throw!(
+                j == i0,
+                format!(
+                    "index mismatch for player {}: keystore has {}, players map has {}",
+                    member_name, i0, j
+                )
+            );
+        }
+    }
+    let (i, consumers) = ses_arch(member_name, &cfg.players_reshared);
+    let absent = absent_consumers(cfg).catch_()?;
+    let required = required_parties(cfg);
+    ready_round(&mut chan, cfg, member_name, &required, K::ALGO)
+        .await
+        .catch_()?;
+    let allowed_paths = keystore
+        .as_ref()
+        .map(|keystore| keystore.allowed_paths.clone())
+        .unwrap_or_default();
+    let epoch = exchange_epoch(
+        &mut chan,
+        keystore
+            .as_ref()
+            .map(|keystore| (keystore.party_index(), keystore.epoch)),
+        (i > 0).then_some(i),
+        &providers,
+        &consumers,
+    )
+    .await
+    .catch("EpochMismatch", "")?;
+    let epoch = epoch.map_or(0, |epoch| epoch + 1);
+    let refreshed_at = now_secs().catch_()?;
+    let keystore = keystore.map(|keystore| keystore.share);
+    let keystore = impl_reshare(chan.clone(), keystore, i, t, providers, consumers)
+        .await
+        .catch_()?;
+    let keystore = keystore.map(|share| Keystore {
+        share,
+        threshold: t,
+        players: ses_members(&cfg.players_reshared),
+        allowed_paths,
+        epoch,
+        refreshed_at,
+        pending: absent.into_keys().collect(),
+    });
+    if let Some(keystore) = &keystore {
+        deliver_pending(&mut chan, cfg, member_name, keystore)
+            .await
+            .catch("DeliveryFailed", "")?;
+    }
+    Ok(keystore)
+}
+
+/// 取回Reshare时因缺席而错过的分片. `build(template, xi)` 构造算法相关的分片结构,
+/// 得到的Keystore须与VSS承诺一致.
+pub(crate) async fn pickup_keystore<K, F>(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    mailbox_sk: [u8; 32],
+    build: F,
+) -> Resultat<Keystore<K>>
+where
+    K: Deliverable,
+    F: FnOnce(&ShareTemplate, K::Share) -> Resultat<K>,
+{
+    let (template, xi) =
+        collect_delivery::<K::Share>(&sesman_url, &session_id, &member_name, &mailbox_sk)
+            .await
+            .catch_()?;
+    assert_throw!(
+        template.algorithm == K::ALGO,
+        format!("delivered share is {}", &template.algorithm)
+    );
+    let share = build(&template, xi).catch_()?;
+    let keystore = template.keystore(share);
+    assert_throw!(
+        keystore.verify_share(),
+        "delivered share is inconsistent with the VSS commitments"
+    );
+    Ok(keystore)
+}
+
+/// 用 `decode` 解码投递中VSS承诺的压缩编码.
+pub(crate) fn decode_vss<P, F>(
+    template: &ShareTemplate,
+    decode: F,
+) -> Resultat<BTreeMap<usize, Vec<P>>>
+where
+    F: Fn(&[u8]) -> Option<P>,
+{
+    let mut vss_scheme = BTreeMap::new();
+    for (&j, coms) in template.vss_scheme.iter() {
+        let coms = coms
+            .iter()
+            .map(|com| decode(com).ifnone("InvalidCommitment", format!("dealer {}", j)))
+            .collect::<Resultat<Vec<P>>>()?;
+        vss_scheme.insert(j, coms);
+    }
+    Ok(vss_scheme)
+}
+
+/// 根私钥导出仪式, 见 `root_export`. `encode(keystore, secret, holders)` 把导出的根私钥编码为 `RecoveredKey`.
+pub(crate) async fn audited_export_root<K, F>(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<K>,
+    encode: F,
+) -> Resultat<Option<RecoveredKey>>
+where
+    K: Deliverable,
+    F: FnOnce(&Keystore<K>, K::Share, BTreeSet<usize>) -> Resultat<RecoveredKey>,
+{
+    let mut audit = AuditEvent::new(Operation::RootExport, K::ALGO, &session_id);
+    audit.set_keystore(&keystore);
+    let res = export_root_in_session(&sesman_url, &session_id, &keystore, &mut audit).await;
+    let res = match res {
+        Ok(Some((secret, holders))) => encode(&keystore, secret, holders).map(Some),
+        Ok(None) => Ok(None),
+        Err(e) => Err(e),
+    };
+    audit.finish(res)
+}
+
+/// `key_id` 为空时返回 `None`, 否则从保管库载入Keystore.
+pub(crate) fn load_opt<K: MpcKeystore>(
+    vault: &Vault,
+    key_id: Option<&str>,
+) -> Resultat<Option<Keystore<K>>> {
+    match key_id {
+        Some(key_id) => Ok(Some(vault.load(key_id).catch_()?)),
+        None => Ok(None),
+    }
+}
+
+/// 会话得到的Keystore存入保管库, 返回key id. 本方没有得到分片时为 `None`.
+pub(crate) fn save_opt<K: MpcKeystore>(
+    vault: &Vault,
+    keystore: Option<Keystore<K>>,
+    member_name: &str,
+) -> Resultat<Option<String>> {
+    match keystore {
+        Some(keystore) => Ok(Some(vault.save(&keystore, member_name).catch_()?)),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,200 @@
+//! 分片体检. 在重要的仪式之前离线检查保存的分片是否仍然完好, 不访问网络.
+//!
+//! 检查项:
+//! 1. 分片与VSS承诺一致, 即 `xi * G` 等于承诺多项式在本方序号处的值.
+//! 2. 各dealer的承诺多项式系数个数都等于门限.
+//! 3. 由常数项承诺得出的群公钥与记录的群公钥 (导出文件或保管库索引中的) 一致.
+//! 4. ElGamal的Paillier密钥与 `paillier_n_dict` 中本方的模数一致.
+//! 5. 本方序号在成员名单中.
+use serde::{Deserialize, Serialize};
+
+use erreur::*;
+
+use crate::{
+    export::{export_algorithm, read_export},
+    keystore::{key_id, share_fingerprint, MpcKeystore},
+    structs::{Keystore, KeystoreElgamal, KeystoreSchnorr},
+    vault::Vault,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ShareHealth {
+    pub key_id: String,
+    pub fingerprint: String,
+    pub algorithm: String,
+    pub i: usize,
+    pub member_name: String,
+    pub threshold: usize,
+    pub epoch: u64,
+    pub group_pk: String,
+    pub share_consistent: bool,
+    pub commitments_consistent: bool,
+    /// 没有记录的群公钥可供比对时为 `None`.
+    pub group_pk_consistent: Option<bool>,
+    /// Schnorr没有此项, 为 `None`.
+    pub paillier_consistent: Option<bool>,
+    pub problems: Vec<String>,
+}
+
+impl ShareHealth {
+    pub fn healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// 检查分片. `recorded_pk` 为别处记录的群公钥压缩编码的hex.
+pub fn check_share<K: MpcKeystore>(
+    keystore: &Keystore<K>,
+    recorded_pk: Option<&str>,
+) -> ShareHealth {
+    let i = keystore.party_index();
+    let group_pk = hex::encode(keystore.group_pk());
+    let member_name = keystore
+        .players
+        .iter()
+        .find(|(_, &j)| j == i)
+        .map(|(name, _)| name.clone())
+        .unwrap_or_default();
+    let share_consistent = keystore.verify_share();
+    let commitments_consistent = keystore.verify_commitments();
+    let group_pk_consistent = recorded_pk.map(|pk| pk == group_pk);
+    let paillier_consistent = keystore.verify_extra();
+
+    let mut problems = Vec::new();
+    if !share_consistent {
+        problems.push("share is inconsistent with the VSS commitments".to_owned());
+    }
+    if !commitments_consistent {
+        problems.push(format!(
+            "VSS commitments do not all have {} coefficients",
+            keystore.threshold
+        ));
+    }
+    if group_pk_consistent == Some(false) {
+        problems.push(format!(
+            "constant-term commitments give group public key {}, recorded {}",
+            &group_pk,
+            recorded_pk.unwrap_or_default()
+        ));
+    }
+    if paillier_consistent == Some(false) {
+        problems.push(format!(
+            "paillier key does not match entry {} of paillier_n_dict",
+            i
+        ));
+    }
+    if member_name.is_empty() {
+        problems.push(format!("party {} has no name in the players map", i));
+    }
+
+    ShareHealth {
+        key_id: key_id(keystore),
+        fingerprint: share_fingerprint(keystore),
+        algorithm: K::ALGO.to_owned(),
+        i,
+        member_name,
+        threshold: keystore.threshold,
+        epoch: keystore.epoch,
+        group_pk,
+        share_consistent,
+        commitments_consistent,
+        group_pk_consistent,
+        paillier_consistent,
+        problems,
+    }
+}
+
+/// 检查导出文件中的分片, 与文件中记录的群公钥比对.
+pub fn check_export(data: &[u8]) -> Resultat<ShareHealth> {
+    let algorithm = export_algorithm(data).catch_()?;
+    let health = if algorithm == KeystoreElgamal::ALGO {
+        let (imported, group_pk) = read_export::<KeystoreElgamal>(data).catch_()?;
+        check_share(&imported.keystore, Some(&group_pk))
+    } else if algorithm == KeystoreSchnorr::ALGO {
+        let (imported, group_pk) = read_export::<KeystoreSchnorr>(data).catch_()?;
+        check_share(&imported.keystore, Some(&group_pk))
+    } else {
+        return None.ifnone("UnsupportedAlgorithm", format!("export is {}", &algorithm));
+    };
+    Ok(health)
+}
+
+/// 检查保管库中的分片, 与索引中记录的群公钥比对. 索引不一致时 `Vault::load` 即失败.
+pub fn check_vault(vault: &Vault, key_id: &str) -> Resultat<ShareHealth> {
+    let entry = vault
+        .entries()
+        .catch_()?
+        .into_iter()
+        .find(|entry| entry.key_id == key_id)
+        .ifnone(
+            "KeyNotFound",
+            format!("key id {} is not in the vault", key_id),
+        )?;
+    let health = if entry.algorithm == KeystoreElgamal::ALGO {
+        let keystore: Keystore<KeystoreElgamal> = vault.load(key_id).catch_()?;
+        check_share(&keystore, Some(&entry.group_pk))
+    } else if entry.algorithm == KeystoreSchnorr::ALGO {
+        let keystore: Keystore<KeystoreSchnorr> = vault.load(key_id).catch_()?;
+        check_share(&keystore, Some(&entry.group_pk))
+    } else {
+        return None.ifnone(
+            "UnsupportedAlgorithm",
+            format!("key {} is {}", key_id, &entry.algorithm),
+        );
+    };
+    Ok(health)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use curve25519_dalek::{EdwardsPoint, Scalar};
+
+    use super::*;
+
+    #[test]
+    fn test_check_share() -> Resultat<()> {
+        // 两个dealer, 门限2, 本方序号2.
+        let coefs = [
+            [Scalar::from(3u64), Scalar::from(5u64)],
+            [Scalar::from(7u64), Scalar::from(11u64)],
+        ];
+        let i = Scalar::from(2u64);
+        let xi = coefs.iter().map(|[a0, a1]| a0 + a1 * i).sum();
+        let vss_scheme = coefs
+            .iter()
+            .enumerate()
+            .map(|(j, coef)| (j + 1, coef.iter().map(EdwardsPoint::mul_base).collect()))
+            .collect();
+        let mut keystore = Keystore {
+            share: KeystoreSchnorr {
+                i: 2,
+                xi,
+                vss_scheme,
+                chain_code: [0u8; 32],
+            },
+            threshold: 2,
+            players: BTreeMap::from([("Alice".to_owned(), 1), ("Bob".to_owned(), 2)]),
+            allowed_paths: Vec::new(),
+            epoch: 0,
+            refreshed_at: 0,
+            pending: Vec::new(),
+        };
+        let group_pk = hex::encode(keystore.group_pk());
+        let health = check_share(&keystore, Some(&group_pk));
+        assert_throw!(health.healthy() && health.member_name == "Bob");
+        assert_throw!(health.paillier_consistent.is_none());
+        let fingerprint = health.fingerprint.clone();
+        assert_throw!(fingerprint == share_fingerprint(&keystore.share));
+
+        keystore.share.xi += Scalar::ONE;
+        keystore.threshold = 3;
+        let health = check_share(&keystore, Some("00"));
+        assert_throw!(!health.share_consistent && !health.commitments_consistent);
+        assert_throw!(health.group_pk_consistent == Some(false));
+        assert_throw!(health.problems.len() == 3);
+        assert_throw!(health.key_id == key_id(&keystore) && health.fingerprint != fingerprint);
+        Ok(())
+    }
+}
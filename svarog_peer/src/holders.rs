@@ -0,0 +1,208 @@
+//! 持有者会话的公共流程, Sign和根私钥导出共用: 确定本方的成员名称, 检查分片是否过期,
+//! 准备轮, 核对分片代数, 以及把协议中的失败归因到具体成员.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+};
+
+use erreur::*;
+use svarog_algo::elgamal_secp256k1::KeystoreElgamal;
+use svarog_sesman::SvarogChannel;
+
+use crate::{
+    approval::{await_approval, store_receipt},
+    audit::{AuditEvent, Operation},
+    blame::{attribute, session_parties},
+    check_members,
+    keystore::{key_id, MpcKeystore},
+    policy::{enforce, recheck, SignRequest},
+    ready::{ready_round, required_parties},
+    refresh::exchange_epoch,
+    ses_arch,
+    sign_task::prepare_tasks,
+    signers::select_signers,
+    structs::{Keystore, SessionConfig, SignTask, Signature},
+};
+
+/// 准备轮和代数核对都已通过的会话.
+pub(crate) struct HolderSession {
+    pub chan: SvarogChannel,
+    /// 归因用的通道副本.
+    pub watch: SvarogChannel,
+    pub parties: BTreeMap<usize, Vec<String>>,
+    pub reporter: String,
+}
+
+/// 本方在Keystore中的成员名称.
+pub(crate) fn reporter<K: MpcKeystore>(keystore: &Keystore<K>) -> String {
+    let i = keystore.party_index();
+    keystore
+        .players
+        .iter()
+        .find(|(_, &j)| j == i)
+        .map(|(name, _)| name.clone())
+        .unwrap_or_default()
+}
+
+/// Reshare时缺席的成员不在其他成员的 `paillier_n_dict` 中, Refresh之前不能参与ElGamal签名.
+/// Schnorr没有Paillier密钥, 不受此限制.
+pub(crate) fn check_not_pending<K: MpcKeystore>(
+    keystore: &Keystore<K>,
+    holders: &BTreeSet<usize>,
+) -> Resultat<()> {
+    if K::ALGO != KeystoreElgamal::ALGO {
+        return Ok(());
+    }
+    for name in keystore.pending.iter() {
+        let j = keystore.players.get(name).copied().unwrap_or(0);
+        assert_throw!(
+            !holders.contains(&j),
+            format!(
+                "{} missed the last reshare, run a refresh before signing",
+                name
+            )
+        );
+    }
+    Ok(())
+}
+
+/// 准备轮之后在 `holders` 之间核对分片代数.
+pub(crate) async fn start_holders<K: MpcKeystore>(
+    mut chan: SvarogChannel,
+    cfg: &SessionConfig,
+    keystore: &Keystore<K>,
+    holders: &BTreeSet<usize>,
+    reporter: String,
+) -> Resultat<HolderSession> {
+    let i = keystore.party_index();
+    let parties = session_parties(&[&cfg.players]);
+    let required = required_parties(cfg);
+    ready_round(&mut chan, cfg, &reporter, &required, K::ALGO)
+        .await
+        .catch_()?;
+    let mut watch = chan.clone();
+    let epoch = exchange_epoch(
+        &mut chan,
+        Some((i, keystore.epoch)),
+        Some(i),
+        holders,
+        holders,
+    )
+    .await
+    .catch("EpochMismatch", "");
+    attribute(epoch, &mut watch, &parties, &reporter)
+        .await
+        .catch_()?;
+    Ok(HolderSession {
+        chan,
+        watch,
+        parties,
+        reporter,
+    })
+}
+
+fn sign_request<K: MpcKeystore>(
+    keystore: &Keystore<K>,
+    cfg: &SessionConfig,
+    tasks: &[(Vec<u8>, String)],
+) -> SignRequest {
+    SignRequest {
+        key_id: key_id(&keystore.share),
+        cosigners: cfg
+            .players
+            .iter()
+            .filter(|(_, &att)| att)
+            .map(|(name, _)| name.clone())
+            .collect(),
+        bip32_paths: tasks.iter().map(|(_, path)| path.clone()).collect(),
+    }
+}
+
+/// Sign的会话流程并写审计日志, `impl_sign` 是算法相关的协议部分.
+pub(crate) async fn audited_sign<K, F, Fut>(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<K>,
+    tasks: Vec<SignTask>,
+    impl_sign: F,
+) -> Resultat<Vec<Signature>>
+where
+    K: MpcKeystore,
+    F: FnOnce(SvarogChannel, K, BTreeSet<usize>, Vec<(Vec<u8>, String)>) -> Fut,
+    Fut: Future<Output = Resultat<Vec<Signature>>>,
+{
+    let mut audit = AuditEvent::new(Operation::Sign, K::ALGO, &session_id);
+    audit.set_keystore(&keystore);
+    let res = sign_in_session(
+        sesman_url, session_id, keystore, tasks, &mut audit, impl_sign,
+    )
+    .await;
+    if let Ok(sigs) = &res {
+        audit.set_signatures(sigs);
+    }
+    audit.finish(res)
+}
+
+/// Sign的会话流程, `impl_sign` 是算法相关的协议部分.
+/// 签名策略和人工审批在报到和准备轮之前完成, 未获批准的一方不会加入协议.
+/// 签名成功之后才计入速率限制.
+async fn sign_in_session<K, F, Fut>(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<K>,
+    tasks: Vec<SignTask>,
+    audit: &mut AuditEvent,
+    impl_sign: F,
+) -> Resultat<Vec<Signature>>
+where
+    K: MpcKeystore,
+    F: FnOnce(SvarogChannel, K, BTreeSet<usize>, Vec<(Vec<u8>, String)>) -> Fut,
+    Fut: Future<Output = Resultat<Vec<Signature>>>,
+{
+    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
+    let https = sesman_url.starts_with("https://");
+    let tasks = prepare_tasks(K::ALGO, &keystore.allowed_paths, &tasks).catch_()?;
+    audit.set_tasks(&tasks);
+    let i = keystore.party_index();
+    let reporter = reporter(&keystore);
+    check_not_pending(&keystore, &BTreeSet::from([i])).catch_()?;
+
+    let (mut chan, cfg) = SvarogChannel::use_session(&session_id, &sesman_url, https)
+        .await
+        .catch_()?;
+    audit.set_config(&cfg).catch_()?;
+    check_members(&keystore.players, &cfg.players).catch_()?;
+    // 动态选择签名方时, 这里的cosigners是全体出席的候选者.
+    let req = sign_request(&keystore, &cfg, &tasks);
+    let pending = enforce(&req).catch_()?;
+    let approval = await_approval(&session_id, i, K::ALGO, &req, &tasks)
+        .await
+        .catch_()?;
+
+    let cfg = select_signers(&mut chan, cfg, &reporter, keystore.threshold)
+        .await
+        .catch_()?;
+    audit.set_config(&cfg).catch_()?;
+    let locked = sign_request(&keystore, &cfg, &tasks);
+    if locked.cosigners != req.cosigners {
+        recheck(&locked).catch_()?;
+    }
+    let (_, signers) = ses_arch("", &cfg.players);
+    assert_throw!(signers.contains(&i), "signer not in the session");
+    check_not_pending(&keystore, &signers).catch_()?;
+
+    let mut ses = start_holders(chan, &cfg, &keystore, &signers, reporter)
+        .await
+        .catch_()?;
+    let sigs = impl_sign(ses.chan, keystore.share, signers, tasks).await;
+    let sigs = attribute(sigs, &mut ses.watch, &ses.parties, &ses.reporter)
+        .await
+        .catch_()?;
+    if let Some(pending) = pending {
+        pending.commit().catch_()?;
+    }
+    if let Some((receipt, config)) = approval {
+        store_receipt(&config, receipt, &sigs).catch_()?;
+    }
+    Ok(sigs)
+}
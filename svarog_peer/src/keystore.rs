@@ -0,0 +1,243 @@
+//! 两种Keystore的公共抽象. 保管库等与算法无关的模块通过它访问Keystore.
+use curve25519_dalek::{traits::Identity, EdwardsPoint};
+use erreur::*;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use svarog_algo::{
+    elgamal_secp256k1::{KeystoreElgamal, ProjectivePoint, Scalar},
+    schnorr_ed25519::KeystoreSchnorr,
+};
+
+use crate::{
+    bip32::{derive_pk, derive_pk_ed25519},
+    structs::Keystore,
+};
+
+/// 群密钥的标识, 由算法和群公钥确定. 同一把群密钥的各方分片, 以及Reshare和Refresh前后的分片, key id相同.
+pub fn key_id<K: MpcKeystore>(keystore: &K) -> String {
+    format!("{}-{}", K::ALGO, hex::encode(keystore.group_pk()))
+}
+
+/// 分片的指纹, 由key id, 成员序号和 `xi * G` 确定, 不泄露分片本身.
+/// 各方的分片指纹互不相同, Reshare和Refresh之后也会改变.
+pub fn share_fingerprint<K: MpcKeystore>(keystore: &K) -> String {
+    let mut ha = Sha256::new();
+    ha.update(b"svarog share fingerprint");
+    ha.update(key_id(keystore).as_bytes());
+    ha.update((keystore.party_index() as u64).to_be_bytes());
+    ha.update(keystore.public_share());
+    hex::encode(&ha.finalize()[..16])
+}
+
+pub trait MpcKeystore: Serialize + DeserializeOwned + Clone + Send + Sync + 'static {
+    /// 算法标签, 写入保管库和导出文件.
+    const ALGO: &'static str;
+
+    /// 本方在keystore中的序号, 从1开始.
+    fn party_index(&self) -> usize;
+
+    /// 群公钥的压缩编码. secp256k1为33字节, ed25519为32字节.
+    fn group_pk(&self) -> Vec<u8>;
+
+    /// 本方分片的公开部分 `xi * G` 的压缩编码.
+    fn public_share(&self) -> Vec<u8>;
+
+    /// BIP32根节点的链码.
+    fn chain_code(&self) -> [u8; 32];
+
+    /// 按 `bip32_path` 非硬化派生的子公钥, 编码与 `group_pk` 相同.
+    fn derive_pk(&self, bip32_path: &str) -> Resultat<Vec<u8>>;
+
+    /// 门限, 即签名所需的最少人数. 等于VSS承诺多项式的系数个数.
+    fn threshold(&self) -> usize;
+
+    /// 成员 `j` 的分片的公开部分 `sum_l sum_k C_lk * j^k`, 由VSS承诺得出, 压缩编码.
+    fn public_share_of(&self, j: usize) -> Vec<u8>;
+
+    /// 检查 `xi * G == sum_j sum_k C_jk * i^k`, 即分片与VSS承诺一致.
+    fn verify_share(&self) -> bool {
+        self.public_share() == self.public_share_of(self.party_index())
+    }
+
+    /// 检查每个dealer的VSS承诺多项式都有 `threshold()` 个系数.
+    fn verify_commitments(&self) -> bool;
+
+    /// 算法特有的自洽检查. ElGamal检查Paillier密钥与 `paillier_n_dict` 中本方的模数一致.
+    /// 无此类检查, 或无法检查时为 `None`.
+    fn verify_extra(&self) -> Option<bool> {
+        None
+    }
+
+    /// 分片的代数, 见 `Keystore.epoch`. 裸分片没有代数, 视为0.
+    fn epoch(&self) -> u64 {
+        0
+    }
+}
+
+impl MpcKeystore for KeystoreElgamal {
+    const ALGO: &'static str = "elgamal_secp256k1";
+
+    fn party_index(&self) -> usize {
+        self.i
+    }
+
+    fn group_pk(&self) -> Vec<u8> {
+        let mut pk = ProjectivePoint::IDENTITY;
+        for coef_coms in self.vss_scheme.values() {
+            pk += coef_coms[0];
+        }
+        pk.to33bytes().to_vec()
+    }
+
+    fn public_share(&self) -> Vec<u8> {
+        (ProjectivePoint::GENERATOR * self.xi).to33bytes().to_vec()
+    }
+
+    fn chain_code(&self) -> [u8; 32] {
+        self.chain_code
+    }
+
+    fn derive_pk(&self, bip32_path: &str) -> Resultat<Vec<u8>> {
+        let pk = derive_pk(self, bip32_path).catch_()?;
+        Ok(pk.to33bytes().to_vec())
+    }
+
+    fn threshold(&self) -> usize {
+        self.vss_scheme.values().next().map_or(0, |c| c.len())
+    }
+
+    fn public_share_of(&self, j: usize) -> Vec<u8> {
+        let j = Scalar::from(j as u64);
+        let mut expected = ProjectivePoint::IDENTITY;
+        for coef_coms in self.vss_scheme.values() {
+            let mut eval = ProjectivePoint::IDENTITY;
+            for com in coef_coms.iter().rev() {
+                eval = eval * j + com;
+            }
+            expected += eval;
+        }
+        expected.to33bytes().to_vec()
+    }
+
+    fn verify_commitments(&self) -> bool {
+        let t = self.threshold();
+        t > 0
+            && self
+                .vss_scheme
+                .values()
+                .all(|coef_coms| coef_coms.len() == t)
+    }
+
+    fn verify_extra(&self) -> Option<bool> {
+        let n = match self.paillier_n_dict.get(&self.i) {
+            Some(n) => serde_json::to_value(n).ok()?,
+            None => return Some(false),
+        };
+        let key = serde_json::to_value(&self.paillier_key).ok()?;
+        key.get("n").map(|own| own == &n)
+    }
+}
+
+impl MpcKeystore for KeystoreSchnorr {
+    const ALGO: &'static str = "schnorr_ed25519";
+
+    fn party_index(&self) -> usize {
+        self.i
+    }
+
+    fn group_pk(&self) -> Vec<u8> {
+        let mut pk = EdwardsPoint::identity();
+        for coef_coms in self.vss_scheme.values() {
+            pk += coef_coms[0];
+        }
+        pk.compress().to_bytes().to_vec()
+    }
+
+    fn public_share(&self) -> Vec<u8> {
+        EdwardsPoint::mul_base(&self.xi)
+            .compress()
+            .to_bytes()
+            .to_vec()
+    }
+
+    fn chain_code(&self) -> [u8; 32] {
+        self.chain_code
+    }
+
+    fn derive_pk(&self, bip32_path: &str) -> Resultat<Vec<u8>> {
+        let pk = derive_pk_ed25519(self, bip32_path).catch_()?;
+        Ok(pk.compress().to_bytes().to_vec())
+    }
+
+    fn threshold(&self) -> usize {
+        self.vss_scheme.values().next().map_or(0, |c| c.len())
+    }
+
+    fn public_share_of(&self, j: usize) -> Vec<u8> {
+        use curve25519_dalek::Scalar;
+
+        let j = Scalar::from(j as u64);
+        let mut expected = EdwardsPoint::identity();
+        for coef_coms in self.vss_scheme.values() {
+            let mut eval = EdwardsPoint::identity();
+            for com in coef_coms.iter().rev() {
+                eval = eval * j + com;
+            }
+            expected += eval;
+        }
+        expected.compress().to_bytes().to_vec()
+    }
+
+    fn verify_commitments(&self) -> bool {
+        let t = self.threshold();
+        t > 0
+            && self
+                .vss_scheme
+                .values()
+                .all(|coef_coms| coef_coms.len() == t)
+    }
+}
+
+impl<K: MpcKeystore> MpcKeystore for Keystore<K> {
+    const ALGO: &'static str = K::ALGO;
+
+    fn party_index(&self) -> usize {
+        self.share.party_index()
+    }
+
+    fn group_pk(&self) -> Vec<u8> {
+        self.share.group_pk()
+    }
+
+    fn public_share(&self) -> Vec<u8> {
+        self.share.public_share()
+    }
+
+    fn chain_code(&self) -> [u8; 32] {
+        self.share.chain_code()
+    }
+
+    fn derive_pk(&self, bip32_path: &str) -> Resultat<Vec<u8>> {
+        self.share.derive_pk(bip32_path)
+    }
+
+    fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    fn public_share_of(&self, j: usize) -> Vec<u8> {
+        self.share.public_share_of(j)
+    }
+
+    fn verify_commitments(&self) -> bool {
+        self.share.verify_commitments() && self.share.threshold() == self.threshold
+    }
+
+    fn verify_extra(&self) -> Option<bool> {
+        self.share.verify_extra()
+    }
+
+    fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
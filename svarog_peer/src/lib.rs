@@ -4,10 +4,36 @@ use erreur::*;
 use svarog_grpc::SessionConfig;
 use svarog_sesman::SvarogChannel;
 
+pub mod approval;
+pub mod audit;
+pub mod bip32;
+pub mod blame;
 pub mod btc;
-pub use btc as eth;
+pub mod btc_address;
+pub mod eip712;
+pub mod eth;
+pub mod export;
+pub(crate) mod flows;
+pub mod health;
+pub(crate) mod holders;
+pub mod keystore;
+pub mod mailbox;
+pub mod mnemonic;
+pub(crate) mod paillier;
+pub mod policy;
+pub mod psbt;
+pub mod ready;
+pub mod recovery;
+pub mod refresh;
+pub mod root_export;
+pub mod sig_encoding;
+pub mod sign_task;
+pub mod signers;
 pub mod solana;
+pub mod solana_tx;
 pub mod structs;
+pub mod vault;
+pub mod verify;
 
 pub async fn new_session(cfg: SessionConfig) -> Resultat<String> {
     assert_throw!(cfg.sesman_url.starts_with("http://") || cfg.sesman_url.starts_with("https://"));
@@ -21,6 +47,40 @@ pub async fn new_session(cfg: SessionConfig) -> Resultat<String> {
     Ok(sid)
 }
 
+/// 成员名称到成员序号的映射. 序号按名称排序后从1开始编号.
+fn ses_members(names: &HashMap<String, bool>) -> BTreeMap<String, usize> {
+    let names: BTreeSet<&String> = names.keys().collect();
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(j, name)| (name.clone(), j + 1))
+        .collect()
+}
+
+/// 检查会话的成员名单与Keystore记录的成员结构是否一致.
+fn check_members(members: &BTreeMap<String, usize>, names: &HashMap<String, bool>) -> Resultat<()> {
+    let session = ses_members(names);
+    for (name, j) in session.iter() {
+        let i = members
+            .get(name)
+            .ifnone("PlayerUnknown", format!("player {} unknown", name))?;
+        assert_throw!(
+            i == j,
+            format!(
+                "index mismatch for player {}: keystore has {}, session has {}",
+                name, i, j
+            )
+        );
+    }
+    for name in members.keys() {
+        assert_throw!(
+            session.contains_key(name),
+            format!("player {} missing from the session", name)
+        );
+    }
+    Ok(())
+}
+
 fn ses_arch(name: &str, names: &HashMap<String, bool>) -> (usize, BTreeSet<usize>) {
     let names: BTreeMap<String, bool> = names.iter().map(|(k, v)| (k.clone(), *v)).collect();
     let mut i = 0;
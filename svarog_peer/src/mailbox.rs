@@ -0,0 +1,758 @@
+//! 缺席consumer的分片投递.
+//!
+//! Reshare允许至多 `SessionConfig.max_absent_consumers` 个consumer缺席, 出席的consumer不少于门限即可.
+//! 缺席成员的序号仍按 `players_reshared` 的全部名单编号, 出席的consumer完成Reshare后,
+//! 作为helper为每个缺席成员 `a` 计算分片 `x_a = sum_i lambda_i(a) * x_i`:
+//! 1. 每个helper广播一次性的临时公钥, 附上用自己的分片 `x_i` 作的Schnorr签名.
+//!    其他helper用VSS承诺算出的 `x_i * G` 验证, sesman无法替换临时公钥.
+//! 2. helper `i` 把 `lambda_i(a) * x_i` 拆成随机的加法分量, 分别加密发给其他helper.
+//! 3. 每个helper把收到的分量与自留的分量相加, 连同不含秘密的模板, 用缺席成员的邮箱公钥
+//!    (`SessionConfig.mailbox_pks`) 加密后存入sesman的邮箱.
+//!
+//! 单个helper的投递与 `x_i` 无关, sesman看不到任何明文分量, 即使它篡改转发的消息. 缺席成员之后用 `biz_reshare_pickup`
+//! 取回全部投递, 相加得到 `x_a`, 并用VSS承诺验证.
+//! 新Keystore的 `pending` 记录尚未取回分片的成员, 下一次Refresh后清空.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::{Add, Mul, Sub},
+};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use curve25519_dalek::{edwards::CompressedEdwardsY, EdwardsPoint};
+use erreur::*;
+use k256::{
+    elliptic_curve::{ops::Reduce, Field, PrimeField},
+    PublicKey, U256,
+};
+use mpc_sig_abs::BatchMessenger;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use svarog_algo::{
+    elgamal_secp256k1::{self, KeystoreElgamal},
+    schnorr_ed25519::KeystoreSchnorr,
+};
+use svarog_grpc::SessionConfig;
+use svarog_sesman::SvarogChannel;
+
+use crate::{blame::Abort, keystore::MpcKeystore, ses_members, structs::Keystore};
+
+const DELIVER_KEY_TOPIC: &str = "svarog_peer_deliver_key";
+const DELIVER_PIECE_TOPIC: &str = "svarog_peer_deliver_piece";
+
+/// 新的邮箱私钥. 对应的公钥由 `mailbox_pk` 得到, 交给发起Reshare的一方写入会话配置.
+pub fn generate_mailbox_key() -> [u8; 32] {
+    let mut sk = [0u8; 32];
+    OsRng.fill_bytes(&mut sk);
+    sk
+}
+
+pub fn mailbox_pk(sk: &[u8; 32]) -> [u8; 32] {
+    let sk = curve25519_dalek::Scalar::from_bytes_mod_order(*sk);
+    EdwardsPoint::mul_base(&sk).compress().to_bytes()
+}
+
+fn cipher_key(tag: &str, ephemeral: &[u8], pk: &[u8], shared: &EdwardsPoint) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(tag.as_bytes());
+    hasher.update(ephemeral);
+    hasher.update(pk);
+    hasher.update(shared.compress().as_bytes());
+    hasher.finalize().into()
+}
+
+/// ECIES: `E || nonce || ciphertext`, 其中 `E` 是临时公钥, `tag` 作为附加数据.
+pub(crate) fn seal(pk: &[u8], tag: &str, plain: &[u8]) -> Resultat<Vec<u8>> {
+    let point = CompressedEdwardsY::from_slice(pk)
+        .ok()
+        .and_then(|pk| pk.decompress())
+        .ifnone("InvalidMailboxKey", "")?;
+    let mut wide = [0u8; 64];
+    OsRng.fill_bytes(&mut wide);
+    let e = curve25519_dalek::Scalar::from_bytes_mod_order_wide(&wide);
+    let ephemeral = EdwardsPoint::mul_base(&e).compress().to_bytes();
+    let key = cipher_key(tag, &ephemeral, pk, &(point * e));
+
+    let mut nonce = [0u8; 24];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = XChaCha20Poly1305::new(&key.into())
+        .encrypt(
+            &XNonce::from(nonce),
+            Payload {
+                msg: plain,
+                aad: tag.as_bytes(),
+            },
+        )
+        .catch("EncryptionFailed", "")?;
+    let mut out = ephemeral.to_vec();
+    out.extend_from_slice(&nonce);
+    out.extend(ciphertext);
+    Ok(out)
+}
+
+pub(crate) fn open(sk: &[u8; 32], tag: &str, sealed: &[u8]) -> Resultat<Vec<u8>> {
+    assert_throw!(sealed.len() > 32 + 24, "sealed message too short");
+    let (ephemeral, rest) = sealed.split_at(32);
+    let (nonce, ciphertext) = rest.split_at(24);
+    let point = CompressedEdwardsY::from_slice(ephemeral)
+        .ok()
+        .and_then(|e| e.decompress())
+        .ifnone("DecryptionFailed", "invalid ephemeral key")?;
+    let sk = curve25519_dalek::Scalar::from_bytes_mod_order(*sk);
+    let pk = EdwardsPoint::mul_base(&sk).compress().to_bytes();
+    let key = cipher_key(tag, ephemeral, &pk, &(point * sk));
+    let nonce: [u8; 24] = nonce.try_into().catch_()?;
+    let plain = XChaCha20Poly1305::new(&key.into())
+        .decrypt(
+            &XNonce::from(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: tag.as_bytes(),
+            },
+        )
+        .catch("DecryptionFailed", "wrong mailbox key or corrupted mail")?;
+    Ok(plain)
+}
+
+/// 两条曲线的标量域上投递所需的运算.
+pub(crate) trait ShareScalar:
+    Sized + Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+{
+    fn from_index(i: usize) -> Self;
+    fn random() -> Self;
+    fn inverse(&self) -> Option<Self>;
+    fn to_bytes32(&self) -> [u8; 32];
+    fn from_bytes32(bytes: &[u8; 32]) -> Option<Self>;
+    /// `self * G` 的压缩编码.
+    fn public_point(&self) -> Vec<u8>;
+    /// 数据的哈希模群阶.
+    fn hash_to_scalar(data: &[u8]) -> Self;
+    /// 检查 `self * G == R + c * P`, `R` 和 `P` 为压缩编码. 编码无效时为false.
+    fn check_schnorr(&self, c: &Self, r: &[u8], pk: &[u8]) -> bool;
+}
+
+impl ShareScalar for elgamal_secp256k1::Scalar {
+    fn from_index(i: usize) -> Self {
+        Self::from(i as u64)
+    }
+
+    fn random() -> Self {
+        <Self as Field>::random(&mut OsRng)
+    }
+
+    fn inverse(&self) -> Option<Self> {
+        self.invert().into()
+    }
+
+    fn to_bytes32(&self) -> [u8; 32] {
+        self.to_bytes().into()
+    }
+
+    fn from_bytes32(bytes: &[u8; 32]) -> Option<Self> {
+        Self::from_repr((*bytes).into()).into()
+    }
+
+    fn public_point(&self) -> Vec<u8> {
+        (elgamal_secp256k1::ProjectivePoint::GENERATOR * self)
+            .to33bytes()
+            .to_vec()
+    }
+
+    fn hash_to_scalar(data: &[u8]) -> Self {
+        <Self as Reduce<U256>>::reduce_bytes(&Sha256::digest(data))
+    }
+
+    fn check_schnorr(&self, c: &Self, r: &[u8], pk: &[u8]) -> bool {
+        let decode = |bytes: &[u8]| {
+            PublicKey::from_sec1_bytes(bytes)
+                .ok()
+                .map(|point| point.to_projective())
+        };
+        match (decode(r), decode(pk)) {
+            (Some(r), Some(pk)) => {
+                elgamal_secp256k1::ProjectivePoint::GENERATOR * self == r + pk * c
+            }
+            _ => false,
+        }
+    }
+}
+
+impl ShareScalar for curve25519_dalek::Scalar {
+    fn from_index(i: usize) -> Self {
+        Self::from(i as u64)
+    }
+
+    fn random() -> Self {
+        let mut wide = [0u8; 64];
+        OsRng.fill_bytes(&mut wide);
+        Self::from_bytes_mod_order_wide(&wide)
+    }
+
+    fn inverse(&self) -> Option<Self> {
+        (*self != Self::ZERO).then(|| self.invert())
+    }
+
+    fn to_bytes32(&self) -> [u8; 32] {
+        self.to_bytes()
+    }
+
+    fn from_bytes32(bytes: &[u8; 32]) -> Option<Self> {
+        Self::from_canonical_bytes(*bytes).into()
+    }
+
+    fn public_point(&self) -> Vec<u8> {
+        EdwardsPoint::mul_base(self).compress().to_bytes().to_vec()
+    }
+
+    fn hash_to_scalar(data: &[u8]) -> Self {
+        Self::from_bytes_mod_order_wide(&Sha512::digest(data).into())
+    }
+
+    fn check_schnorr(&self, c: &Self, r: &[u8], pk: &[u8]) -> bool {
+        let decode = |bytes: &[u8]| {
+            CompressedEdwardsY::from_slice(bytes)
+                .ok()
+                .and_then(|point| point.decompress())
+        };
+        match (decode(r), decode(pk)) {
+            (Some(r), Some(pk)) => EdwardsPoint::mul_base(self) == r + pk * c,
+            _ => false,
+        }
+    }
+}
+
+fn share_challenge<S: ShareScalar>(tag: &str, r: &[u8], pk: &[u8], msg: &[u8]) -> S {
+    let mut data = tag.as_bytes().to_vec();
+    data.extend_from_slice(r);
+    data.extend_from_slice(pk);
+    data.extend_from_slice(msg);
+    S::hash_to_scalar(&data)
+}
+
+/// 用分片 `x` 对 `msg` 作Schnorr签名 `R || s`, `s = k + H(tag || R || x * G || msg) * x`.
+pub(crate) fn sign_with_share<S: ShareScalar>(x: &S, tag: &str, msg: &[u8]) -> Vec<u8> {
+    let k = S::random();
+    let mut sig = k.public_point();
+    let c = share_challenge::<S>(tag, &sig, &x.public_point(), msg);
+    sig.extend_from_slice(&(k + c * *x).to_bytes32());
+    sig
+}
+
+/// 用成员的公开分片 `pk` (见 `MpcKeystore::public_share_of`) 验证 `sign_with_share` 的签名.
+pub(crate) fn verify_share_sig<S: ShareScalar>(
+    pk: &[u8],
+    tag: &str,
+    msg: &[u8],
+    sig: &[u8],
+) -> bool {
+    if sig.len() <= 32 {
+        return false;
+    }
+    let (r, s) = sig.split_at(sig.len() - 32);
+    match to_scalar::<S>(s) {
+        Ok(s) => s.check_schnorr(&share_challenge::<S>(tag, r, pk, msg), r, pk),
+        Err(_) => false,
+    }
+}
+
+/// 可以向缺席成员投递分片的Keystore.
+pub(crate) trait Deliverable: MpcKeystore {
+    type Share: ShareScalar;
+
+    fn share_scalar(&self) -> Self::Share;
+
+    /// VSS承诺的压缩编码.
+    fn vss_bytes(&self) -> BTreeMap<usize, Vec<Vec<u8>>>;
+
+    /// 算法特有的公开数据. ElGamal为经serde_pickle序列化的 `paillier_n_dict`.
+    fn public_extra(&self) -> Resultat<Vec<u8>>;
+}
+
+impl Deliverable for KeystoreElgamal {
+    type Share = elgamal_secp256k1::Scalar;
+
+    fn share_scalar(&self) -> Self::Share {
+        self.xi
+    }
+
+    fn vss_bytes(&self) -> BTreeMap<usize, Vec<Vec<u8>>> {
+        self.vss_scheme
+            .iter()
+            .map(|(&j, coms)| (j, coms.iter().map(|c| c.to33bytes().to_vec()).collect()))
+            .collect()
+    }
+
+    fn public_extra(&self) -> Resultat<Vec<u8>> {
+        serde_pickle::to_vec(&self.paillier_n_dict, Default::default()).catch_()
+    }
+}
+
+impl Deliverable for KeystoreSchnorr {
+    type Share = curve25519_dalek::Scalar;
+
+    fn share_scalar(&self) -> Self::Share {
+        self.xi
+    }
+
+    fn vss_bytes(&self) -> BTreeMap<usize, Vec<Vec<u8>>> {
+        self.vss_scheme
+            .iter()
+            .map(|(&j, coms)| {
+                (
+                    j,
+                    coms.iter()
+                        .map(|c| c.compress().to_bytes().to_vec())
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn public_extra(&self) -> Resultat<Vec<u8>> {
+        Ok(Vec::new())
+    }
+}
+
+/// 缺席成员的Keystore中除分片以外的部分. 所有helper给出的模板必须相同.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ShareTemplate {
+    pub algorithm: String,
+    pub i: usize,
+    pub threshold: usize,
+    pub players: BTreeMap<String, usize>,
+    pub pending: Vec<String>,
+    pub allowed_paths: Vec<String>,
+    pub epoch: u64,
+    pub refreshed_at: u64,
+    pub chain_code: [u8; 32],
+    pub vss_scheme: BTreeMap<usize, Vec<Vec<u8>>>,
+    pub extra: Vec<u8>,
+}
+
+impl ShareTemplate {
+    pub(crate) fn keystore<K>(self, share: K) -> Keystore<K> {
+        Keystore {
+            share,
+            threshold: self.threshold,
+            players: self.players,
+            allowed_paths: self.allowed_paths,
+            epoch: self.epoch,
+            refreshed_at: self.refreshed_at,
+            pending: self.pending,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct ShareDelivery {
+    from: usize,
+    helpers: BTreeSet<usize>,
+    piece: [u8; 32],
+    template: ShareTemplate,
+}
+
+fn mailbox_tag(session_id: &str, recipient: &str) -> String {
+    format!("svarog-mailbox/{}/{}", session_id, recipient)
+}
+
+fn deliver_key_tag(session_id: &str, src: usize) -> String {
+    format!("svarog-deliver-key/{}/{}", session_id, src)
+}
+
+fn piece_tag(session_id: &str, a: usize, src: usize, dst: usize) -> String {
+    format!("svarog-deliver/{}/{}/{}/{}", session_id, a, src, dst)
+}
+
+/// 缺席的consumer, 名称到序号. 检查缺席人数, 出席人数和邮箱公钥.
+pub(crate) fn absent_consumers(cfg: &SessionConfig) -> Resultat<BTreeMap<String, usize>> {
+    let members = ses_members(&cfg.players_reshared);
+    let absent: BTreeMap<String, usize> = members
+        .into_iter()
+        .filter(|(name, _)| cfg.players_reshared.get(name) == Some(&false))
+        .collect();
+    let present = cfg.players_reshared.len() - absent.len();
+    let names: Vec<&str> = absent.keys().map(|name| name.as_str()).collect();
+    assert_throw!(
+        absent.len() as u64 <= cfg.max_absent_consumers,
+        format!(
+            "{} consumers absent ({}), at most {} allowed",
+            absent.len(),
+            names.join(", "),
+            cfg.max_absent_consumers
+        )
+    );
+    assert_throw!(
+        present >= cfg.threshold as usize,
+        format!(
+            "{} consumers present, threshold is {}",
+            present, cfg.threshold
+        )
+    );
+    for name in absent.keys() {
+        let pk = cfg
+            .mailbox_pks
+            .get(name)
+            .ifnone("MailboxKeyMissing", format!("absent consumer {}", name))?;
+        assert_throw!(
+            pk.len() == 32,
+            format!("mailbox key of {} should be 32 bytes", name)
+        );
+    }
+    Ok(absent)
+}
+
+/// `helpers` 上在 `a` 处插值时 `i` 的Lagrange系数.
+pub(crate) fn lagrange<S: ShareScalar>(
+    i: usize,
+    a: usize,
+    helpers: &BTreeSet<usize>,
+) -> Resultat<S> {
+    let mut num = S::from_index(1);
+    let mut den = S::from_index(1);
+    for &j in helpers.iter().filter(|&&j| j != i) {
+        num = num * (S::from_index(a) - S::from_index(j));
+        den = den * (S::from_index(i) - S::from_index(j));
+    }
+    let den = den
+        .inverse()
+        .ifnone("InvalidIndex", "duplicate helper index")?;
+    Ok(num * den)
+}
+
+/// 把 `v` 拆成 `n` 个随机的加法分量.
+fn split<S: ShareScalar>(v: S, n: usize) -> Vec<S> {
+    let mut pieces: Vec<S> = (1..n).map(|_| S::random()).collect();
+    let rest = pieces.iter().fold(v, |acc, &r| acc - r);
+    pieces.push(rest);
+    pieces
+}
+
+pub(crate) fn to_scalar<S: ShareScalar>(bytes: &[u8]) -> Resultat<S> {
+    let bytes: [u8; 32] = bytes.try_into().catch("InvalidScalar", "")?;
+    S::from_bytes32(&bytes).ifnone("InvalidScalar", "")
+}
+
+/// Reshare结束后由每个出席的consumer调用, 为 `keystore.pending` 中的成员投递分片.
+pub(crate) async fn deliver_pending<K: Deliverable>(
+    chan: &mut SvarogChannel,
+    cfg: &SessionConfig,
+    member_name: &str,
+    keystore: &Keystore<K>,
+) -> Resultat<()> {
+    if keystore.pending.is_empty() {
+        return Ok(());
+    }
+    let i = keystore.party_index();
+    let helpers: BTreeSet<usize> = keystore
+        .players
+        .iter()
+        .filter(|(name, _)| !keystore.pending.contains(name))
+        .map(|(_, &j)| j)
+        .collect();
+    assert_throw!(helpers.contains(&i), "helper not among present consumers");
+    let others: Vec<usize> = helpers.iter().copied().filter(|&j| j != i).collect();
+    let sid = chan.sid().to_owned();
+    let parties: BTreeMap<usize, Vec<String>> = keystore
+        .players
+        .iter()
+        .map(|(name, &k)| (k, vec![name.clone()]))
+        .collect();
+
+    // 第一轮: 交换临时公钥, 用分片签名.
+    let share = keystore.share.share_scalar();
+    let ephemeral_sk = generate_mailbox_key();
+    let ephemeral_pk = mailbox_pk(&ephemeral_sk).to_vec();
+    let key_sig = sign_with_share(&share, &deliver_key_tag(&sid, i), &ephemeral_pk);
+    let key_msg = (ephemeral_pk, key_sig);
+    for &j in others.iter() {
+        chan.register_send(DELIVER_KEY_TOPIC, i, j, 0, &key_msg)
+            .catch_()?;
+    }
+    chan.execute_send().await.catch_()?;
+    for &j in others.iter() {
+        chan.register_receive(DELIVER_KEY_TOPIC, j, i, 0).catch_()?;
+    }
+    chan.execute_receive().await.catch_()?;
+    let mut helper_pks: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+    let mut forged = BTreeSet::new();
+    for &j in others.iter() {
+        let (pk, sig): (Vec<u8>, Vec<u8>) =
+            chan.unpack_receive(DELIVER_KEY_TOPIC, j, i, 0).catch_()?;
+        let share_pk = keystore.share.public_share_of(j);
+        if !verify_share_sig::<K::Share>(&share_pk, &deliver_key_tag(&sid, j), &pk, &sig) {
+            forged.insert(j);
+        }
+        helper_pks.insert(j, pk);
+    }
+    chan.clear();
+    if !forged.is_empty() {
+        let reason = "ephemeral key not signed by the share";
+        return Abort::new(&sid, &forged, &parties, reason).throw();
+    }
+
+    // 第二轮: 交换加法分量.
+    let mut own_pieces: BTreeMap<usize, K::Share> = BTreeMap::new();
+    for name in keystore.pending.iter() {
+        let a = *keystore
+            .players
+            .get(name)
+            .ifnone("PlayerUnknown", format!("pending player {}", name))?;
+        let v = lagrange::<K::Share>(i, a, &helpers).catch_()? * share;
+        let mut pieces = split(v, helpers.len());
+        own_pieces.insert(a, pieces.pop().ifnone_()?);
+        for (&j, piece) in others.iter().zip(pieces.iter()) {
+            let sealed = seal(
+                &helper_pks[&j],
+                &piece_tag(&sid, a, i, j),
+                &piece.to_bytes32(),
+            )
+            .catch_()?;
+            chan.register_send(DELIVER_PIECE_TOPIC, i, j, a, &sealed)
+                .catch_()?;
+        }
+    }
+    chan.execute_send().await.catch_()?;
+    for &a in own_pieces.keys() {
+        for &j in others.iter() {
+            chan.register_receive(DELIVER_PIECE_TOPIC, j, i, a)
+                .catch_()?;
+        }
+    }
+    chan.execute_receive().await.catch_()?;
+    let mut sums: BTreeMap<usize, K::Share> = BTreeMap::new();
+    for (&a, &own) in own_pieces.iter() {
+        let mut sum = own;
+        for &j in others.iter() {
+            let sealed: Vec<u8> = chan.unpack_receive(DELIVER_PIECE_TOPIC, j, i, a).catch_()?;
+            let piece = open(&ephemeral_sk, &piece_tag(&sid, a, j, i), &sealed)
+                .and_then(|plain| to_scalar::<K::Share>(&plain));
+            let piece = match piece {
+                Ok(piece) => piece,
+                Err(_) => {
+                    let reason = format!("invalid share piece for party {}", a);
+                    return Abort::new(&sid, &[j].into(), &parties, &reason).throw();
+                }
+            };
+            sum = sum + piece;
+        }
+        sums.insert(a, sum);
+    }
+    chan.clear();
+
+    // 投递到缺席成员的邮箱.
+    for name in keystore.pending.iter() {
+        let a = keystore.players[name];
+        let template = ShareTemplate {
+            algorithm: K::ALGO.to_owned(),
+            i: a,
+            threshold: keystore.threshold,
+            players: keystore.players.clone(),
+            pending: keystore.pending.clone(),
+            allowed_paths: keystore.allowed_paths.clone(),
+            epoch: keystore.epoch,
+            refreshed_at: keystore.refreshed_at,
+            chain_code: keystore.chain_code(),
+            vss_scheme: keystore.share.vss_bytes(),
+            extra: keystore.share.public_extra().catch_()?,
+        };
+        let delivery = ShareDelivery {
+            from: i,
+            helpers: helpers.clone(),
+            piece: sums[&a].to_bytes32(),
+            template,
+        };
+        let pk = cfg
+            .mailbox_pks
+            .get(name)
+            .ifnone("MailboxKeyMissing", format!("absent consumer {}", name))?;
+        let plain = serde_json::to_vec(&delivery).catch_()?;
+        let sealed = seal(pk, &mailbox_tag(&sid, name), &plain).catch_()?;
+        chan.put_mail(name, member_name, sealed).await.catch_()?;
+    }
+    Ok(())
+}
+
+/// 取回并核对全部投递, 返回模板和相加得到的分片.
+pub(crate) async fn collect_delivery<S: ShareScalar>(
+    sesman_url: &str,
+    session_id: &str,
+    member_name: &str,
+    mailbox_sk: &[u8; 32],
+) -> Resultat<(ShareTemplate, S)> {
+    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
+    let https = sesman_url.starts_with("https://");
+    let mails = SvarogChannel::get_mail(session_id, sesman_url, https, member_name)
+        .await
+        .catch_()?;
+    assert_throw!(!mails.is_empty(), "no share delivered to this member yet");
+
+    let tag = mailbox_tag(session_id, member_name);
+    let mut deliveries: BTreeMap<usize, ShareDelivery> = BTreeMap::new();
+    for mail in mails.iter() {
+        let plain = open(mailbox_sk, &tag, &mail.payload)
+            .catch("InvalidMail", format!("from {}", &mail.sender))?;
+        let delivery: ShareDelivery = serde_json::from_slice(&plain).catch_()?;
+        assert_throw!(
+            delivery.template.players.get(&mail.sender) == Some(&delivery.from),
+            format!("mail from {} claims party {}", &mail.sender, delivery.from)
+        );
+        deliveries.insert(delivery.from, delivery);
+    }
+    // 以多数helper给出的模板为准, 与之不同的helper被归责.
+    let mut groups: Vec<(&ShareTemplate, &BTreeSet<usize>, BTreeSet<usize>)> = Vec::new();
+    for (&j, delivery) in deliveries.iter() {
+        match groups
+            .iter_mut()
+            .find(|(t, h, _)| **t == delivery.template && **h == delivery.helpers)
+        {
+            Some((_, _, senders)) => {
+                senders.insert(j);
+            }
+            None => groups.push((&delivery.template, &delivery.helpers, [j].into())),
+        }
+    }
+    groups.sort_by_key(|(_, _, senders)| std::cmp::Reverse(senders.len()));
+    let (template, helpers, _) = groups.first().ifnone_()?;
+    let (template, helpers) = ((*template).clone(), (*helpers).clone());
+    if groups.len() > 1 {
+        let culprits: BTreeSet<usize> = groups[1..]
+            .iter()
+            .flat_map(|(_, _, senders)| senders.iter().copied())
+            .collect();
+        let parties = template
+            .players
+            .iter()
+            .map(|(name, &k)| (k, vec![name.clone()]))
+            .collect();
+        return Abort::new(
+            session_id,
+            &culprits,
+            &parties,
+            "inconsistent share delivery",
+        )
+        .throw();
+    }
+    let received: BTreeSet<usize> = deliveries.keys().copied().collect();
+    let missing: Vec<usize> = helpers.difference(&received).copied().collect();
+    assert_throw!(
+        missing.is_empty(),
+        format!("waiting for deliveries from parties {:?}", missing)
+    );
+    assert_throw!(
+        template.players.get(member_name) == Some(&template.i),
+        "the delivery is for another member"
+    );
+    assert_throw!(
+        template.pending.iter().any(|name| name == member_name),
+        "this member was not absent in the reshare"
+    );
+
+    let mut share = S::from_index(0);
+    for delivery in deliveries.values() {
+        share = share + to_scalar::<S>(&delivery.piece).catch_()?;
+    }
+    Ok((template, share))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn check_delivery<S: ShareScalar + PartialEq>() -> Resultat<()> {
+        // f(x) = c0 + c1 * x + c2 * x^2, 门限3.
+        let coefs: Vec<S> = (0..3).map(|_| S::random()).collect();
+        let eval = |x: usize| {
+            coefs
+                .iter()
+                .rev()
+                .fold(S::from_index(0), |acc, &c| acc * S::from_index(x) + c)
+        };
+        let helpers: BTreeSet<usize> = [1, 3, 4, 6].into();
+        let a = 5;
+        let mut sums: BTreeMap<usize, S> = helpers.iter().map(|&j| (j, S::from_index(0))).collect();
+        for &i in helpers.iter() {
+            let v = lagrange::<S>(i, a, &helpers).catch_()? * eval(i);
+            for (&j, piece) in helpers.iter().zip(split(v, helpers.len())) {
+                let sum = sums.get_mut(&j).ifnone_()?;
+                *sum = *sum + piece;
+            }
+        }
+        let share = sums.values().fold(S::from_index(0), |acc, &w| acc + w);
+        assert_throw!(share == eval(a));
+        assert_throw!(S::from_bytes32(&share.to_bytes32()) == Some(share));
+        Ok(())
+    }
+
+    #[test]
+    fn test_delivery_math() -> Resultat<()> {
+        check_delivery::<elgamal_secp256k1::Scalar>().catch_()?;
+        check_delivery::<curve25519_dalek::Scalar>().catch_()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_seal_open() -> Resultat<()> {
+        let sk = generate_mailbox_key();
+        let pk = mailbox_pk(&sk);
+        let sealed = seal(&pk, "ses/Alice", b"share").catch_()?;
+        assert_throw!(open(&sk, "ses/Alice", &sealed).catch_()? == b"share");
+        assert_throw!(open(&sk, "ses/Bob", &sealed).is_err());
+        assert_throw!(open(&generate_mailbox_key(), "ses/Alice", &sealed).is_err());
+        Ok(())
+    }
+
+    fn check_share_sig<S: ShareScalar>() -> Resultat<()> {
+        let x = S::random();
+        let pk = x.public_point();
+        let sig = sign_with_share(&x, "ses/1", b"ephemeral");
+        assert_throw!(verify_share_sig::<S>(&pk, "ses/1", b"ephemeral", &sig));
+        assert_throw!(!verify_share_sig::<S>(&pk, "ses/2", b"ephemeral", &sig));
+        assert_throw!(!verify_share_sig::<S>(&pk, "ses/1", b"forged", &sig));
+        let other = S::random().public_point();
+        assert_throw!(!verify_share_sig::<S>(&other, "ses/1", b"ephemeral", &sig));
+        assert_throw!(!verify_share_sig::<S>(
+            &pk,
+            "ses/1",
+            b"ephemeral",
+            &sig[1..]
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_share_sig() -> Resultat<()> {
+        check_share_sig::<elgamal_secp256k1::Scalar>().catch_()?;
+        check_share_sig::<curve25519_dalek::Scalar>().catch_()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_absent_consumers() -> Resultat<()> {
+        let mut cfg = SessionConfig {
+            threshold: 2,
+            players_reshared: HashMap::from([
+                ("Alice".to_owned(), true),
+                ("Bob".to_owned(), false),
+                ("Charlie".to_owned(), true),
+            ]),
+            ..Default::default()
+        };
+        assert_throw!(absent_consumers(&cfg).is_err());
+        cfg.max_absent_consumers = 1;
+        assert_throw!(absent_consumers(&cfg).is_err());
+        cfg.mailbox_pks.insert(
+            "Bob".to_owned(),
+            mailbox_pk(&generate_mailbox_key()).to_vec(),
+        );
+        let absent = absent_consumers(&cfg).catch_()?;
+        assert_throw!(absent == BTreeMap::from([("Bob".to_owned(), 2)]));
+        cfg.threshold = 3;
+        assert_throw!(absent_consumers(&cfg).is_err());
+        Ok(())
+    }
+}
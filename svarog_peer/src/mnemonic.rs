@@ -0,0 +1,125 @@
+//! BIP39助记词的校验. `keygen_mnem` 在连接会话之前调用, 输错一个词会立即报错, 而不是事后得到错误的地址.
+//!
+//! 支持BIP39的全部语言, 语言按词表自动识别. 每个词都必须在词表中, 校验和必须正确. 助记词和密码
+//! 规范化为NFKD, 词之间用单个空格分隔.
+//!
+//! `Mnemonics` 在drop时清零. 交给 `svarog_algo` 的副本由它管理, 本crate无法清零.
+use std::borrow::Cow;
+
+use bip39::Mnemonic;
+use erreur::*;
+use hmac::{Hmac, Mac};
+use k256::elliptic_curve::PrimeField;
+use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+use svarog_algo::elgamal_secp256k1::{ProjectivePoint, Scalar};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+    bip32::{ExtendedPk, XPUB_MAINNET, XPUB_TESTNET},
+    structs::Mnemonics,
+};
+
+/// 助记词对应的BIP32根节点, 供provider与现有钱包比对.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ExpectedRoot {
+    /// 压缩公钥的hex, 与 `Keystore::group_pk` 相同.
+    pub group_pk: String,
+    pub chain_code: String,
+    pub xpub: String,
+}
+
+/// 校验助记词, 返回规范化之后的副本.
+pub fn normalize_mnemonics(mnem: &Mnemonics) -> Resultat<Mnemonics> {
+    let mut mnemonic = Mnemonic::parse(mnem.phrases.as_str()).catch("InvalidMnemonic", "")?;
+    let phrases = Zeroizing::new(mnemonic.to_string());
+    mnemonic.zeroize();
+    Ok(Mnemonics {
+        phrases: nfkd(&phrases),
+        password: nfkd(&mnem.password),
+    })
+}
+
+/// NFKD规范化. 返回值是唯一的新副本: 已是NFKD时复制一次, 否则直接取走规范化的结果.
+fn nfkd(text: &str) -> String {
+    let mut text = Cow::Borrowed(text);
+    Mnemonic::normalize_utf8_cow(&mut text);
+    text.into_owned()
+}
+
+/// 计算助记词对应的secp256k1根节点 (BIP32主密钥), 即 `keygen_mnem` 之后应得的群公钥和链码.
+/// 仅ElGamal: ed25519没有统一的助记词派生标准.
+pub fn expected_root(mnem: &Mnemonics, testnet: bool) -> Resultat<ExpectedRoot> {
+    let mnem = normalize_mnemonics(mnem).catch_()?;
+    let mut mnemonic = Mnemonic::parse_normalized(mnem.phrases.as_str()).catch_()?;
+    let seed = Zeroizing::new(mnemonic.to_seed_normalized(&mnem.password));
+    mnemonic.zeroize();
+
+    let mut mac = Hmac::<Sha512>::new_from_slice(b"Bitcoin seed").catch_()?;
+    mac.update(&seed[..]);
+    let mut i = Zeroizing::new([0u8; 64]);
+    i.copy_from_slice(&mac.finalize().into_bytes());
+    let mut il = Zeroizing::new([0u8; 32]);
+    il.copy_from_slice(&i[..32]);
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(&i[32..]);
+    let x: Option<Scalar> = Scalar::from_repr((*il).into()).into();
+    let x = x.ifnone("InvalidMnemonic", "master key is out of range")?;
+    let pk = ProjectivePoint::GENERATOR * x;
+    assert_throw!(pk != ProjectivePoint::IDENTITY, "master key is zero");
+
+    let root = ExtendedPk::root(pk, chain_code);
+    Ok(ExpectedRoot {
+        group_pk: hex::encode(pk.to33bytes()),
+        chain_code: hex::encode(chain_code),
+        xpub: root.to_xpub(if testnet { XPUB_TESTNET } else { XPUB_MAINNET }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mnem(phrases: &str) -> Mnemonics {
+        Mnemonics {
+            phrases: phrases.to_owned(),
+            password: "".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_normalize_mnemonics() -> Resultat<()> {
+        let valid = "park remain person kitchen mule spell knee armed position rail grid ankle";
+        let spaced = "park  remain person kitchen mule spell knee armed position rail grid\tankle";
+        assert_throw!(normalize_mnemonics(&mnem(spaced))?.phrases == valid);
+
+        let typo = "park remain person kitchen mule spell knee armed positoin rail grid ankle";
+        assert_throw!(normalize_mnemonics(&mnem(typo)).is_err());
+        let checksum = ["abandon"; 12].join(" ");
+        assert_throw!(normalize_mnemonics(&mnem(&checksum)).is_err());
+        let short = "park remain person kitchen mule spell knee armed position rail grid";
+        assert_throw!(normalize_mnemonics(&mnem(short)).is_err());
+
+        // "cafe\u{301}" 是 "café" 的NFKD形式.
+        let password = Mnemonics {
+            phrases: valid.to_owned(),
+            password: "caf\u{e9}".to_owned(),
+        };
+        assert_throw!(normalize_mnemonics(&password)?.password == "cafe\u{301}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_expected_root() -> Resultat<()> {
+        let abandon = format!("{} about", ["abandon"; 11].join(" "));
+        let root = expected_root(&mnem(&abandon), false)?;
+        assert_throw!(
+            root.group_pk == "03d902f35f560e0470c63313c7369168d9d7df2d49bf295fd9fb7cb109ccee0494"
+        );
+        assert_throw!(
+            root.xpub
+                == "xpub661MyMwAqRbcFkPHucMnrGNzDwb6teAX1RbKQmqtEF8kK3Z7LZ59qafCjB9eCRLiTVG3uxBxgKvRgbubRhqSKXnGGb1aoaqLrpMBDrVxga8"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! 本地生成Paillier密钥. Reshare时缺席的ElGamal成员取回分片后用它补齐Keystore,
+//! 分片本身不进入任何网络协议.
+//!
+//! `N = p * q`, `p` 和 `q` 是1024位的随机素数, 模4余3, 最高两位为1, 因此 `N` 恰好2048位.
+//! 素性先用小素数试除, 再做Miller-Rabin检验.
+use erreur::*;
+use rand::{rngs::OsRng, RngCore};
+use svarog_algo::{
+    elgamal_secp256k1::PaillierKey2048,
+    num_bigint::{BigInt, Sign},
+};
+
+const PRIME_BITS: usize = 1024;
+const MILLER_RABIN_ROUNDS: usize = 40;
+const TRIAL_DIVISION_BOUND: u32 = 2000;
+
+/// 新的Paillier密钥及其模数 `N`.
+pub(crate) fn generate_paillier_key() -> Resultat<(PaillierKey2048, BigInt)> {
+    let p = random_prime(PRIME_BITS);
+    let mut q = random_prime(PRIME_BITS);
+    while q == p {
+        q = random_prime(PRIME_BITS);
+    }
+    let n = &p * &q;
+    let key = PaillierKey2048::import(p, q).catch_()?;
+    Ok((key, n))
+}
+
+fn random_bits(bits: usize) -> BigInt {
+    let mut bytes = vec![0u8; bits.div_ceil(8)];
+    OsRng.fill_bytes(&mut bytes);
+    bytes[0] &= 0xff >> (bytes.len() * 8 - bits);
+    BigInt::from_bytes_be(Sign::Plus, &bytes)
+}
+
+/// `bits` 位的随机素数, 最高两位为1, 模4余3.
+fn random_prime(bits: usize) -> BigInt {
+    let mask = (BigInt::from(3u8) << (bits - 2)) | BigInt::from(3u8);
+    loop {
+        let candidate = random_bits(bits) | &mask;
+        if is_probable_prime(&candidate, MILLER_RABIN_ROUNDS) {
+            return candidate;
+        }
+    }
+}
+
+fn small_primes() -> impl Iterator<Item = u32> {
+    (2..TRIAL_DIVISION_BOUND).filter(|&k| (2..).take_while(|d| d * d <= k).all(|d| k % d != 0))
+}
+
+fn is_probable_prime(n: &BigInt, rounds: usize) -> bool {
+    let zero = BigInt::from(0u8);
+    let one = BigInt::from(1u8);
+    let two = BigInt::from(2u8);
+    if n < &two {
+        return false;
+    }
+    for p in small_primes() {
+        let p = BigInt::from(p);
+        if n == &p {
+            return true;
+        }
+        if n % &p == zero {
+            return false;
+        }
+    }
+
+    // n - 1 = d * 2^s, d为奇数.
+    let n1 = n - &one;
+    let s = n1.trailing_zeros().unwrap_or(0);
+    let d = &n1 >> s;
+    let range = n - BigInt::from(3u8);
+    'witness: for _ in 0..rounds {
+        // 随机底数 a 取自 [2, n - 2].
+        let a = random_bits(n.bits() as usize + 64) % &range + &two;
+        let mut x = a.modpow(&d, n);
+        if x == one || x == n1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = x.modpow(&two, n);
+            if x == n1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_probable_prime() -> Resultat<()> {
+        let mersenne = (BigInt::from(1u8) << 127usize) - BigInt::from(1u8);
+        assert_throw!(is_probable_prime(&mersenne, 20));
+        assert_throw!(is_probable_prime(&BigInt::from(1999u32), 20));
+        // Carmichael数和两个大素数之积.
+        assert_throw!(!is_probable_prime(&BigInt::from(561u32), 20));
+        assert_throw!(!is_probable_prime(
+            &(&mersenne * BigInt::from(2147483647u32)),
+            20
+        ));
+        assert_throw!(!is_probable_prime(&BigInt::from(1u8), 20));
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_prime() -> Resultat<()> {
+        let p = random_prime(256);
+        assert_throw!(p.bits() == 256);
+        assert_throw!(&p % BigInt::from(4u8) == BigInt::from(3u8));
+        assert_throw!(p >> 254usize == BigInt::from(3u8));
+        assert_throw!(is_probable_prime(&random_prime(256), 20));
+        Ok(())
+    }
+}
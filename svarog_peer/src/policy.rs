@@ -0,0 +1,351 @@
+//! 签名策略. 每个参与方在Sign开始协议之前, 用本地安装的策略评估签名请求; 任何一条规则不满足时拒绝加入.
+//!
+//! 策略以JSON声明, 例如
+//! ```json
+//! {"rules": [
+//!     {"rule": "path_prefixes", "prefixes": ["m/44/60"]},
+//!     {"rule": "max_tasks", "max": 10},
+//!     {"rule": "rate_limit", "max_signs": 100, "window_secs": 3600},
+//!     {"rule": "required_cosigners", "names": ["Alice"]},
+//!     {"rule": "time_window", "start": "09:00", "end": "18:00", "utc_offset_minutes": 480}
+//! ]}
+//! ```
+//! 速率限制按Keystore计数, 每次成功的Sign计为一次; 进行中的Sign在评估时也算在内,
+//! 以免并发的Sign一起越过限制. 计数只保存在本进程的内存中, 进程重启后清零.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use erreur::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{bip32::parse_path, sign_task::check_path_allowed};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum Rule {
+    /// 每个任务的bip32路径都必须落在某个前缀之下.
+    PathPrefixes { prefixes: Vec<String> },
+    /// 一次Sign最多的任务数.
+    MaxTasks { max: usize },
+    /// 每个Keystore在 `window_secs` 秒内最多参与 `max_signs` 次Sign.
+    RateLimit { max_signs: usize, window_secs: u64 },
+    /// 这些成员必须出席.
+    RequiredCosigners { names: Vec<String> },
+    /// 每天允许签名的时段 `[start, end)`, 格式为 `HH:MM`. `start > end` 表示跨越午夜.
+    TimeWindow {
+        start: String,
+        end: String,
+        #[serde(default)]
+        utc_offset_minutes: i32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct Policy {
+    pub rules: Vec<Rule>,
+}
+
+/// 待评估的签名请求.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignRequest {
+    pub key_id: String,
+    /// 出席本场会话的成员名称.
+    pub cosigners: BTreeSet<String>,
+    pub bip32_paths: Vec<String>,
+}
+
+static POLICY: RwLock<Option<Policy>> = RwLock::new(None);
+static HISTORY: Mutex<BTreeMap<String, KeyHistory>> = Mutex::new(BTreeMap::new());
+
+/// 一个Keystore的签名记录.
+#[derive(Default)]
+struct KeyHistory {
+    /// 成功的Sign的时刻.
+    signs: Vec<u64>,
+    /// 通过评估, 尚未结束的Sign数.
+    in_flight: usize,
+}
+
+/// 通过评估, 尚未结束的Sign. 成功后调用 `commit` 计入速率限制; 未提交就丢弃时不计入.
+pub(crate) struct PendingSign {
+    key_id: String,
+}
+
+/// 安装本进程的签名策略, 替换已有的策略. `None` 表示不限制.
+pub fn install_policy(policy: Option<Policy>) -> Resultat<()> {
+    if let Some(policy) = &policy {
+        policy.validate().catch_()?;
+    }
+    *POLICY.write().ok().ifnone("PoisonedLock", "policy")? = policy;
+    Ok(())
+}
+
+pub fn installed_policy() -> Option<Policy> {
+    POLICY.read().ok().and_then(|policy| policy.clone())
+}
+
+fn parse_hhmm(s: &str) -> Resultat<u32> {
+    let (h, m) = s
+        .split_once(':')
+        .ifnone("InvalidPolicy", format!("time {} is not HH:MM", s))?;
+    let h: u32 = h
+        .parse()
+        .catch("InvalidPolicy", format!("bad hour in {}", s))?;
+    let m: u32 = m
+        .parse()
+        .catch("InvalidPolicy", format!("bad minute in {}", s))?;
+    assert_throw!(h < 24 && m < 60, format!("time {} is out of range", s));
+    Ok(h * 60 + m)
+}
+
+impl Policy {
+    pub fn from_json(json: &str) -> Resultat<Self> {
+        let policy: Self = serde_json::from_str(json).catch("InvalidPolicy", "")?;
+        policy.validate().catch_()?;
+        Ok(policy)
+    }
+
+    pub fn validate(&self) -> Resultat<()> {
+        for rule in self.rules.iter() {
+            match rule {
+                Rule::PathPrefixes { prefixes } => {
+                    for prefix in prefixes {
+                        parse_path(prefix).catch("InvalidPolicy", "")?;
+                    }
+                }
+                Rule::RateLimit { window_secs, .. } => {
+                    assert_throw!(*window_secs > 0, "rate limit window should be positive");
+                }
+                Rule::TimeWindow { start, end, .. } => {
+                    parse_hhmm(start).catch_()?;
+                    parse_hhmm(end).catch_()?;
+                }
+                Rule::MaxTasks { .. } | Rule::RequiredCosigners { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// 评估签名请求. `now` 是Unix秒; `history` 是该Keystore此前通过评估的时刻.
+    /// 错误信息指出违反的规则.
+    pub fn check(&self, req: &SignRequest, now: u64, history: &[u64]) -> Resultat<()> {
+        for rule in self.rules.iter() {
+            match rule {
+                Rule::PathPrefixes { prefixes } => {
+                    for path in req.bip32_paths.iter() {
+                        let parsed = parse_path(path).catch_()?;
+                        check_path_allowed(prefixes, &parsed)
+                            .catch("", format!("rule path_prefixes: {}", path))?;
+                    }
+                }
+                Rule::MaxTasks { max } => {
+                    assert_throw!(
+                        req.bip32_paths.len() <= *max,
+                        format!(
+                            "rule max_tasks: batch has {} tasks, limit is {}",
+                            req.bip32_paths.len(),
+                            max
+                        )
+                    );
+                }
+                Rule::RateLimit {
+                    max_signs,
+                    window_secs,
+                } => {
+                    let since = now.saturating_sub(*window_secs);
+                    let n = history.iter().filter(|&&t| t > since).count();
+                    assert_throw!(
+                        n < *max_signs,
+                        format!(
+                            "rule rate_limit: {} signs in the last {} seconds, limit is {}",
+                            n, window_secs, max_signs
+                        )
+                    );
+                }
+                Rule::RequiredCosigners { names } => {
+                    let missing: Vec<&str> = names
+                        .iter()
+                        .filter(|name| !req.cosigners.contains(*name))
+                        .map(|name| name.as_str())
+                        .collect();
+                    assert_throw!(
+                        missing.is_empty(),
+                        format!("rule required_cosigners: {} absent", missing.join(", "))
+                    );
+                }
+                Rule::TimeWindow {
+                    start,
+                    end,
+                    utc_offset_minutes,
+                } => {
+                    let start = parse_hhmm(start).catch_()?;
+                    let end = parse_hhmm(end).catch_()?;
+                    let local = now as i64 + *utc_offset_minutes as i64 * 60;
+                    let minute = (local.rem_euclid(86400) / 60) as u32;
+                    let inside = if start <= end {
+                        start <= minute && minute < end
+                    } else {
+                        minute >= start || minute < end
+                    };
+                    assert_throw!(
+                        inside,
+                        format!(
+                            "rule time_window: {:02}:{:02} is outside the window",
+                            minute / 60,
+                            minute % 60
+                        )
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn now_secs() -> Resultat<u64> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .catch_()?
+        .as_secs();
+    Ok(now)
+}
+
+/// 用已安装的策略评估请求. 进行中的Sign按现在的时刻计入速率限制.
+/// 未安装策略时返回 `None`.
+pub(crate) fn enforce(req: &SignRequest) -> Resultat<Option<PendingSign>> {
+    let policy = match installed_policy() {
+        Some(policy) => policy,
+        None => return Ok(None),
+    };
+    let now = now_secs().catch_()?;
+    let mut history = HISTORY.lock().ok().ifnone("PoisonedLock", "history")?;
+    let entry = history.entry(req.key_id.clone()).or_default();
+    let mut counted = entry.signs.clone();
+    counted.extend(std::iter::repeat_n(now, entry.in_flight));
+    policy
+        .check(req, now, &counted)
+        .catch("PolicyViolation", format!("key {}", &req.key_id))?;
+    entry.in_flight += 1;
+    Ok(Some(PendingSign {
+        key_id: req.key_id.clone(),
+    }))
+}
+
+impl PendingSign {
+    /// Sign成功后调用, 把本次Sign计入该Keystore的速率限制.
+    pub(crate) fn commit(self) -> Resultat<()> {
+        let now = now_secs().catch_()?;
+        let max_window = installed_policy()
+            .unwrap_or_default()
+            .rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::RateLimit { window_secs, .. } => Some(*window_secs),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let mut history = HISTORY.lock().ok().ifnone("PoisonedLock", "history")?;
+        let entry = history.entry(self.key_id.clone()).or_default();
+        entry.signs.retain(|&t| t + max_window > now);
+        entry.signs.push(now);
+        Ok(())
+    }
+}
+
+impl Drop for PendingSign {
+    fn drop(&mut self) {
+        if let Ok(mut history) = HISTORY.lock() {
+            if let Some(entry) = history.get_mut(&self.key_id) {
+                entry.in_flight = entry.in_flight.saturating_sub(1);
+            }
+        }
+    }
+}
+
+/// 签名方锁定之后重新评估请求, 不再计入速率限制. 用于动态选择签名方时检查最终的签名方名单.
+pub(crate) fn recheck(req: &SignRequest) -> Resultat<()> {
+    let policy = match installed_policy() {
+        Some(policy) => policy,
+        None => return Ok(()),
+    };
+    let now = now_secs().catch_()?;
+    policy
+        .check(req, now, &[])
+        .catch("PolicyViolation", format!("key {}", &req.key_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_rules() -> Resultat<()> {
+        let policy = Policy::from_json(
+            r#"{"rules": [
+                {"rule": "path_prefixes", "prefixes": ["m/44/60"]},
+                {"rule": "max_tasks", "max": 2},
+                {"rule": "rate_limit", "max_signs": 2, "window_secs": 60},
+                {"rule": "required_cosigners", "names": ["Alice"]},
+                {"rule": "time_window", "start": "22:00", "end": "06:00"}
+            ]}"#,
+        )
+        .catch_()?;
+        let req = SignRequest {
+            key_id: "k".to_owned(),
+            cosigners: ["Alice".to_owned(), "Bob".to_owned()].into(),
+            bip32_paths: vec!["m/44/60/0".to_owned()],
+        };
+        let night = 23 * 3600;
+        policy.check(&req, night, &[]).catch_()?;
+        assert_throw!(policy.check(&req, 12 * 3600, &[]).is_err());
+        assert_throw!(policy.check(&req, night, &[night - 10, night - 5]).is_err());
+        policy
+            .check(&req, night, &[night - 100, night - 5])
+            .catch_()?;
+
+        let mut bad = req.clone();
+        bad.bip32_paths.push("m/0".to_owned());
+        assert_throw!(policy.check(&bad, night, &[]).is_err());
+        let mut bad = req.clone();
+        bad.bip32_paths = vec!["m/44/60/1".to_owned(); 3];
+        assert_throw!(policy.check(&bad, night, &[]).is_err());
+        let mut bad = req.clone();
+        bad.cosigners.remove("Alice");
+        assert_throw!(policy.check(&bad, night, &[]).is_err());
+
+        assert_throw!(Policy::from_json(
+            r#"{"rules": [{"rule": "time_window", "start": "25:00", "end": "06:00"}]}"#
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rate_limit_counts_successful_signs() -> Resultat<()> {
+        let policy = Policy::from_json(
+            r#"{"rules": [{"rule": "rate_limit", "max_signs": 1, "window_secs": 3600}]}"#,
+        )
+        .catch_()?;
+        install_policy(Some(policy)).catch_()?;
+        let req = SignRequest {
+            key_id: "test_rate_limit_counts_successful_signs".to_owned(),
+            cosigners: BTreeSet::new(),
+            bip32_paths: vec!["m/0".to_owned()],
+        };
+
+        // 失败的Sign不计入.
+        let pending = enforce(&req).catch_()?;
+        drop(pending);
+        let pending = enforce(&req).catch_()?.ifnone_()?;
+        // 进行中的Sign已计入.
+        assert_throw!(enforce(&req).is_err());
+        pending.commit().catch_()?;
+        assert_throw!(enforce(&req).is_err());
+        install_policy(None).catch_()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,712 @@
+//! BIP174 PSBT (版本0) 签名.
+//!
+//! 输入的BIP32派生记录若以MPC根公钥的指纹开头, 且派生出的公钥与记录一致, 则视为归MPC密钥所有.
+//! 对这些输入计算legacy或BIP143 sighash, 在一场会话中批量签名, 再把DER签名和sighash标志写回PSBT.
+//! 签名在返回前已规范化为低s.
+//! 不支持taproot输入.
+use erreur::*;
+use sha2::{Digest, Sha256};
+use svarog_algo::elgamal_secp256k1::KeystoreElgamal;
+
+use crate::{
+    bip32::{fingerprint, hash160, ExtendedPk, HARDENED},
+    btc::biz_sign,
+    sig_encoding::{encode_signature, SigEncoding},
+    structs::{Keystore, MessageKind, SignTask},
+};
+
+pub const PSBT_MAGIC: &[u8; 5] = b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_IN_NON_WITNESS_UTXO: u8 = 0x00;
+const PSBT_IN_WITNESS_UTXO: u8 = 0x01;
+const PSBT_IN_PARTIAL_SIG: u8 = 0x02;
+const PSBT_IN_SIGHASH_TYPE: u8 = 0x03;
+const PSBT_IN_REDEEM_SCRIPT: u8 = 0x04;
+const PSBT_IN_WITNESS_SCRIPT: u8 = 0x05;
+const PSBT_IN_BIP32_DERIVATION: u8 = 0x06;
+const PSBT_IN_FINAL_SCRIPTSIG: u8 = 0x07;
+const PSBT_IN_FINAL_SCRIPTWITNESS: u8 = 0x08;
+
+pub const SIGHASH_ALL: u32 = 0x01;
+pub const SIGHASH_NONE: u32 = 0x02;
+pub const SIGHASH_SINGLE: u32 = 0x03;
+pub const SIGHASH_ANYONECANPAY: u32 = 0x80;
+
+/// 按原始顺序保存的键值对, 以便原样写回未识别的字段.
+pub type KvMap = Vec<(Vec<u8>, Vec<u8>)>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxIn {
+    pub prev_txid: [u8; 32],
+    pub vout: u32,
+    pub script_sig: Vec<u8>,
+    pub sequence: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxOut {
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tx {
+    pub version: u32,
+    pub inputs: Vec<TxIn>,
+    pub outputs: Vec<TxOut>,
+    pub lock_time: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Psbt {
+    pub tx: Tx,
+    pub global: KvMap,
+    pub inputs: Vec<KvMap>,
+    pub outputs: Vec<KvMap>,
+}
+
+/// 一个归MPC密钥所有的输入, 及其待签的sighash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PsbtTask {
+    pub input: usize,
+    pub pk: [u8; 33],
+    pub bip32_path: String,
+    pub sighash: [u8; 32],
+    pub sighash_type: u32,
+}
+
+pub fn sha256d(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn take(&mut self, n: usize) -> Resultat<&'a [u8]> {
+        assert_throw!(
+            self.buf.len() - self.pos >= n,
+            format!("unexpected end of data at offset {}", self.pos)
+        );
+        let res = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(res)
+    }
+
+    fn u8(&mut self) -> Resultat<u8> {
+        Ok(self.take(1).catch_()?[0])
+    }
+
+    fn u32(&mut self) -> Resultat<u32> {
+        let b: [u8; 4] = self.take(4).catch_()?.try_into().catch_()?;
+        Ok(u32::from_le_bytes(b))
+    }
+
+    fn u64(&mut self) -> Resultat<u64> {
+        let b: [u8; 8] = self.take(8).catch_()?.try_into().catch_()?;
+        Ok(u64::from_le_bytes(b))
+    }
+
+    fn compact_size(&mut self) -> Resultat<u64> {
+        let res = match self.u8().catch_()? {
+            0xfd => u16::from_le_bytes(self.take(2).catch_()?.try_into().catch_()?) as u64,
+            0xfe => self.u32().catch_()? as u64,
+            0xff => self.u64().catch_()?,
+            n => n as u64,
+        };
+        Ok(res)
+    }
+
+    fn var_bytes(&mut self) -> Resultat<&'a [u8]> {
+        let n = self.compact_size().catch_()? as usize;
+        self.take(n)
+    }
+}
+
+fn write_compact_size(buf: &mut Vec<u8>, n: u64) {
+    if n < 0xfd {
+        buf.push(n as u8);
+    } else if n <= 0xffff {
+        buf.push(0xfd);
+        buf.extend_from_slice(&(n as u16).to_le_bytes());
+    } else if n <= 0xffff_ffff {
+        buf.push(0xfe);
+        buf.extend_from_slice(&(n as u32).to_le_bytes());
+    } else {
+        buf.push(0xff);
+        buf.extend_from_slice(&n.to_le_bytes());
+    }
+}
+
+fn write_var_bytes(buf: &mut Vec<u8>, data: &[u8]) {
+    write_compact_size(buf, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+
+impl TxOut {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.value.to_le_bytes());
+        write_var_bytes(buf, &self.script_pubkey);
+    }
+
+    fn read(r: &mut Reader) -> Resultat<Self> {
+        Ok(Self {
+            value: r.u64().catch_()?,
+            script_pubkey: r.var_bytes().catch_()?.to_vec(),
+        })
+    }
+}
+
+impl Tx {
+    /// 解析交易. 带见证的交易也能解析, 但见证数据被丢弃.
+    pub fn parse(data: &[u8]) -> Resultat<Self> {
+        let mut r = Reader::new(data);
+        let version = r.u32().catch_()?;
+        let mut n_in = r.compact_size().catch_()?;
+        let segwit = n_in == 0;
+        if segwit {
+            assert_throw!(r.u8().catch_()? == 1, "bad segwit flag");
+            n_in = r.compact_size().catch_()?;
+        }
+        let mut inputs = Vec::new();
+        for _ in 0..n_in {
+            inputs.push(TxIn {
+                prev_txid: r.take(32).catch_()?.try_into().catch_()?,
+                vout: r.u32().catch_()?,
+                script_sig: r.var_bytes().catch_()?.to_vec(),
+                sequence: r.u32().catch_()?,
+            });
+        }
+        let n_out = r.compact_size().catch_()?;
+        let mut outputs = Vec::new();
+        for _ in 0..n_out {
+            outputs.push(TxOut::read(&mut r).catch_()?);
+        }
+        if segwit {
+            for _ in 0..n_in {
+                let n_items = r.compact_size().catch_()?;
+                for _ in 0..n_items {
+                    r.var_bytes().catch_()?;
+                }
+            }
+        }
+        let lock_time = r.u32().catch_()?;
+        assert_throw!(r.is_empty(), "trailing bytes after transaction");
+        Ok(Self {
+            version,
+            inputs,
+            outputs,
+            lock_time,
+        })
+    }
+
+    /// 不含见证的序列化.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&self.version.to_le_bytes());
+        write_compact_size(&mut buf, self.inputs.len() as u64);
+        for txin in self.inputs.iter() {
+            buf.extend_from_slice(&txin.prev_txid);
+            buf.extend_from_slice(&txin.vout.to_le_bytes());
+            write_var_bytes(&mut buf, &txin.script_sig);
+            buf.extend_from_slice(&txin.sequence.to_le_bytes());
+        }
+        write_compact_size(&mut buf, self.outputs.len() as u64);
+        for txout in self.outputs.iter() {
+            txout.write(&mut buf);
+        }
+        buf.extend_from_slice(&self.lock_time.to_le_bytes());
+        buf
+    }
+
+    /// 内部字节序的txid, 与输入中的 `prev_txid` 直接可比.
+    pub fn txid(&self) -> [u8; 32] {
+        sha256d(&self.serialize())
+    }
+
+    pub fn legacy_sighash(&self, index: usize, script_code: &[u8], sighash_type: u32) -> [u8; 32] {
+        let base = sighash_type & 0x1f;
+        if base == SIGHASH_SINGLE && index >= self.outputs.len() {
+            let mut one = [0u8; 32];
+            one[0] = 1;
+            return one;
+        }
+
+        let mut tx = self.clone();
+        for (k, txin) in tx.inputs.iter_mut().enumerate() {
+            txin.script_sig = if k == index {
+                script_code.to_vec()
+            } else {
+                Vec::new()
+            };
+            if k != index && (base == SIGHASH_NONE || base == SIGHASH_SINGLE) {
+                txin.sequence = 0;
+            }
+        }
+        if base == SIGHASH_NONE {
+            tx.outputs.clear();
+        } else if base == SIGHASH_SINGLE {
+            tx.outputs.truncate(index + 1);
+            for txout in tx.outputs.iter_mut().take(index) {
+                txout.value = u64::MAX;
+                txout.script_pubkey.clear();
+            }
+        }
+        if sighash_type & SIGHASH_ANYONECANPAY != 0 {
+            tx.inputs = vec![tx.inputs[index].clone()];
+        }
+
+        let mut buf = tx.serialize();
+        buf.extend_from_slice(&sighash_type.to_le_bytes());
+        sha256d(&buf)
+    }
+
+    pub fn bip143_sighash(
+        &self,
+        index: usize,
+        script_code: &[u8],
+        amount: u64,
+        sighash_type: u32,
+    ) -> [u8; 32] {
+        let base = sighash_type & 0x1f;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+
+        let mut hash_prevouts = [0u8; 32];
+        if !anyone_can_pay {
+            let mut buf = Vec::new();
+            for txin in self.inputs.iter() {
+                buf.extend_from_slice(&txin.prev_txid);
+                buf.extend_from_slice(&txin.vout.to_le_bytes());
+            }
+            hash_prevouts = sha256d(&buf);
+        }
+
+        let mut hash_sequence = [0u8; 32];
+        if !anyone_can_pay && base != SIGHASH_SINGLE && base != SIGHASH_NONE {
+            let mut buf = Vec::new();
+            for txin in self.inputs.iter() {
+                buf.extend_from_slice(&txin.sequence.to_le_bytes());
+            }
+            hash_sequence = sha256d(&buf);
+        }
+
+        let mut hash_outputs = [0u8; 32];
+        if base != SIGHASH_SINGLE && base != SIGHASH_NONE {
+            let mut buf = Vec::new();
+            for txout in self.outputs.iter() {
+                txout.write(&mut buf);
+            }
+            hash_outputs = sha256d(&buf);
+        } else if base == SIGHASH_SINGLE && index < self.outputs.len() {
+            let mut buf = Vec::new();
+            self.outputs[index].write(&mut buf);
+            hash_outputs = sha256d(&buf);
+        }
+
+        let txin = &self.inputs[index];
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&self.version.to_le_bytes());
+        buf.extend_from_slice(&hash_prevouts);
+        buf.extend_from_slice(&hash_sequence);
+        buf.extend_from_slice(&txin.prev_txid);
+        buf.extend_from_slice(&txin.vout.to_le_bytes());
+        write_var_bytes(&mut buf, script_code);
+        buf.extend_from_slice(&amount.to_le_bytes());
+        buf.extend_from_slice(&txin.sequence.to_le_bytes());
+        buf.extend_from_slice(&hash_outputs);
+        buf.extend_from_slice(&self.lock_time.to_le_bytes());
+        buf.extend_from_slice(&sighash_type.to_le_bytes());
+        sha256d(&buf)
+    }
+}
+
+fn read_map(r: &mut Reader) -> Resultat<KvMap> {
+    let mut map = KvMap::new();
+    loop {
+        let key = r.var_bytes().catch_()?;
+        if key.is_empty() {
+            break;
+        }
+        let value = r.var_bytes().catch_()?;
+        assert_throw!(
+            map.iter().all(|(k, _)| k != key),
+            format!("duplicate key {}", hex::encode(key))
+        );
+        map.push((key.to_vec(), value.to_vec()));
+    }
+    Ok(map)
+}
+
+fn write_map(buf: &mut Vec<u8>, map: &KvMap) {
+    for (key, value) in map.iter() {
+        write_var_bytes(buf, key);
+        write_var_bytes(buf, value);
+    }
+    buf.push(0x00);
+}
+
+fn map_get(map: &KvMap, key_type: u8) -> Option<&[u8]> {
+    map.iter()
+        .find(|(k, _)| k.len() == 1 && k[0] == key_type)
+        .map(|(_, v)| v.as_slice())
+}
+
+fn is_p2sh(script: &[u8]) -> bool {
+    script.len() == 23 && script[0] == 0xa9 && script[1] == 0x14 && script[22] == 0x87
+}
+
+fn is_p2wpkh(script: &[u8]) -> bool {
+    script.len() == 22 && script[0] == 0x00 && script[1] == 0x14
+}
+
+fn is_p2wsh(script: &[u8]) -> bool {
+    script.len() == 34 && script[0] == 0x00 && script[1] == 0x20
+}
+
+fn is_witness_program(script: &[u8]) -> bool {
+    script.len() >= 4
+        && script.len() <= 42
+        && (script[0] == 0x00 || (0x51..=0x60).contains(&script[0]))
+        && script[1] as usize + 2 == script.len()
+}
+
+impl Psbt {
+    pub fn parse(data: &[u8]) -> Resultat<Self> {
+        let mut r = Reader::new(data);
+        assert_throw!(
+            r.take(PSBT_MAGIC.len()).catch_()? == PSBT_MAGIC,
+            "not a PSBT"
+        );
+        let global = read_map(&mut r).catch_()?;
+        let tx = map_get(&global, PSBT_GLOBAL_UNSIGNED_TX)
+            .ifnone("InvalidPsbt", "missing unsigned transaction")?;
+        let tx = Tx::parse(tx).catch("InvalidPsbt", "bad unsigned transaction")?;
+        assert_throw!(
+            tx.inputs.iter().all(|txin| txin.script_sig.is_empty()),
+            "unsigned transaction has non-empty scriptSig"
+        );
+
+        let mut inputs = Vec::new();
+        for _ in 0..tx.inputs.len() {
+            inputs.push(read_map(&mut r).catch_()?);
+        }
+        let mut outputs = Vec::new();
+        for _ in 0..tx.outputs.len() {
+            outputs.push(read_map(&mut r).catch_()?);
+        }
+        assert_throw!(r.is_empty(), "trailing bytes after PSBT");
+        Ok(Self {
+            tx,
+            global,
+            inputs,
+            outputs,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = PSBT_MAGIC.to_vec();
+        write_map(&mut buf, &self.global);
+        for map in self.inputs.iter().chain(self.outputs.iter()) {
+            write_map(&mut buf, map);
+        }
+        buf
+    }
+
+    /// 被花费的输出.
+    pub fn spent_output(&self, index: usize) -> Resultat<TxOut> {
+        let map = &self.inputs[index];
+        let txin = &self.tx.inputs[index];
+        if let Some(prev_tx) = map_get(map, PSBT_IN_NON_WITNESS_UTXO) {
+            let prev_tx = Tx::parse(prev_tx).catch_()?;
+            assert_throw!(
+                prev_tx.txid() == txin.prev_txid,
+                format!("input {} has a mismatched non-witness utxo", index)
+            );
+            let txout = prev_tx.outputs.get(txin.vout as usize).ifnone(
+                "InvalidPsbt",
+                format!("input {} spends a missing output", index),
+            )?;
+            return Ok(txout.clone());
+        }
+        let txout = map_get(map, PSBT_IN_WITNESS_UTXO)
+            .ifnone("InvalidPsbt", format!("input {} has no utxo", index))?;
+        let mut r = Reader::new(txout);
+        let txout = TxOut::read(&mut r).catch_()?;
+        assert_throw!(
+            r.is_empty(),
+            format!("input {} has a bad witness utxo", index)
+        );
+        Ok(txout)
+    }
+
+    pub fn sighash_type(&self, index: usize) -> Resultat<u32> {
+        match map_get(&self.inputs[index], PSBT_IN_SIGHASH_TYPE) {
+            Some(v) => {
+                let v: [u8; 4] = v.try_into().catch("InvalidPsbt", "bad sighash type")?;
+                Ok(u32::from_le_bytes(v))
+            }
+            None => Ok(SIGHASH_ALL),
+        }
+    }
+
+    /// 按输入的脚本类型选择legacy或BIP143算法.
+    pub fn sighash(&self, index: usize) -> Resultat<[u8; 32]> {
+        assert_throw!(index < self.inputs.len(), format!("no input {}", index));
+        let map = &self.inputs[index];
+        let sighash_type = self.sighash_type(index).catch_()?;
+        let spent = self.spent_output(index).catch_()?;
+
+        let mut script = spent.script_pubkey.clone();
+        if is_p2sh(&script) {
+            let redeem_script = map_get(map, PSBT_IN_REDEEM_SCRIPT).ifnone(
+                "InvalidPsbt",
+                format!("input {} spends p2sh without redeem script", index),
+            )?;
+            assert_throw!(
+                hash160(redeem_script)[..] == script[2..22],
+                format!("input {} has a mismatched redeem script", index)
+            );
+            script = redeem_script.to_vec();
+        }
+
+        if is_p2wpkh(&script) {
+            let mut script_code = vec![0x76, 0xa9, 0x14];
+            script_code.extend_from_slice(&script[2..]);
+            script_code.extend_from_slice(&[0x88, 0xac]);
+            Ok(self
+                .tx
+                .bip143_sighash(index, &script_code, spent.value, sighash_type))
+        } else if is_p2wsh(&script) {
+            let script_code = map_get(map, PSBT_IN_WITNESS_SCRIPT).ifnone(
+                "InvalidPsbt",
+                format!("input {} spends p2wsh without witness script", index),
+            )?;
+            assert_throw!(
+                Sha256::digest(script_code)[..] == script[2..],
+                format!("input {} has a mismatched witness script", index)
+            );
+            Ok(self
+                .tx
+                .bip143_sighash(index, script_code, spent.value, sighash_type))
+        } else {
+            assert_throw!(
+                !is_witness_program(&script),
+                format!("input {} uses an unsupported witness version", index)
+            );
+            // legacy sighash不承诺金额, 必须由完整的前序交易 (已核对txid) 给出被花费的输出.
+            assert_throw!(
+                map_get(map, PSBT_IN_NON_WITNESS_UTXO).is_some(),
+                format!(
+                    "input {} spends a legacy output without non-witness utxo",
+                    index
+                )
+            );
+            Ok(self.tx.legacy_sighash(index, &script, sighash_type))
+        }
+    }
+
+    /// 找出归MPC密钥所有的输入, 已经最终化的输入被跳过.
+    pub fn sign_tasks(&self, keystore: &KeystoreElgamal) -> Resultat<Vec<PsbtTask>> {
+        let root = ExtendedPk::from_keystore(keystore).catch_()?;
+        let root_fp = fingerprint(&root.pk);
+        let mut tasks = Vec::new();
+        for (index, map) in self.inputs.iter().enumerate() {
+            if map_get(map, PSBT_IN_FINAL_SCRIPTSIG).is_some()
+                || map_get(map, PSBT_IN_FINAL_SCRIPTWITNESS).is_some()
+            {
+                continue;
+            }
+            for (key, value) in map.iter() {
+                if key.len() != 34 || key[0] != PSBT_IN_BIP32_DERIVATION {
+                    continue;
+                }
+                if value.len() < 4 || value.len() % 4 != 0 || value[..4] != root_fp {
+                    continue;
+                }
+                let path: Vec<u32> = value[4..]
+                    .chunks(4)
+                    .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                    .collect();
+                assert_throw!(
+                    path.iter().all(|&idx| idx < HARDENED),
+                    format!("input {} uses hardened derivation", index)
+                );
+                let node = root.derive_path(&path).catch_()?;
+                let pk = node.pk.to33bytes();
+                assert_throw!(
+                    pk[..] == key[1..],
+                    format!("input {} has a mismatched bip32 derivation", index)
+                );
+                let bip32_path = std::iter::once("m".to_owned())
+                    .chain(path.iter().map(|idx| idx.to_string()))
+                    .collect::<Vec<_>>()
+                    .join("/");
+                tasks.push(PsbtTask {
+                    input: index,
+                    pk,
+                    bip32_path,
+                    sighash: self.sighash(index).catch_()?,
+                    sighash_type: self.sighash_type(index).catch_()?,
+                });
+            }
+        }
+        Ok(tasks)
+    }
+
+    /// 写入 `PSBT_IN_PARTIAL_SIG`, 已有同一公钥的签名时覆盖.
+    pub fn add_partial_sig(&mut self, index: usize, pk: &[u8; 33], sig: Vec<u8>) {
+        let mut key = vec![PSBT_IN_PARTIAL_SIG];
+        key.extend_from_slice(pk);
+        let map = &mut self.inputs[index];
+        match map.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = sig,
+            None => map.push((key, sig)),
+        }
+    }
+}
+
+/// 解析PSBT, 在一场会话中签名所有归MPC密钥所有的输入, 返回写入部分签名后的PSBT.
+pub async fn biz_sign_psbt(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<KeystoreElgamal>,
+    psbt: Vec<u8>,
+) -> Resultat<Vec<u8>> {
+    let mut psbt = Psbt::parse(&psbt).catch("InvalidPsbt", "")?;
+    let tasks = psbt.sign_tasks(&keystore.share).catch_()?;
+    assert_throw!(!tasks.is_empty(), "no input is owned by this keystore");
+
+    let sign_tasks = tasks
+        .iter()
+        .map(|task| SignTask {
+            message: task.sighash.to_vec(),
+            bip32_path: task.bip32_path.clone(),
+            kind: MessageKind::Prehashed32,
+        })
+        .collect();
+    let sigs = biz_sign(sesman_url, session_id, keystore, sign_tasks)
+        .await
+        .catch_()?;
+    assert_throw!(sigs.len() == tasks.len());
+
+    for (task, sig) in tasks.iter().zip(sigs.iter()) {
+        assert_throw!(
+            sig.pk == task.pk,
+            format!("input {} was signed by an unexpected key", task.input)
+        );
+        let der = encode_signature(
+            sig,
+            SigEncoding::Der {
+                sighash: Some(task.sighash_type as u8),
+            },
+        )
+        .catch_()?;
+        psbt.add_partial_sig(task.input, &task.pk, der);
+    }
+    Ok(psbt.serialize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BIP143 中 native P2WPKH 的示例.
+    #[test]
+    fn test_bip143_sighash() -> Resultat<()> {
+        let tx = hex::decode("0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000").catch_()?;
+        let tx = Tx::parse(&tx).catch_()?;
+        let script_code =
+            hex::decode("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").catch_()?;
+        let sighash = tx.bip143_sighash(1, &script_code, 600000000, SIGHASH_ALL);
+        assert_throw!(
+            hex::encode(sighash)
+                == "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_psbt_roundtrip() -> Resultat<()> {
+        let tx = hex::decode("0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000").catch_()?;
+        let mut witness_utxo = Vec::new();
+        TxOut {
+            value: 600000000,
+            script_pubkey: hex::decode("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").catch_()?,
+        }
+        .write(&mut witness_utxo);
+        let psbt = Psbt {
+            tx: Tx::parse(&tx).catch_()?,
+            global: vec![(vec![PSBT_GLOBAL_UNSIGNED_TX], tx.clone())],
+            inputs: vec![vec![], vec![(vec![PSBT_IN_WITNESS_UTXO], witness_utxo)]],
+            outputs: vec![vec![], vec![]],
+        };
+        let parsed = Psbt::parse(&psbt.serialize()).catch_()?;
+        assert_throw!(parsed == psbt);
+        assert_throw!(
+            hex::encode(parsed.sighash(1).catch_()?)
+                == "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+        assert_throw!(parsed.sighash(0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_checks() -> Resultat<()> {
+        let tx = hex::decode("0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000").catch_()?;
+        let p2wpkh = hex::decode("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").catch_()?;
+        let psbt = |script_pubkey: Vec<u8>, extra: KvMap| -> Resultat<Psbt> {
+            let mut witness_utxo = Vec::new();
+            TxOut {
+                value: 600000000,
+                script_pubkey,
+            }
+            .write(&mut witness_utxo);
+            let mut input = vec![(vec![PSBT_IN_WITNESS_UTXO], witness_utxo)];
+            input.extend(extra);
+            Ok(Psbt {
+                tx: Tx::parse(&tx).catch_()?,
+                global: vec![(vec![PSBT_GLOBAL_UNSIGNED_TX], tx.clone())],
+                inputs: vec![vec![], input],
+                outputs: vec![vec![], vec![]],
+            })
+        };
+
+        // P2SH-P2WPKH: 脚本哈希一致时与native P2WPKH的sighash相同.
+        let mut p2sh = vec![0xa9, 0x14];
+        p2sh.extend_from_slice(&hash160(&p2wpkh));
+        p2sh.push(0x87);
+        let nested = psbt(
+            p2sh.clone(),
+            vec![(vec![PSBT_IN_REDEEM_SCRIPT], p2wpkh.clone())],
+        )?;
+        assert_throw!(
+            hex::encode(nested.sighash(1).catch_()?)
+                == "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+        let mut other = p2wpkh.clone();
+        other[21] ^= 1;
+        let forged = psbt(p2sh, vec![(vec![PSBT_IN_REDEEM_SCRIPT], other.clone())])?;
+        assert_throw!(forged.sighash(1).is_err());
+
+        let mut p2wsh = vec![0x00, 0x20];
+        p2wsh.extend_from_slice(&Sha256::digest(&p2wpkh));
+        let forged = psbt(p2wsh, vec![(vec![PSBT_IN_WITNESS_SCRIPT], other)])?;
+        assert_throw!(forged.sighash(1).is_err());
+
+        // legacy输入只有witness utxo时, 金额没有被承诺.
+        let p2pkh = hex::decode("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").catch_()?;
+        assert_throw!(psbt(p2pkh, vec![])?.sighash(1).is_err());
+        Ok(())
+    }
+}
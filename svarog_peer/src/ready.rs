@@ -0,0 +1,219 @@
+//! 会话开始前的就绪检查. `SessionConfig.ready_timeout_secs` 非零时启用.
+//!
+//! 各方先经sesman发布就绪标记, 其中有版本和能力, 所需的各方都就绪后才开始协议.
+//! 期限内未就绪, 或版本和能力不兼容的参与方以 `IdentifiableAbort` 列出,
+//! 不必等到协议中途在 `outbox` 阻塞. 发起方可以用 `await_ready` 等待各方就绪.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use erreur::*;
+use svarog_grpc::{ReadyMarker, SessionConfig};
+use svarog_sesman::SvarogChannel;
+use tokio::time::{sleep, Duration, Instant};
+
+use crate::{
+    blame::{Abort, Culprit},
+    keystore::MpcKeystore,
+    ses_members,
+    structs::{KeystoreElgamal, KeystoreSchnorr},
+};
+
+pub const PEER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// 本方支持的能力, 写入就绪标记.
+pub fn capabilities() -> Vec<String> {
+    [
+        KeystoreElgamal::ALGO,
+        KeystoreSchnorr::ALGO,
+        "epoch",
+        "mailbox",
+        "blame",
+    ]
+    .iter()
+    .map(|c| c.to_string())
+    .collect()
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReadyReport {
+    pub ready: Vec<String>,
+    pub absent: Vec<String>,
+    /// 已就绪, 但版本不同或不支持本次会话的算法.
+    pub incompatible: Vec<String>,
+}
+
+/// 版本号去掉修订号的部分, 例如 `0.1.3` 为 `0.1`.
+fn release(version: &str) -> &str {
+    version
+        .rsplit_once('.')
+        .map_or(version, |(release, _)| release)
+}
+
+pub(crate) fn evaluate(
+    required: &BTreeSet<String>,
+    markers: &[ReadyMarker],
+    algorithm: &str,
+) -> ReadyReport {
+    let markers: HashMap<&str, &ReadyMarker> = markers
+        .iter()
+        .map(|marker| (marker.player.as_str(), marker))
+        .collect();
+    let mut report = ReadyReport::default();
+    for name in required.iter() {
+        match markers.get(name.as_str()) {
+            None => report.absent.push(name.clone()),
+            Some(marker)
+                if release(&marker.version) != release(PEER_VERSION)
+                    || !marker.capabilities.iter().any(|c| c == algorithm) =>
+            {
+                report.incompatible.push(name.clone())
+            }
+            Some(_) => report.ready.push(name.clone()),
+        }
+    }
+    report
+}
+
+/// 会话中出席的成员, 以及他们的序号. Reshare时名称可能同时是provider和consumer, 取provider的序号.
+fn attending(cfg: &SessionConfig) -> BTreeMap<String, usize> {
+    let mut names = BTreeMap::new();
+    for players in [&cfg.players_reshared, &cfg.players] {
+        for (name, j) in ses_members(players) {
+            if players.get(&name) == Some(&true) {
+                names.insert(name, j);
+            }
+        }
+    }
+    names
+}
+
+/// 轮询就绪标记, 直到 `required` 中各方都就绪. 超时或有一方不兼容时失败.
+/// 协议按会话配置中的全体出席成员运行, 所以不能只等其中一部分.
+async fn wait_ready(
+    chan: &mut SvarogChannel,
+    cfg: &SessionConfig,
+    required: &BTreeSet<String>,
+    algorithm: &str,
+) -> Resultat<ReadyReport> {
+    let deadline = Instant::now() + Duration::from_secs(cfg.ready_timeout_secs);
+    loop {
+        let markers = chan.ready_markers().await.catch_()?;
+        let report = evaluate(required, &markers, algorithm);
+        if report.ready.len() == required.len() {
+            return Ok(report);
+        }
+        if !report.incompatible.is_empty() || Instant::now() >= deadline {
+            let members = attending(cfg);
+            let culprits = report
+                .absent
+                .iter()
+                .chain(report.incompatible.iter())
+                .map(|name| Culprit {
+                    index: members.get(name).copied().unwrap_or(0),
+                    name: name.clone(),
+                })
+                .collect();
+            let abort = Abort {
+                session_id: chan.sid().to_owned(),
+                culprits,
+                reason: format!(
+                    "{} of {} required parties ready; absent: [{}], incompatible: [{}]",
+                    report.ready.len(),
+                    required.len(),
+                    report.absent.join(", "),
+                    report.incompatible.join(", ")
+                ),
+            };
+            return abort.throw();
+        }
+        sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// 参与方在协议开始前调用. 未启用就绪检查时直接返回.
+pub(crate) async fn ready_round(
+    chan: &mut SvarogChannel,
+    cfg: &SessionConfig,
+    member_name: &str,
+    required: &BTreeSet<String>,
+    algorithm: &str,
+) -> Resultat<()> {
+    if cfg.ready_timeout_secs == 0 {
+        return Ok(());
+    }
+    chan.post_ready(member_name, PEER_VERSION, capabilities())
+        .await
+        .catch_()?;
+    wait_ready(chan, cfg, required, algorithm)
+        .await
+        .catch("NotReady", "")?;
+    Ok(())
+}
+
+/// 会话中全体出席成员的名称.
+pub(crate) fn required_parties(cfg: &SessionConfig) -> BTreeSet<String> {
+    attending(cfg).into_keys().collect()
+}
+
+/// 发起方分发会话号后调用, 等待全体出席成员就绪.
+pub async fn await_ready(
+    sesman_url: &str,
+    session_id: &str,
+    algorithm: &str,
+) -> Resultat<ReadyReport> {
+    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
+    let https = sesman_url.starts_with("https://");
+    let (mut chan, cfg) = SvarogChannel::use_session(session_id, sesman_url, https)
+        .await
+        .catch_()?;
+    assert_throw!(
+        cfg.ready_timeout_secs > 0,
+        "ready check is not enabled for this session"
+    );
+    let required = required_parties(&cfg);
+    let report = wait_ready(&mut chan, &cfg, &required, algorithm)
+        .await
+        .catch("NotReady", "")?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(player: &str, version: &str, capabilities: &[&str]) -> ReadyMarker {
+        ReadyMarker {
+            session_id: "ses".to_owned(),
+            player: player.to_owned(),
+            version: version.to_owned(),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_evaluate() -> Resultat<()> {
+        let required: BTreeSet<String> = ["Alice", "Bob", "Charlie", "David"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let markers = vec![
+            marker("Alice", PEER_VERSION, &["elgamal_secp256k1"]),
+            marker("Bob", PEER_VERSION, &["schnorr_ed25519"]),
+            marker("Charlie", "999.0.0", &["elgamal_secp256k1"]),
+            marker("Eve", PEER_VERSION, &["elgamal_secp256k1"]),
+        ];
+        let report = evaluate(&required, &markers, "elgamal_secp256k1");
+        assert_throw!(report.ready == vec!["Alice".to_owned()]);
+        assert_throw!(report.absent == vec!["David".to_owned()]);
+        assert_throw!(report.incompatible == vec!["Bob".to_owned(), "Charlie".to_owned()]);
+
+        let cfg = SessionConfig {
+            players: HashMap::from([("Alice".to_owned(), true), ("Bob".to_owned(), false)]),
+            players_reshared: HashMap::from([("Carol".to_owned(), true)]),
+            ..Default::default()
+        };
+        let members = attending(&cfg);
+        assert_throw!(members.len() == 2);
+        assert_throw!(members.get("Alice") == Some(&1) && members.get("Carol") == Some(&1));
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use std::{fs::OpenOptions, io::Write};
+
+use clap::{Arg, ArgAction, Command};
+use erreur::*;
+use svarog_peer::recovery::recover_from_exports;
+use zeroize::Zeroizing;
+
+fn main() -> Resultat<()> {
+    // Parse args
+    let matches = Command::new("svarog_recover")
+        .about(
+            "Reconstruct the full private key from threshold many keystore exports. \
+             The key is no longer protected by MPC afterwards.",
+        )
+        .arg(
+            Arg::new("exports")
+                .required(true)
+                .num_args(1..)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("confirm")
+                .long("confirm")
+                .required(true)
+                .help("key id of the key to recover, confirms that the full key will be exposed")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("out")
+                .long("out")
+                .required(true)
+                .help("file to write the recovered key to, must not exist yet")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("testnet")
+                .long("testnet")
+                .help("encode WIF and xprv for testnet")
+                .action(ArgAction::SetTrue),
+        )
+        .get_matches();
+    let confirm = matches.get_one::<String>("confirm").ifnone_()?;
+    let out = matches.get_one::<String>("out").ifnone_()?;
+
+    let mut exports = Vec::new();
+    for path in matches.get_many::<String>("exports").ifnone_()? {
+        let data = std::fs::read(path).catch("", format!("Try reading {}", path))?;
+        exports.push(data);
+    }
+    let key = recover_from_exports(&exports, confirm, matches.get_flag("testnet"))
+        .catch("RecoveryFailed", "")?;
+
+    let mut opts = OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+    let mut file = opts.open(out).catch("", format!("Try creating {}", out))?;
+    let json = Zeroizing::new(serde_json::to_vec_pretty(&key).catch_()?);
+    file.write_all(&json).catch_()?;
+    println!(
+        "recovered {} from parties {:?}, written to {}",
+        &key.key_id, &key.parties, out
+    );
+    Ok(())
+}
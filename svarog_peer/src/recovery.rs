@@ -0,0 +1,293 @@
+//! 离线恢复完整私钥, 用于灾难恢复和迁移到其他系统. 恢复之后MPC不再提供任何保护, 慎用.
+//!
+//! 输入至少门限个成员的Keystore导出文件 (门限即签名所需的最少人数, 也就是多项式次数t加1).
+//! 每个文件导入时都用VSS承诺验证分片, 然后在0处Lagrange插值得到私钥, 并与群公钥比对.
+//! 调用方必须给出这把密钥的key id作为确认, 以免误恢复了别的密钥.
+//!
+//! ElGamal输出私钥的hex, WIF和根节点的xprv. Schnorr输出的是私钥标量而不是Ed25519种子:
+//! 分布式生成的密钥没有种子, 只能导入支持扩展私钥 (标量) 的钱包或库.
+use std::collections::BTreeSet;
+
+use curve25519_dalek::EdwardsPoint;
+use erreur::*;
+use serde::{Deserialize, Serialize};
+use svarog_algo::elgamal_secp256k1::{ProjectivePoint, Scalar};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+    bip32::base58check,
+    export::{export_algorithm, import_keystore},
+    keystore::{key_id, MpcKeystore},
+    mailbox::{lagrange, ShareScalar},
+    structs::{Keystore, KeystoreElgamal, KeystoreSchnorr},
+};
+
+pub const XPRV_MAINNET: [u8; 4] = [0x04, 0x88, 0xad, 0xe4];
+pub const XPRV_TESTNET: [u8; 4] = [0x04, 0x35, 0x83, 0x94];
+
+/// drop时清零私钥的各种编码, `Debug` 不输出它们.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RecoveredKey {
+    pub key_id: String,
+    pub algorithm: String,
+    pub group_pk: String,
+    pub chain_code: String,
+    /// 插值所用的成员序号.
+    pub parties: Vec<usize>,
+    /// 私钥 (Schnorr为私钥标量) 的hex, 大端 (secp256k1) 或小端 (ed25519).
+    pub secret: String,
+    /// 对应压缩公钥的WIF. 仅ElGamal.
+    pub wif: Option<String>,
+    /// 根节点的扩展私钥. 仅ElGamal.
+    pub xprv: Option<String>,
+}
+
+impl std::fmt::Debug for RecoveredKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecoveredKey")
+            .field("key_id", &self.key_id)
+            .field("algorithm", &self.algorithm)
+            .field("group_pk", &self.group_pk)
+            .field("chain_code", &self.chain_code)
+            .field("parties", &self.parties)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for RecoveredKey {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+        self.wif.zeroize();
+        self.xprv.zeroize();
+    }
+}
+
+/// 检查各方的Keystore属于同一把密钥, 处于同一代数, 序号互不相同, 人数不少于门限,
+/// 返回在0处插值的结果. 不同代数的分片属于不同的多项式, 插值得不到私钥.
+fn interpolate<K: MpcKeystore, S: ShareScalar>(
+    keystores: &[Keystore<K>],
+    share_scalar: impl Fn(&K) -> S,
+    confirm_key_id: &str,
+) -> Resultat<(S, BTreeSet<usize>)> {
+    let first = keystores
+        .first()
+        .ifnone("NotEnoughShares", "no keystore given")?;
+    let kid = key_id(first);
+    assert_throw!(
+        confirm_key_id == kid,
+        format!("recovery of {} is not confirmed", &kid)
+    );
+    let mut parties = BTreeSet::new();
+    for keystore in keystores.iter() {
+        let i = keystore.party_index();
+        assert_throw!(
+            key_id(keystore) == kid,
+            format!("share {} belongs to {}, not {}", i, key_id(keystore), &kid)
+        );
+        assert_throw!(
+            keystore.epoch == first.epoch,
+            format!(
+                "share {} is at epoch {}, share {} at epoch {}",
+                i,
+                keystore.epoch,
+                first.party_index(),
+                first.epoch
+            )
+        );
+        assert_throw!(
+            keystore.verify_share(),
+            format!("share {} is inconsistent with the VSS commitments", i)
+        );
+        assert_throw!(parties.insert(i), format!("share {} is given twice", i));
+    }
+    assert_throw!(
+        parties.len() >= first.threshold,
+        format!(
+            "{} shares given, {} are required",
+            parties.len(),
+            first.threshold
+        )
+    );
+
+    let mut secret = S::from_index(0);
+    for keystore in keystores.iter() {
+        let i = keystore.party_index();
+        let lambda: S = lagrange(i, 0, &parties).catch_()?;
+        secret = secret + lambda * share_scalar(&keystore.share);
+    }
+    Ok((secret, parties))
+}
+
+pub fn recover_elgamal(
+    keystores: &[Keystore<KeystoreElgamal>],
+    confirm_key_id: &str,
+    testnet: bool,
+) -> Resultat<RecoveredKey> {
+    let (x, parties) = interpolate(keystores, |share| share.xi, confirm_key_id).catch_()?;
+    elgamal_key(&keystores[0], x, parties, testnet)
+}
+
+/// 与群公钥比对, 并编码ElGamal私钥 `x`.
+pub(crate) fn elgamal_key(
+    first: &Keystore<KeystoreElgamal>,
+    x: Scalar,
+    parties: BTreeSet<usize>,
+    testnet: bool,
+) -> Resultat<RecoveredKey> {
+    let group_pk = first.group_pk();
+    assert_throw!(
+        (ProjectivePoint::GENERATOR * x).to33bytes().to_vec() == group_pk,
+        "recovered key does not match the group public key"
+    );
+    let secret = Zeroizing::new(x.to_bytes32());
+    let chain_code = first.chain_code();
+
+    let mut wif = Zeroizing::new(vec![if testnet { 0xef } else { 0x80 }]);
+    wif.extend_from_slice(&secret[..]);
+    wif.push(0x01);
+
+    let mut xprv = Zeroizing::new(Vec::with_capacity(78));
+    xprv.extend_from_slice(if testnet {
+        &XPRV_TESTNET
+    } else {
+        &XPRV_MAINNET
+    });
+    xprv.push(0);
+    xprv.extend_from_slice(&[0u8; 4]);
+    xprv.extend_from_slice(&0u32.to_be_bytes());
+    xprv.extend_from_slice(&chain_code);
+    xprv.push(0);
+    xprv.extend_from_slice(&secret[..]);
+
+    Ok(RecoveredKey {
+        key_id: key_id(first),
+        algorithm: KeystoreElgamal::ALGO.to_owned(),
+        group_pk: hex::encode(group_pk),
+        chain_code: hex::encode(chain_code),
+        parties: parties.into_iter().collect(),
+        secret: hex::encode(&secret[..]),
+        wif: Some(base58check(&wif)),
+        xprv: Some(base58check(&xprv)),
+    })
+}
+
+pub fn recover_schnorr(
+    keystores: &[Keystore<KeystoreSchnorr>],
+    confirm_key_id: &str,
+) -> Resultat<RecoveredKey> {
+    let (x, parties) = interpolate(keystores, |share| share.xi, confirm_key_id).catch_()?;
+    schnorr_key(&keystores[0], x, parties)
+}
+
+/// 与群公钥比对, 并编码Schnorr私钥标量 `x`.
+pub(crate) fn schnorr_key(
+    first: &Keystore<KeystoreSchnorr>,
+    x: curve25519_dalek::Scalar,
+    parties: BTreeSet<usize>,
+) -> Resultat<RecoveredKey> {
+    let group_pk = first.group_pk();
+    assert_throw!(
+        EdwardsPoint::mul_base(&x).compress().to_bytes().to_vec() == group_pk,
+        "recovered key does not match the group public key"
+    );
+    Ok(RecoveredKey {
+        key_id: key_id(first),
+        algorithm: KeystoreSchnorr::ALGO.to_owned(),
+        group_pk: hex::encode(group_pk),
+        chain_code: hex::encode(first.chain_code()),
+        parties: parties.into_iter().collect(),
+        secret: hex::encode(Zeroizing::new(x.to_bytes32()).as_slice()),
+        wif: None,
+        xprv: None,
+    })
+}
+
+/// 从导出文件恢复. 算法由文件决定, 所有文件的算法必须相同.
+pub fn recover_from_exports(
+    exports: &[Vec<u8>],
+    confirm_key_id: &str,
+    testnet: bool,
+) -> Resultat<RecoveredKey> {
+    let first = exports
+        .first()
+        .ifnone("NotEnoughShares", "no export given")?;
+    let algorithm = export_algorithm(first).catch_()?;
+    if algorithm == KeystoreElgamal::ALGO {
+        let keystores = exports
+            .iter()
+            .map(|data| import_keystore::<KeystoreElgamal>(data).map(|k| k.keystore))
+            .collect::<Resultat<Vec<_>>>()?;
+        recover_elgamal(&keystores, confirm_key_id, testnet)
+    } else if algorithm == KeystoreSchnorr::ALGO {
+        let keystores = exports
+            .iter()
+            .map(|data| import_keystore::<KeystoreSchnorr>(data).map(|k| k.keystore))
+            .collect::<Resultat<Vec<_>>>()?;
+        recover_schnorr(&keystores, confirm_key_id)
+    } else {
+        None.ifnone("UnsupportedAlgorithm", format!("export is {}", &algorithm))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use curve25519_dalek::Scalar;
+
+    use super::*;
+
+    /// 门限2, 三个成员. 两个dealer的多项式分别为 `3 + 5x` 和 `7 + 11x`, 私钥为10.
+    fn keystores() -> Vec<Keystore<KeystoreSchnorr>> {
+        let coefs = [
+            [Scalar::from(3u64), Scalar::from(5u64)],
+            [Scalar::from(7u64), Scalar::from(11u64)],
+        ];
+        let vss_scheme: BTreeMap<usize, Vec<EdwardsPoint>> = coefs
+            .iter()
+            .enumerate()
+            .map(|(j, coef)| (j + 1, coef.iter().map(EdwardsPoint::mul_base).collect()))
+            .collect();
+        (1..=3u64)
+            .map(|i| Keystore {
+                share: KeystoreSchnorr {
+                    i: i as usize,
+                    xi: coefs.iter().map(|[a0, a1]| a0 + a1 * Scalar::from(i)).sum(),
+                    vss_scheme: vss_scheme.clone(),
+                    chain_code: [0u8; 32],
+                },
+                threshold: 2,
+                players: BTreeMap::from([
+                    ("Alice".to_owned(), 1),
+                    ("Bob".to_owned(), 2),
+                    ("Charlie".to_owned(), 3),
+                ]),
+                allowed_paths: Vec::new(),
+                epoch: 0,
+                refreshed_at: 0,
+                pending: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_recover_schnorr() -> Resultat<()> {
+        let keystores = keystores();
+        let kid = key_id(&keystores[0]);
+        let key = recover_schnorr(&keystores[1..], &kid)?;
+        assert_throw!(key.secret == hex::encode(Scalar::from(10u64).to_bytes()));
+        assert_throw!(key.parties == vec![2, 3]);
+
+        assert_throw!(recover_schnorr(&keystores[1..], "wrong").is_err());
+        assert_throw!(recover_schnorr(&keystores[..1], &kid).is_err());
+        let twice = vec![keystores[0].clone(), keystores[0].clone()];
+        assert_throw!(recover_schnorr(&twice, &kid).is_err());
+
+        let mut stale = keystores[1..].to_vec();
+        stale[1].epoch = 1;
+        let err = recover_schnorr(&stale, &kid).err().ifnone_()?;
+        assert_throw!(format!("{}", err).contains("share 3 is at epoch 1, share 2 at epoch 0"));
+        assert_throw!(!format!("{:?}", key).contains(&key.secret));
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+//! 主动刷新分片. 成员和门限不变, 所有成员得到同一私钥的新分片, `Keystore.epoch` 加一.
+//!
+//! Refresh是成员结构不变的Reshare: 会话的 `players` 与 `players_reshared` 相同且全部出席,
+//! 每个成员同时是provider和consumer. 用 `refresh_config` 构造会话配置即可.
+//!
+//! Sign和Reshare开始协议之前, 参与方经会话交换各自的 `epoch`, 不一致时拒绝继续,
+//! 因此刷新后的旧分片不能再与新分片一起使用.
+//!
+//! 本crate不运行定时器, 刷新由调用方调度: `install_refresh_interval` 只设置间隔,
+//! `refresh_due` 只做判断. 发起方定期调用 `start_refresh_if_due`, 到期时它创建Refresh会话并返回会话号,
+//! 发起方把会话号分发给全体成员, 各成员再调用 `biz_refresh` 或 `biz_refresh_vault`.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::RwLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use erreur::*;
+use mpc_sig_abs::BatchMessenger;
+use svarog_grpc::SessionConfig;
+use svarog_sesman::SvarogChannel;
+
+use crate::{new_session, ses_members, structs::Keystore};
+
+const EPOCH_TOPIC: &str = "svarog_peer_epoch";
+
+static REFRESH_INTERVAL: RwLock<Option<u64>> = RwLock::new(None);
+
+/// 设置定期刷新的间隔秒数. `None` 表示不定期刷新.
+pub fn install_refresh_interval(interval_secs: Option<u64>) -> Resultat<()> {
+    assert_throw!(
+        interval_secs != Some(0),
+        "refresh interval should be positive"
+    );
+    *REFRESH_INTERVAL
+        .write()
+        .ok()
+        .ifnone("PoisonedLock", "refresh interval")? = interval_secs;
+    Ok(())
+}
+
+pub fn refresh_interval() -> Option<u64> {
+    REFRESH_INTERVAL.read().ok().and_then(|interval| *interval)
+}
+
+pub(crate) fn now_secs() -> Resultat<u64> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .catch_()?
+        .as_secs())
+}
+
+/// 按已设置的间隔, 判断Keystore在 `now` (Unix秒) 时是否应当刷新. 只做判断, 不发起刷新.
+pub fn refresh_due<K>(keystore: &Keystore<K>, now: u64) -> bool {
+    match refresh_interval() {
+        Some(interval) => keystore.refreshed_at.saturating_add(interval) <= now,
+        None => false,
+    }
+}
+
+/// Refresh会话的配置: 全体成员既是provider也是consumer.
+pub fn refresh_config<K>(keystore: &Keystore<K>, sesman_url: &str) -> SessionConfig {
+    let players: HashMap<String, bool> = keystore
+        .players
+        .keys()
+        .map(|name| (name.clone(), true))
+        .collect();
+    SessionConfig {
+        sesman_url: sesman_url.to_owned(),
+        session_id: String::new(),
+        threshold: keystore.threshold as u64,
+        players: players.clone(),
+        players_reshared: players,
+        ..Default::default()
+    }
+}
+
+/// 发起方的调度入口: Keystore按已设置的间隔到期时, 创建Refresh会话并返回会话号; 未到期时返回 `None`.
+/// 会话号要由调用方分发给全体成员.
+pub async fn start_refresh_if_due<K>(
+    keystore: &Keystore<K>,
+    sesman_url: &str,
+) -> Resultat<Option<String>> {
+    let now = now_secs().catch_()?;
+    if !refresh_due(keystore, now) {
+        return Ok(None);
+    }
+    let sid = new_session(refresh_config(keystore, sesman_url))
+        .await
+        .catch_()?;
+    Ok(Some(sid))
+}
+
+/// 检查会话确实是本Keystore的Refresh.
+pub(crate) fn check_refresh_config<K>(keystore: &Keystore<K>, cfg: &SessionConfig) -> Resultat<()> {
+    let players: BTreeSet<&String> = cfg.players.keys().collect();
+    let consumers: BTreeSet<&String> = cfg.players_reshared.keys().collect();
+    assert_throw!(
+        players == consumers,
+        "refresh should keep the membership unchanged"
+    );
+    let absent: Vec<&str> = cfg
+        .players
+        .iter()
+        .chain(cfg.players_reshared.iter())
+        .filter(|(_, &att)| !att)
+        .map(|(name, _)| name.as_str())
+        .collect::<BTreeSet<&str>>()
+        .into_iter()
+        .collect();
+    assert_throw!(
+        absent.is_empty(),
+        format!(
+            "all members should attend a refresh, absent: {}",
+            absent.join(", ")
+        )
+    );
+    assert_throw!(
+        ses_members(&cfg.players) == keystore.players,
+        "session members differ from the keystore"
+    );
+    assert_throw!(
+        cfg.threshold as usize == keystore.threshold,
+        format!(
+            "refresh should keep the threshold {}, session has {}",
+            keystore.threshold, cfg.threshold
+        )
+    );
+    Ok(())
+}
+
+/// `senders` 中的每一方把自己的 `epoch` 发给 `receivers` 中的每一方.
+/// 本方以序号 `sender.0` 发送, 以序号 `receiver` 接收; 两者可以只有其一.
+/// 接收方检查收到的 `epoch` 全部相同并返回它; 不接收时返回 `None`.
+pub(crate) async fn exchange_epoch(
+    chan: &mut SvarogChannel,
+    sender: Option<(usize, u64)>,
+    receiver: Option<usize>,
+    senders: &BTreeSet<usize>,
+    receivers: &BTreeSet<usize>,
+) -> Resultat<Option<u64>> {
+    // 发给自己的消息不经过会话.
+    let to_self = |src: usize, dst: usize| {
+        src == dst && sender.map(|(j, _)| j) == Some(src) && receiver == Some(dst)
+    };
+
+    if let Some((j, epoch)) = sender {
+        for &dst in receivers.iter().filter(|&&dst| !to_self(j, dst)) {
+            chan.register_send(EPOCH_TOPIC, j, dst, 0, &epoch)
+                .catch_()?;
+        }
+        chan.execute_send().await.catch_()?;
+    }
+    let i = match receiver {
+        Some(i) => i,
+        None => {
+            chan.clear();
+            return Ok(None);
+        }
+    };
+    for &src in senders.iter().filter(|&&src| !to_self(src, i)) {
+        chan.register_receive(EPOCH_TOPIC, src, i, 0).catch_()?;
+    }
+    chan.execute_receive().await.catch_()?;
+    let mut epochs: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for &src in senders.iter() {
+        let epoch: u64 = match sender {
+            Some((_, epoch)) if to_self(src, i) => epoch,
+            _ => chan.unpack_receive(EPOCH_TOPIC, src, i, 0).catch_()?,
+        };
+        epochs.entry(epoch).or_default().push(src);
+    }
+    chan.clear();
+
+    let summary: Vec<String> = epochs
+        .iter()
+        .map(|(epoch, parties)| format!("epoch {} at parties {:?}", epoch, parties))
+        .collect();
+    assert_throw!(
+        epochs.len() <= 1,
+        format!("shares are from different epochs: {}", summary.join("; "))
+    );
+    Ok(epochs.keys().next().copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_config() -> Resultat<()> {
+        let keystore = Keystore {
+            share: (),
+            threshold: 2,
+            players: [("Alice".to_owned(), 1), ("Bob".to_owned(), 2)].into(),
+            allowed_paths: Vec::new(),
+            epoch: 3,
+            refreshed_at: 1000,
+            pending: Vec::new(),
+        };
+        let mut cfg = refresh_config(&keystore, "http://127.0.0.1:2000");
+        check_refresh_config(&keystore, &cfg).catch_()?;
+        cfg.players_reshared.insert("Bob".to_owned(), false);
+        assert_throw!(check_refresh_config(&keystore, &cfg).is_err());
+        cfg.players_reshared.insert("Bob".to_owned(), true);
+        cfg.threshold = 1;
+        assert_throw!(check_refresh_config(&keystore, &cfg).is_err());
+
+        install_refresh_interval(Some(600)).catch_()?;
+        assert_throw!(!refresh_due(&keystore, 1599));
+        assert_throw!(refresh_due(&keystore, 1600));
+        install_refresh_interval(None).catch_()?;
+        assert_throw!(!refresh_due(&keystore, u64::MAX));
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+//! 根私钥导出仪式, 用于冷备份. 至少门限个持有者在会话中合作, 只有指定的导出方
+//! (`SessionConfig.exporter`) 得到完整的根私钥和链码.
+//!
+//! 1. 导出方广播一次性的临时公钥, 附上用自己的分片作的签名.
+//! 2. 其余持有者 `i` 用VSS承诺算出导出方的公开分片, 验证签名后计算 `lambda_i(0) * x_i`,
+//!    用临时公钥加密后只发给导出方.
+//! 3. 导出方解密, 用VSS承诺逐一验证各项, 相加得到根私钥并与群公钥比对.
+//!
+//! 每个持有者只送出自己的一项. 临时公钥经导出方的分片签名, sesman无法换成自己的公钥,
+//! 因此只要导出方不泄露, 其余参与方和sesman (包括篡改转发消息的sesman) 都得不到根私钥.
+//! 签名或某一项无效时以 `IdentifiableAbort` 指出发送方.
+//!
+//! 分布式生成的密钥没有BIP39助记词. 即使密钥是由 `keygen_mnem` 导入的, 助记词到根私钥的推导
+//! (PBKDF2和HMAC-SHA512) 也不可逆, 因此无法还原助记词和密码. 导出的xprv可以直接导入钱包,
+//! 与原助记词派生出相同的BIP32密钥树.
+use std::collections::{BTreeMap, BTreeSet};
+
+use erreur::*;
+use mpc_sig_abs::BatchMessenger;
+use svarog_sesman::SvarogChannel;
+
+use crate::{
+    audit::AuditEvent,
+    blame::{attribute, Abort},
+    check_members,
+    holders::{reporter, start_holders},
+    keystore::MpcKeystore,
+    mailbox::{
+        generate_mailbox_key, lagrange, mailbox_pk, open, seal, sign_with_share, to_scalar,
+        verify_share_sig, Deliverable, ShareScalar,
+    },
+    ses_arch,
+    structs::Keystore,
+};
+
+const EXPORT_KEY_TOPIC: &str = "svarog_peer_export_key";
+const EXPORT_PIECE_TOPIC: &str = "svarog_peer_export_piece";
+
+fn export_key_tag(session_id: &str, exporter: usize) -> String {
+    format!("svarog-export-key/{}/{}", session_id, exporter)
+}
+
+fn export_tag(session_id: &str, src: usize) -> String {
+    format!("svarog-export/{}/{}", session_id, src)
+}
+
+/// 导出方得到根私钥, 其余持有者得到 `None`. `holders` 为出席的持有者, 包括导出方.
+pub(crate) async fn export_root<K: Deliverable>(
+    chan: &mut SvarogChannel,
+    keystore: &Keystore<K>,
+    exporter: usize,
+    holders: &BTreeSet<usize>,
+) -> Resultat<Option<K::Share>> {
+    let i = keystore.party_index();
+    assert_throw!(holders.contains(&i), "holder not in the session");
+    assert_throw!(
+        holders.contains(&exporter),
+        format!("exporter {} is not among the attending holders", exporter)
+    );
+    assert_throw!(
+        holders.len() >= keystore.threshold,
+        format!(
+            "{} holders attend, {} are required",
+            holders.len(),
+            keystore.threshold
+        )
+    );
+    let others: Vec<usize> = holders.iter().copied().filter(|&j| j != exporter).collect();
+    let sid = chan.sid().to_owned();
+    let share = keystore.share.share_scalar();
+    let term = lagrange::<K::Share>(i, 0, holders).catch_()? * share;
+    let parties: BTreeMap<usize, Vec<String>> = keystore
+        .players
+        .iter()
+        .map(|(name, &k)| (k, vec![name.clone()]))
+        .collect();
+
+    // 第一轮: 导出方广播临时公钥, 用分片签名.
+    let ephemeral_sk = generate_mailbox_key();
+    if i == exporter {
+        let ephemeral_pk = mailbox_pk(&ephemeral_sk).to_vec();
+        let key_sig = sign_with_share(&share, &export_key_tag(&sid, i), &ephemeral_pk);
+        let key_msg = (ephemeral_pk, key_sig);
+        for &j in others.iter() {
+            chan.register_send(EXPORT_KEY_TOPIC, i, j, 0, &key_msg)
+                .catch_()?;
+        }
+        chan.execute_send().await.catch_()?;
+    } else {
+        chan.register_receive(EXPORT_KEY_TOPIC, exporter, i, 0)
+            .catch_()?;
+        chan.execute_receive().await.catch_()?;
+        let (pk, sig): (Vec<u8>, Vec<u8>) = chan
+            .unpack_receive(EXPORT_KEY_TOPIC, exporter, i, 0)
+            .catch_()?;
+        chan.clear();
+        let exporter_pk = keystore.share.public_share_of(exporter);
+        let tag = export_key_tag(&sid, exporter);
+        if !verify_share_sig::<K::Share>(&exporter_pk, &tag, &pk, &sig) {
+            let reason = "export key not signed by the exporter's share";
+            return Abort::new(&sid, &[exporter].into(), &parties, reason).throw();
+        }
+
+        // 第二轮: 把自己的一项加密后发给导出方.
+        let sealed = seal(&pk, &export_tag(&sid, i), &term.to_bytes32()).catch_()?;
+        chan.register_send(EXPORT_PIECE_TOPIC, i, exporter, 0, &sealed)
+            .catch_()?;
+        chan.execute_send().await.catch_()?;
+        return Ok(None);
+    }
+
+    for &j in others.iter() {
+        chan.register_receive(EXPORT_PIECE_TOPIC, j, i, 0)
+            .catch_()?;
+    }
+    chan.execute_receive().await.catch_()?;
+    let mut secret = term;
+    let mut invalid = BTreeSet::new();
+    for &j in others.iter() {
+        let sealed: Vec<u8> = chan.unpack_receive(EXPORT_PIECE_TOPIC, j, i, 0).catch_()?;
+        let piece = open(&ephemeral_sk, &export_tag(&sid, j), &sealed)
+            .and_then(|plain| to_scalar::<K::Share>(&plain));
+        let lambda = lagrange::<K::Share>(j, 0, holders).catch_()?;
+        // `piece / lambda_j` 应当是成员 `j` 的分片.
+        let valid = match (&piece, lambda.inverse()) {
+            (Ok(piece), Some(inv)) => {
+                (*piece * inv).public_point() == keystore.share.public_share_of(j)
+            }
+            _ => false,
+        };
+        match piece {
+            Ok(piece) if valid => secret = secret + piece,
+            _ => {
+                invalid.insert(j);
+            }
+        }
+    }
+    chan.clear();
+    if !invalid.is_empty() {
+        let reason = "invalid root export piece";
+        return Abort::new(&sid, &invalid, &parties, reason).throw();
+    }
+    assert_throw!(
+        secret.public_point() == keystore.group_pk(),
+        "exported root key does not match the group public key"
+    );
+    Ok(Some(secret))
+}
+
+/// 在会话中运行导出仪式, 导出方得到根私钥和出席的持有者.
+pub(crate) async fn export_root_in_session<K: Deliverable>(
+    sesman_url: &str,
+    session_id: &str,
+    keystore: &Keystore<K>,
+    audit: &mut AuditEvent,
+) -> Resultat<Option<(K::Share, BTreeSet<usize>)>> {
+    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
+    let https = sesman_url.starts_with("https://");
+
+    let (chan, cfg) = SvarogChannel::use_session(session_id, sesman_url, https)
+        .await
+        .catch_()?;
+    audit.set_config(&cfg).catch_()?;
+    check_members(&keystore.players, &cfg.players).catch_()?;
+    let exporter = *keystore.players.get(&cfg.exporter).ifnone(
+        "PlayerUnknown",
+        format!("exporter {} unknown", &cfg.exporter),
+    )?;
+    let (_, holders) = ses_arch("", &cfg.players);
+    let reporter = reporter(keystore);
+    let mut ses = start_holders(chan, &cfg, keystore, &holders, reporter)
+        .await
+        .catch_()?;
+    let secret = export_root(&mut ses.chan, keystore, exporter, &holders).await;
+    let secret = attribute(secret, &mut ses.watch, &ses.parties, &ses.reporter)
+        .await
+        .catch_()?;
+    Ok(secret.map(|secret| (secret, holders)))
+}
@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+
+use clap::{Arg, ArgAction, Command};
+use erreur::*;
+use svarog_peer::{
+    health::{check_export, check_vault},
+    vault::{Vault, VaultKey},
+};
+
+const PASSPHRASE_ENV: &str = "SVAROG_VAULT_PASSPHRASE";
+
+fn main() -> Resultat<()> {
+    // Parse args
+    let matches = Command::new("svarog_share_check")
+        .about("Check a stored keystore share against its VSS commitments offline")
+        .arg(
+            Arg::new("export")
+                .long("export")
+                .help("keystore export file")
+                .conflicts_with("vault")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("vault")
+                .long("vault")
+                .help("vault directory, the passphrase is read from SVAROG_VAULT_PASSPHRASE")
+                .requires("key-id")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("key-id")
+                .long("key-id")
+                .help("key id in the vault")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("kek")
+                .long("kek")
+                .help("key encryption key file of the vault, instead of the passphrase")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("print the report as JSON")
+                .action(ArgAction::SetTrue),
+        )
+        .get_matches();
+
+    let health = if let Some(path) = matches.get_one::<String>("export") {
+        let data = std::fs::read(path).catch("", format!("Try reading {}", path))?;
+        check_export(&data).catch("InvalidExport", path)?
+    } else {
+        let dir: PathBuf = matches
+            .get_one::<String>("vault")
+            .ifnone("", "either --export or --vault is required")?
+            .into();
+        assert_throw!(dir.is_dir(), format!("{} is not a vault", dir.display()));
+        let key_id = matches.get_one::<String>("key-id").ifnone_()?;
+        let key = match matches.get_one::<String>("kek") {
+            Some(kek) => VaultKey::KekFile(kek.into()),
+            None => VaultKey::Passphrase(
+                std::env::var(PASSPHRASE_ENV)
+                    .catch("", format!("{} is not set", PASSPHRASE_ENV))?
+                    .into(),
+            ),
+        };
+        let vault = Vault::open(&dir, key).catch_()?;
+        check_vault(&vault, key_id).catch_()?
+    };
+
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&health).catch_()?);
+    } else {
+        let check = |ok: Option<bool>| match ok {
+            Some(true) => "ok",
+            Some(false) => "FAILED",
+            None => "n/a",
+        };
+        println!("key id      {}", &health.key_id);
+        println!("fingerprint {}", &health.fingerprint);
+        println!("party       {} ({})", health.i, &health.member_name);
+        println!("threshold   {}", health.threshold);
+        println!("epoch       {}", health.epoch);
+        println!("share       {}", check(Some(health.share_consistent)));
+        println!("commitments {}", check(Some(health.commitments_consistent)));
+        println!("group pk    {}", check(health.group_pk_consistent));
+        println!("paillier    {}", check(health.paillier_consistent));
+    }
+    assert_throw!(
+        health.healthy(),
+        format!("unhealthy share: {}", health.problems.join("; "))
+    );
+    Ok(())
+}
@@ -0,0 +1,290 @@
+//! `structs::Signature` 与各种标准签名编码之间的转换.
+//!
+//! | 编码      | 格式                                          |
+//! |-----------|-----------------------------------------------|
+//! | `Der`     | ASN.1 DER ECDSA签名, 可选地附加一个sighash字节 |
+//! | `Compact` | 64字节 `r || s`                               |
+//! | `EthRsv`  | `r || s || v`, `v` 的取值见 `EthV`            |
+//! | `Ed25519` | 64字节 `R || s`                               |
+use erreur::*;
+use serde::{Deserialize, Serialize};
+use svarog_grpc::{
+    EncodedSignature, EthV as PbEthV, SignatureEncoding as PbEncoding, SignatureFormat,
+};
+
+use crate::structs::Signature;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EthV {
+    /// 0或1.
+    RecoveryId,
+    /// 27或28.
+    Legacy,
+    /// `chain_id * 2 + 35` 或 `chain_id * 2 + 36`.
+    Eip155(u64),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SigEncoding {
+    Der { sighash: Option<u8> },
+    Compact,
+    EthRsv(EthV),
+    Ed25519,
+}
+
+impl SigEncoding {
+    /// 从gRPC的 `SignatureFormat` 转换.
+    pub fn from_pb(format: &SignatureFormat) -> Resultat<Self> {
+        let encoding = PbEncoding::try_from(format.encoding).catch(
+            "InvalidSignatureFormat",
+            format!("unknown encoding {}", format.encoding),
+        )?;
+        let res = match encoding {
+            PbEncoding::Der => SigEncoding::Der {
+                sighash: match format.sighash {
+                    0 => None,
+                    x => Some(
+                        u8::try_from(x).catch("InvalidSignatureFormat", "sighash overflows u8")?,
+                    ),
+                },
+            },
+            PbEncoding::Compact => SigEncoding::Compact,
+            PbEncoding::EthRsv => {
+                let eth_v = PbEthV::try_from(format.eth_v).catch(
+                    "InvalidSignatureFormat",
+                    format!("unknown eth_v {}", format.eth_v),
+                )?;
+                SigEncoding::EthRsv(match eth_v {
+                    PbEthV::RecoveryId => EthV::RecoveryId,
+                    PbEthV::Legacy => EthV::Legacy,
+                    PbEthV::Eip155 => EthV::Eip155(format.chain_id),
+                })
+            }
+            PbEncoding::Ed25519 => SigEncoding::Ed25519,
+        };
+        Ok(res)
+    }
+}
+
+pub fn encode_signature(sig: &Signature, encoding: SigEncoding) -> Resultat<Vec<u8>> {
+    match encoding {
+        SigEncoding::Der { sighash } => {
+            let mut res = to_der(sig);
+            res.extend(sighash);
+            Ok(res)
+        }
+        SigEncoding::Compact | SigEncoding::Ed25519 => Ok(to_compact(sig).to_vec()),
+        SigEncoding::EthRsv(eth_v) => to_eth_rsv(sig, eth_v),
+    }
+}
+
+/// 按gRPC请求中的 `SignatureFormat` 输出签名.
+pub fn encode_signature_pb(
+    sig: &Signature,
+    format: &SignatureFormat,
+) -> Resultat<EncodedSignature> {
+    let encoding = SigEncoding::from_pb(format).catch_()?;
+    Ok(EncodedSignature {
+        value: encode_signature(sig, encoding).catch_()?,
+        pk: sig.pk.clone(),
+    })
+}
+
+/// `pk` 不在编码中, 由调用方提供.
+pub fn decode_signature(data: &[u8], encoding: SigEncoding, pk: Vec<u8>) -> Resultat<Signature> {
+    let (r, s, v) = match encoding {
+        SigEncoding::Der { sighash } => {
+            let der = match sighash {
+                Some(flag) => {
+                    let (last, der) = data
+                        .split_last()
+                        .ifnone("InvalidSignature", "empty signature")?;
+                    assert_throw!(
+                        *last == flag,
+                        format!("sighash flag is {:#04x}, expected {:#04x}", last, flag)
+                    );
+                    der
+                }
+                None => data,
+            };
+            let (r, s) = from_der(der).catch_()?;
+            (r, s, 0)
+        }
+        SigEncoding::Compact | SigEncoding::Ed25519 => {
+            let (r, s) = from_compact(data).catch_()?;
+            (r, s, 0)
+        }
+        SigEncoding::EthRsv(eth_v) => from_eth_rsv(data, eth_v).catch_()?,
+    };
+    Ok(Signature { r, s, v, pk })
+}
+
+pub fn to_der(sig: &Signature) -> Vec<u8> {
+    fn der_int(x: &[u8; 32]) -> Vec<u8> {
+        let pos = x.iter().position(|&b| b != 0).unwrap_or(31);
+        let mut res = vec![0x02];
+        if x[pos] >= 0x80 {
+            res.push((33 - pos) as u8);
+            res.push(0x00);
+        } else {
+            res.push((32 - pos) as u8);
+        }
+        res.extend_from_slice(&x[pos..]);
+        res
+    }
+    let r = der_int(&sig.r);
+    let s = der_int(&sig.s);
+    let mut res = vec![0x30, (r.len() + s.len()) as u8];
+    res.extend(r);
+    res.extend(s);
+    res
+}
+
+/// 严格的DER解析, 拒绝非最简编码.
+pub fn from_der(data: &[u8]) -> Resultat<([u8; 32], [u8; 32])> {
+    fn der_int(data: &[u8]) -> Resultat<([u8; 32], &[u8])> {
+        assert_throw!(data.len() >= 2 && data[0] == 0x02, "expect a DER integer");
+        let len = data[1] as usize;
+        assert_throw!(
+            (1..=33).contains(&len) && data.len() >= 2 + len,
+            "bad DER integer length"
+        );
+        let x = &data[2..2 + len];
+        assert_throw!(x[0] & 0x80 == 0, "negative DER integer");
+        assert_throw!(
+            len == 1 || x[0] != 0 || x[1] & 0x80 != 0,
+            "non-minimal DER integer"
+        );
+        assert_throw!(len < 33 || x[0] == 0, "DER integer exceeds 256 bits");
+        let x = if len == 33 { &x[1..] } else { x };
+        let mut res = [0u8; 32];
+        res[32 - x.len()..].copy_from_slice(x);
+        Ok((res, &data[2 + len..]))
+    }
+
+    assert_throw!(
+        data.len() >= 2 && data[0] == 0x30 && data[1] as usize == data.len() - 2,
+        "bad DER sequence"
+    );
+    let (r, rest) = der_int(&data[2..]).catch_()?;
+    let (s, rest) = der_int(rest).catch_()?;
+    assert_throw!(rest.is_empty(), "trailing bytes in DER signature");
+    Ok((r, s))
+}
+
+pub fn to_compact(sig: &Signature) -> [u8; 64] {
+    let mut res = [0u8; 64];
+    res[..32].copy_from_slice(&sig.r);
+    res[32..].copy_from_slice(&sig.s);
+    res
+}
+
+pub fn from_compact(data: &[u8]) -> Resultat<([u8; 32], [u8; 32])> {
+    assert_throw!(
+        data.len() == 64,
+        format!("signature should be 64 bytes, got {}", data.len())
+    );
+    Ok((
+        data[..32].try_into().catch_()?,
+        data[32..].try_into().catch_()?,
+    ))
+}
+
+/// `v` 以大端编码附在 `r || s` 之后. 除EIP-155的大chain id外, 总长度为65字节.
+pub fn to_eth_rsv(sig: &Signature, eth_v: EthV) -> Resultat<Vec<u8>> {
+    assert_throw!(sig.v <= 1, format!("unexpected recovery id {}", sig.v));
+    let v = match eth_v {
+        EthV::RecoveryId => sig.v as u128,
+        EthV::Legacy => 27 + sig.v as u128,
+        EthV::Eip155(chain_id) => chain_id as u128 * 2 + 35 + sig.v as u128,
+    };
+    let v = v.to_be_bytes();
+    let pos = v.iter().position(|&b| b != 0).unwrap_or(v.len() - 1);
+    let mut res = to_compact(sig).to_vec();
+    res.extend_from_slice(&v[pos..]);
+    Ok(res)
+}
+
+pub fn from_eth_rsv(data: &[u8], eth_v: EthV) -> Resultat<([u8; 32], [u8; 32], u8)> {
+    assert_throw!(
+        data.len() >= 65 && data.len() <= 64 + 16,
+        format!("bad signature length {}", data.len())
+    );
+    let (r, s) = from_compact(&data[..64]).catch_()?;
+    let v = data[64..]
+        .iter()
+        .fold(0u128, |acc, &b| (acc << 8) | b as u128);
+    let base = match eth_v {
+        EthV::RecoveryId => 0,
+        EthV::Legacy => 27,
+        EthV::Eip155(chain_id) => chain_id as u128 * 2 + 35,
+    };
+    assert_throw!(
+        v == base || v == base + 1,
+        format!("v = {} does not match {:?}", v, eth_v)
+    );
+    Ok((r, s, (v - base) as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Signature {
+        let mut r = [0u8; 32];
+        r[0] = 0x80;
+        r[31] = 1;
+        let mut s = [0u8; 32];
+        s[2] = 0x7f;
+        s[31] = 2;
+        Signature {
+            r,
+            s,
+            v: 1,
+            pk: vec![2; 33],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() -> Resultat<()> {
+        let sig = sample();
+        for encoding in [
+            SigEncoding::Der { sighash: None },
+            SigEncoding::Der {
+                sighash: Some(0x81),
+            },
+            SigEncoding::Compact,
+            SigEncoding::EthRsv(EthV::RecoveryId),
+            SigEncoding::EthRsv(EthV::Legacy),
+            SigEncoding::EthRsv(EthV::Eip155(1)),
+            SigEncoding::EthRsv(EthV::Eip155(137_000)),
+        ] {
+            let data = encode_signature(&sig, encoding).catch_()?;
+            let mut decoded = decode_signature(&data, encoding, sig.pk.clone()).catch_()?;
+            // 只有以太坊编码携带恢复位.
+            if !matches!(encoding, SigEncoding::EthRsv(_)) {
+                decoded.v = sig.v;
+            }
+            assert_throw!(decoded == sig, format!("{:?}", encoding));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_der() -> Resultat<()> {
+        let der = to_der(&sample());
+        assert_throw!(der[..6] == [0x30, 0x43, 0x02, 0x21, 0x00, 0x80]);
+        assert_throw!(der[37..40] == [0x02, 0x1e, 0x7f]);
+        assert_throw!(from_der(&[0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01]).is_ok());
+        // 非最简编码
+        assert_throw!(from_der(&[0x30, 0x07, 0x02, 0x02, 0x00, 0x01, 0x02, 0x01, 0x01]).is_err());
+        // 33字节且首字节非零, 超过256位
+        let mut big = vec![0x30, 0x26, 0x02, 0x21, 0x01];
+        big.extend_from_slice(&[0x80; 32]);
+        big.extend_from_slice(&[0x02, 0x01, 0x01]);
+        assert_throw!(from_der(&big).is_err());
+        let rsv = to_eth_rsv(&sample(), EthV::Legacy).catch_()?;
+        assert_throw!(rsv.len() == 65 && rsv[64] == 28);
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+//! 按 `SignTask.kind` 计算交给 `sign_batch` 的消息, 并校验bip32路径.
+//! 在会话开始前调用, 不合法的任务不会进入会话.
+use erreur::*;
+use sha2::{Digest, Sha256};
+use svarog_algo::{elgamal_secp256k1::KeystoreElgamal, schnorr_ed25519::KeystoreSchnorr};
+
+use crate::{
+    bip32::parse_path,
+    eth::keccak256,
+    keystore::MpcKeystore,
+    structs::{MessageKind, SignTask},
+};
+
+/// secp256k1签的是32字节哈希; ed25519签的是原始消息.
+pub fn task_payload(algorithm: &str, task: &SignTask) -> Resultat<Vec<u8>> {
+    let supported = match algorithm {
+        KeystoreElgamal::ALGO => task.kind != MessageKind::RawEd25519,
+        KeystoreSchnorr::ALGO => task.kind == MessageKind::RawEd25519,
+        _ => false,
+    };
+    assert_throw!(
+        supported,
+        format!(
+            "message kind {:?} is not supported by {}",
+            task.kind, algorithm
+        )
+    );
+
+    let msg = &task.message;
+    let res = match task.kind {
+        MessageKind::Prehashed32 => {
+            assert_throw!(
+                msg.len() == 32,
+                format!("prehashed message should be 32 bytes, got {}", msg.len())
+            );
+            msg.clone()
+        }
+        MessageKind::Sha256 => Sha256::digest(msg).to_vec(),
+        MessageKind::Keccak256 => keccak256(msg).to_vec(),
+        MessageKind::DoubleSha256 => Sha256::digest(Sha256::digest(msg)).to_vec(),
+        MessageKind::RawEd25519 => msg.clone(),
+    };
+    Ok(res)
+}
+
+/// 检查路径是否落在某个允许的前缀之下. 按路径段比较, `m/44/6` 不是 `m/44/60` 的前缀.
+pub fn check_path_allowed(allowed_paths: &[String], path: &[u32]) -> Resultat<()> {
+    if allowed_paths.is_empty() {
+        return Ok(());
+    }
+    let mut allowed = false;
+    for prefix in allowed_paths {
+        let prefix = parse_path(prefix).catch(
+            "InvalidAllowedPath",
+            format!("bad allowed path prefix {}", prefix),
+        )?;
+        allowed |= path.starts_with(&prefix);
+    }
+    assert_throw!(
+        allowed,
+        format!("path is not under any of {}", allowed_paths.join(", "))
+    );
+    Ok(())
+}
+
+/// 转换为 `sign_batch` 所需的 `(消息, bip32路径)`. 错误信息指出第一个不合法的任务.
+pub(crate) fn prepare_tasks(
+    algorithm: &str,
+    allowed_paths: &[String],
+    tasks: &[SignTask],
+) -> Resultat<Vec<(Vec<u8>, String)>> {
+    assert_throw!(!tasks.is_empty(), "no sign task");
+    let mut res = Vec::new();
+    for (k, task) in tasks.iter().enumerate() {
+        let ctx = format!("task #{} (bip32_path {})", k, &task.bip32_path);
+        let path = parse_path(&task.bip32_path).catch("InvalidSignTask", &ctx)?;
+        check_path_allowed(allowed_paths, &path).catch("InvalidSignTask", &ctx)?;
+        let payload = task_payload(algorithm, task).catch("InvalidSignTask", &ctx)?;
+        res.push((payload, task.bip32_path.clone()));
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_payload() -> Resultat<()> {
+        let task = |message: &[u8], kind| SignTask {
+            message: message.to_vec(),
+            bip32_path: "m/0".to_owned(),
+            kind,
+        };
+        let elgamal = KeystoreElgamal::ALGO;
+        let schnorr = KeystoreSchnorr::ALGO;
+
+        let digest = task_payload(elgamal, &task(b"abc", MessageKind::Sha256)).catch_()?;
+        assert_throw!(
+            hex::encode(digest)
+                == "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_throw!(task_payload(elgamal, &task(b"abc", MessageKind::Prehashed32)).is_err());
+        assert_throw!(task_payload(elgamal, &task(b"abc", MessageKind::RawEd25519)).is_err());
+        assert_throw!(task_payload(schnorr, &task(b"abc", MessageKind::Sha256)).is_err());
+        assert_throw!(
+            task_payload(schnorr, &task(b"abc", MessageKind::RawEd25519)).catch_()? == b"abc"
+        );
+
+        // 引入 `kind` 之前序列化的任务.
+        let old: SignTask =
+            serde_json::from_str(r#"{"message":[1,2],"bip32_path":"m/0"}"#).catch_()?;
+        assert_throw!(old.kind == MessageKind::Prehashed32);
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_allowlist() -> Resultat<()> {
+        let allowed = vec!["m/44/60".to_owned(), "m/0".to_owned()];
+        assert_throw!(check_path_allowed(&[], &[1, 2]).is_ok());
+        assert_throw!(check_path_allowed(&allowed, &[44, 60]).is_ok());
+        assert_throw!(check_path_allowed(&allowed, &[44, 60, 0, 7]).is_ok());
+        assert_throw!(check_path_allowed(&allowed, &[0]).is_ok());
+        assert_throw!(check_path_allowed(&allowed, &[44, 6]).is_err());
+        assert_throw!(check_path_allowed(&allowed, &[44]).is_err());
+
+        let task = SignTask {
+            message: vec![0u8; 32],
+            bip32_path: "m/44'/60".to_owned(),
+            kind: MessageKind::Prehashed32,
+        };
+        assert_throw!(prepare_tasks(KeystoreElgamal::ALGO, &[], &[task]).is_err());
+        Ok(())
+    }
+}
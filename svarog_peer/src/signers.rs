@@ -0,0 +1,121 @@
+//! 动态选择签名方. `SessionConfig.signer_quorum` 非零时启用.
+//!
+//! 发起方不必预先指定签名方: 全体持有者都标记为出席, 各方向sesman报到,
+//! 先报到的 `signer_quorum` 方被锁定为本次签名的签名方, 随后按锁定的名单运行协议.
+//! 未被选中的一方以 `NotSelected` 返回, 期限内凑不够人数时以 `QuorumNotReached` 返回.
+use std::collections::HashMap;
+
+use erreur::*;
+use svarog_sesman::SvarogChannel;
+
+use crate::structs::{Keystore, SessionConfig};
+
+/// 动态选择签名方的签名会话配置. 签名方人数取门限.
+pub fn dynamic_sign_config<K>(keystore: &Keystore<K>, sesman_url: &str) -> SessionConfig {
+    SessionConfig {
+        sesman_url: sesman_url.to_owned(),
+        threshold: keystore.threshold as u64,
+        players: keystore
+            .players
+            .keys()
+            .map(|name| (name.clone(), true))
+            .collect(),
+        signer_quorum: keystore.threshold as u64,
+        ..Default::default()
+    }
+}
+
+/// 报到并等待签名方锁定, 返回 `players` 换成锁定名单的会话配置. 未启用时原样返回.
+pub(crate) async fn select_signers(
+    chan: &mut SvarogChannel,
+    cfg: SessionConfig,
+    member_name: &str,
+    threshold: usize,
+) -> Resultat<SessionConfig> {
+    if cfg.signer_quorum == 0 {
+        return Ok(cfg);
+    }
+    assert_throw!(
+        cfg.signer_quorum as usize >= threshold,
+        format!(
+            "signer quorum {} is below the threshold {}",
+            cfg.signer_quorum, threshold
+        )
+    );
+    let set = chan.check_in(member_name).await.catch_()?;
+    if !set.complete {
+        // 未入选且名单不满, 说明先报到的各方已超时离开, 报到已关闭.
+        let closed = if set.selected {
+            ""
+        } else {
+            ", check-in closed"
+        };
+        return None.ifnone(
+            "QuorumNotReached",
+            format!(
+                "only {} of {} signers checked in: [{}]{}",
+                set.signers.len(),
+                cfg.signer_quorum,
+                set.signers.join(", "),
+                closed
+            ),
+        );
+    }
+    if !set.selected {
+        return None.ifnone(
+            "NotSelected",
+            format!(
+                "{} signers are already locked for this session: [{}], {} is not needed",
+                set.signers.len(),
+                set.signers.join(", "),
+                member_name
+            ),
+        );
+    }
+    Ok(locked_config(cfg, &set.signers))
+}
+
+/// 出席标记只保留锁定的签名方.
+fn locked_config(mut cfg: SessionConfig, signers: &[String]) -> SessionConfig {
+    let players: HashMap<String, bool> = cfg
+        .players
+        .keys()
+        .map(|name| (name.clone(), signers.contains(name)))
+        .collect();
+    cfg.players = players;
+    cfg
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::ses_arch;
+
+    #[test]
+    fn test_locked_config() -> Resultat<()> {
+        let keystore = Keystore {
+            share: (),
+            threshold: 2,
+            players: BTreeMap::from([
+                ("Alice".to_owned(), 1),
+                ("Bob".to_owned(), 2),
+                ("Charlie".to_owned(), 3),
+            ]),
+            allowed_paths: Vec::new(),
+            epoch: 0,
+            refreshed_at: 0,
+            pending: Vec::new(),
+        };
+        let cfg = dynamic_sign_config(&keystore, "http://127.0.0.1:9000");
+        assert_throw!(cfg.signer_quorum == 2 && cfg.players.len() == 3);
+        assert_throw!(cfg.players.values().all(|&att| att));
+
+        let cfg = locked_config(cfg, &["Charlie".to_owned(), "Alice".to_owned()]);
+        assert_throw!(cfg.players.len() == 3);
+        let (_, signers) = ses_arch("", &cfg.players);
+        assert_throw!(signers == [1, 3].into());
+        Ok(())
+    }
+}
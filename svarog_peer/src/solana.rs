@@ -3,6 +3,7 @@
 /// 2. 为`keygen_mnem`, `reshare`这两个操作开辟线程. 这两个操作都有provider和consumer两个角色.
 use std::collections::BTreeSet;
 
+use curve25519_dalek::edwards::CompressedEdwardsY;
 use erreur::*;
 use svarog_algo::schnorr_ed25519::{
     keygen, keygen_mnem_consumer, keygen_mnem_provider, reshare_consumer, reshare_provider,
@@ -11,29 +12,23 @@ use svarog_algo::schnorr_ed25519::{
 use svarog_sesman::SvarogChannel;
 
 use crate::{
-    ses_arch,
-    structs::{Mnemonics, SignTask, Signature},
+    flows::{
+        audited_export_root, audited_keygen, audited_keygen_mnem, audited_refresh, audited_reshare,
+        decode_vss, load_opt, pickup_keystore, save_opt,
+    },
+    holders::audited_sign,
+    recovery::{schnorr_key, RecoveredKey},
+    structs::{Keystore, Mnemonics, SignTask, Signature},
+    vault::Vault,
+    verify::verify_batch,
 };
 
 pub async fn biz_keygen(
     sesman_url: String,
     session_id: String,
     member_name: String,
-) -> Resultat<KeystoreSchnorr> {
-    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
-    let https = sesman_url.starts_with("https://");
-
-    let (chan, cfg) = SvarogChannel::use_session(&session_id, &sesman_url, https)
-        .await
-        .catch_()?;
-    let t = cfg.threshold as usize;
-    let (i, players) = ses_arch(&member_name, &cfg.players);
-    assert_throw!(
-        players.len() == cfg.players.len(),
-        "all keygen members should attend"
-    );
-    let keystore = impl_keygen(chan, i, t, players).await.catch_()?;
-    Ok(keystore)
+) -> Resultat<Keystore<KeystoreSchnorr>> {
+    audited_keygen(sesman_url, session_id, member_name, impl_keygen).await
 }
 
 pub async fn biz_keygen_mnem(
@@ -41,71 +36,174 @@ pub async fn biz_keygen_mnem(
     session_id: String,
     member_name: String,
     mnemonics: Option<Mnemonics>,
-) -> Resultat<Option<KeystoreSchnorr>> {
-    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
-    let https = sesman_url.starts_with("https://");
+) -> Resultat<Option<Keystore<KeystoreSchnorr>>> {
+    audited_keygen_mnem(
+        sesman_url,
+        session_id,
+        member_name,
+        mnemonics,
+        impl_keygen_mnem,
+    )
+    .await
+}
+
+pub async fn biz_sign(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<KeystoreSchnorr>,
+    tasks: Vec<SignTask>,
+) -> Resultat<Vec<Signature>> {
+    audited_sign(sesman_url, session_id, keystore, tasks, impl_sign).await
+}
+
+pub async fn biz_reshare(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    keystore: Option<Keystore<KeystoreSchnorr>>,
+) -> Resultat<Option<Keystore<KeystoreSchnorr>>> {
+    audited_reshare(sesman_url, session_id, member_name, keystore, impl_reshare).await
+}
+
+/// 成员和门限不变地刷新分片. 会话配置用 `refresh_config` 构造, 全体成员都要出席.
+pub async fn biz_refresh(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    keystore: Keystore<KeystoreSchnorr>,
+) -> Resultat<Keystore<KeystoreSchnorr>> {
+    audited_refresh(sesman_url, session_id, member_name, keystore, impl_reshare).await
+}
+
+/// 取回Reshare时因缺席而错过的分片. `mailbox_sk` 与会话配置中本方的邮箱公钥对应.
+pub async fn biz_reshare_pickup(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    mailbox_sk: [u8; 32],
+) -> Resultat<Keystore<KeystoreSchnorr>> {
+    pickup_keystore(
+        sesman_url,
+        session_id,
+        member_name,
+        mailbox_sk,
+        |template, xi| {
+            let vss_scheme = decode_vss(template, |com| {
+                CompressedEdwardsY::from_slice(com)
+                    .ok()
+                    .and_then(|com| com.decompress())
+            })
+            .catch_()?;
+            Ok(KeystoreSchnorr {
+                i: template.i,
+                xi,
+                vss_scheme,
+                chain_code: template.chain_code,
+            })
+        },
+    )
+    .await
+}
+
+/// 根私钥导出仪式, 见 `root_export`. 只有 `SessionConfig.exporter` 得到结果, 其余持有者得到 `None`.
+pub async fn biz_export_root(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<KeystoreSchnorr>,
+) -> Resultat<Option<RecoveredKey>> {
+    audited_export_root(sesman_url, session_id, keystore, schnorr_key).await
+}
 
-    let (chan, cfg) = SvarogChannel::use_session(&session_id, &sesman_url, https)
+pub async fn biz_keygen_vault(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    vault: Vault,
+) -> Resultat<String> {
+    let keystore = biz_keygen(sesman_url, session_id, member_name.clone())
         .await
         .catch_()?;
-    let t = cfg.threshold as usize;
-    let (i, players) = ses_arch(&member_name, &cfg.players);
-    assert_throw!(
-        players.len() == cfg.players.len(),
-        "all keygen members should attend"
-    );
-    let keystore = impl_keygen_mnem(chan, i, t, players, mnemonics)
+    let key_id = vault.save(&keystore, &member_name).catch_()?;
+    Ok(key_id)
+}
+
+pub async fn biz_keygen_mnem_vault(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    mnemonics: Option<Mnemonics>,
+    vault: Vault,
+) -> Resultat<Option<String>> {
+    let keystore = biz_keygen_mnem(sesman_url, session_id, member_name.clone(), mnemonics)
         .await
         .catch_()?;
-    Ok(keystore)
+    save_opt(&vault, keystore, &member_name)
 }
 
-pub async fn biz_sign(
+pub async fn biz_sign_vault(
     sesman_url: String,
     session_id: String,
-    keystore: KeystoreSchnorr,
+    key_id: String,
+    vault: Vault,
     tasks: Vec<SignTask>,
 ) -> Resultat<Vec<Signature>> {
-    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
-    let https = sesman_url.starts_with("https://");
+    let keystore: Keystore<KeystoreSchnorr> = vault.load(&key_id).catch_()?;
+    biz_sign(sesman_url, session_id, keystore, tasks).await
+}
 
-    let (chan, cfg) = SvarogChannel::use_session(&session_id, &sesman_url, https)
+/// `key_id`为空表示本方不是provider. 新的Keystore与旧的公钥相同, 因此会覆盖保管库中的旧Keystore.
+pub async fn biz_reshare_vault(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    key_id: Option<String>,
+    vault: Vault,
+) -> Resultat<Option<String>> {
+    let keystore = load_opt(&vault, key_id.as_deref()).catch_()?;
+    let keystore = biz_reshare(sesman_url, session_id, member_name.clone(), keystore)
         .await
         .catch_()?;
-    let (_, signers) = ses_arch("", &cfg.players);
-    let i = keystore.i as usize;
-    assert_throw!(signers.contains(&i), "signer not in the session");
-    let sigs = impl_sign(chan, keystore, signers, tasks).await.catch_()?;
-    Ok(sigs)
+    save_opt(&vault, keystore, &member_name)
 }
 
-pub async fn biz_reshare(
+/// 刷新后的Keystore覆盖保管库中的旧Keystore.
+pub async fn biz_refresh_vault(
     sesman_url: String,
     session_id: String,
     member_name: String,
-    keystore: Option<KeystoreSchnorr>,
-) -> Resultat<Option<KeystoreSchnorr>> {
-    assert_throw!(sesman_url.starts_with("http://") || sesman_url.starts_with("https://"));
-    let https = sesman_url.starts_with("https://");
-
-    let (chan, cfg) = SvarogChannel::use_session(&session_id, &sesman_url, https)
+    key_id: String,
+    vault: Vault,
+) -> Resultat<String> {
+    let keystore: Keystore<KeystoreSchnorr> = vault.load(&key_id).catch_()?;
+    let keystore = biz_refresh(sesman_url, session_id, member_name.clone(), keystore)
         .await
         .catch_()?;
-    let t = cfg.threshold as usize;
-    let (_, providers) = ses_arch("", &cfg.players);
-    if let Some(keystore) = &keystore {
-        let i0 = keystore.i as usize;
-        assert_throw!(providers.contains(&i0), "provider not in the session");
-    }
-    let (i, consumers) = ses_arch(&member_name, &cfg.players_reshared);
-    assert_throw!(
-        consumers.len() == cfg.players_reshared.len(),
-        "all keygen members should attend"
-    );
-    let keystore = impl_reshare(chan, keystore, i, t, providers, consumers)
+    let key_id = vault.save(&keystore, &member_name).catch_()?;
+    Ok(key_id)
+}
+
+pub async fn biz_reshare_pickup_vault(
+    sesman_url: String,
+    session_id: String,
+    member_name: String,
+    mailbox_sk: [u8; 32],
+    vault: Vault,
+) -> Resultat<String> {
+    let keystore = biz_reshare_pickup(sesman_url, session_id, member_name.clone(), mailbox_sk)
         .await
         .catch_()?;
-    Ok(keystore)
+    let key_id = vault.save(&keystore, &member_name).catch_()?;
+    Ok(key_id)
+}
+
+pub async fn biz_export_root_vault(
+    sesman_url: String,
+    session_id: String,
+    key_id: String,
+    vault: Vault,
+) -> Resultat<Option<RecoveredKey>> {
+    let keystore: Keystore<KeystoreSchnorr> = vault.load(&key_id).catch_()?;
+    biz_export_root(sesman_url, session_id, keystore).await
 }
 
 async fn impl_keygen(
@@ -128,9 +226,13 @@ async fn impl_keygen_mnem(
     players: BTreeSet<usize>,
     mnem: Option<Mnemonics>,
 ) -> Resultat<Option<KeystoreSchnorr>> {
-    let provider_thread = if let Some(mnem) = mnem {
-        let future: _ =
-            keygen_mnem_provider(chan.clone(), players.clone(), mnem.phrases, mnem.password);
+    let provider_thread = if let Some(mut mnem) = mnem {
+        let future: _ = keygen_mnem_provider(
+            chan.clone(),
+            players.clone(),
+            std::mem::take(&mut mnem.phrases),
+            std::mem::take(&mut mnem.password),
+        );
         let handle: _ = tokio::spawn(future);
         Some(handle)
     } else {
@@ -162,17 +264,15 @@ async fn impl_sign(
     chan: SvarogChannel,
     keystore: KeystoreSchnorr,
     signers: BTreeSet<usize>,
-    tasks: Vec<SignTask>,
+    tasks: Vec<(Vec<u8>, String)>,
 ) -> Resultat<Vec<Signature>> {
     assert_throw!(tasks.len() >= 1);
     assert_throw!(signers.len() >= 1);
     assert_throw!(signers.contains(&keystore.i));
     let res = {
-        let tasks = tasks
-            .into_iter()
-            .map(|task| (task.message, task.bip32_path))
-            .collect();
-        let sigs = sign_batch(chan, signers, keystore, tasks).await.catch_()?;
+        let sigs = sign_batch(chan, signers, keystore.clone(), tasks.clone())
+            .await
+            .catch_()?;
         let mut res = Vec::new();
         for sig in sigs.into_iter() {
             let sig = Signature {
@@ -185,6 +285,7 @@ async fn impl_sign(
         }
         res
     };
+    verify_batch(&keystore, &tasks, &res).catch_()?;
     Ok(res)
 }
 
@@ -0,0 +1,283 @@
+//! 模块职责:
+//! 1. 从Keystore派生base58编码的Solana地址.
+//! 2. 把转账或指令列表编译为legacy或v0消息, 经`sign_batch`签名后输出base64编码的交易.
+//!
+//! 交易的唯一签名者是手续费支付方, 即MPC密钥在 `bip32_path` 下派生的地址. 暂不支持地址查找表.
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use erreur::*;
+use serde::{Deserialize, Serialize};
+use svarog_algo::schnorr_ed25519::KeystoreSchnorr;
+
+use crate::{
+    bip32::derive_pk_ed25519,
+    solana::biz_sign,
+    structs::{Keystore, MessageKind, SignTask},
+};
+
+pub const SYSTEM_PROGRAM_ID: [u8; 32] = [0u8; 32];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageVersion {
+    Legacy,
+    V0,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AccountMeta {
+    pub pubkey: [u8; 32],
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub program_id: [u8; 32],
+    pub accounts: Vec<AccountMeta>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SolanaTx {
+    pub instructions: Vec<Instruction>,
+    pub recent_blockhash: [u8; 32],
+    pub version: MessageVersion,
+    /// 手续费支付方的派生路径.
+    pub bip32_path: String,
+}
+
+/// 地址的派生与svarog_algo签名所用的公钥一致, 由集成测试 `test_keygen_sign` 逐路径核对.
+pub fn derive_address(keystore: &KeystoreSchnorr, bip32_path: &str) -> Resultat<String> {
+    let pk = derive_pk_ed25519(keystore, bip32_path).catch_()?;
+    Ok(bs58::encode(pk.compress().as_bytes()).into_string())
+}
+
+pub fn parse_pubkey(s: &str) -> Resultat<[u8; 32]> {
+    let bytes = bs58::decode(s)
+        .into_vec()
+        .catch("InvalidPubkey", format!("{} is not base58", s))?;
+    let res: [u8; 32] = bytes
+        .try_into()
+        .ok()
+        .ifnone("InvalidPubkey", format!("{} is not 32 bytes", s))?;
+    Ok(res)
+}
+
+/// System Program的转账指令.
+pub fn transfer(from: [u8; 32], to: [u8; 32], lamports: u64) -> Instruction {
+    let mut data = 2u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&lamports.to_le_bytes());
+    Instruction {
+        program_id: SYSTEM_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta {
+                pubkey: from,
+                is_signer: true,
+                is_writable: true,
+            },
+            AccountMeta {
+                pubkey: to,
+                is_signer: false,
+                is_writable: true,
+            },
+        ],
+        data,
+    }
+}
+
+fn write_compact_u16(buf: &mut Vec<u8>, n: usize) -> Resultat<()> {
+    assert_throw!(
+        n <= u16::MAX as usize,
+        format!("{} overflows compact-u16", n)
+    );
+    let mut n = n;
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+    Ok(())
+}
+
+/// 按Solana的规则排列账户: 可写签名者, 只读签名者, 可写非签名者, 只读非签名者.
+/// 手续费支付方总是第一个账户.
+pub fn compile_message(
+    payer: [u8; 32],
+    instructions: &[Instruction],
+    recent_blockhash: [u8; 32],
+    version: MessageVersion,
+) -> Resultat<Vec<u8>> {
+    assert_throw!(!instructions.is_empty(), "no instruction");
+
+    // pubkey -> (is_signer, is_writable)
+    let mut flags: BTreeMap<[u8; 32], (bool, bool)> = BTreeMap::new();
+    let mut order: Vec<[u8; 32]> = vec![payer];
+    flags.insert(payer, (true, true));
+    let mut touch = |pubkey: [u8; 32], is_signer: bool, is_writable: bool| {
+        let entry = flags.entry(pubkey).or_insert_with(|| {
+            order.push(pubkey);
+            (false, false)
+        });
+        entry.0 |= is_signer;
+        entry.1 |= is_writable;
+    };
+    for ix in instructions {
+        for meta in ix.accounts.iter() {
+            touch(meta.pubkey, meta.is_signer, meta.is_writable);
+        }
+        touch(ix.program_id, false, false);
+    }
+    let rank = |pubkey: &[u8; 32]| {
+        if *pubkey == payer {
+            return 0;
+        }
+        match flags[pubkey] {
+            (true, true) => 1,
+            (true, false) => 2,
+            (false, true) => 3,
+            (false, false) => 4,
+        }
+    };
+    // 稳定排序, 同类账户保持首次出现的顺序.
+    order.sort_by_key(rank);
+
+    let num_signers = order.iter().filter(|k| flags[*k].0).count();
+    let num_readonly_signed = order.iter().filter(|k| flags[*k] == (true, false)).count();
+    let num_readonly_unsigned = order.iter().filter(|k| flags[*k] == (false, false)).count();
+    assert_throw!(
+        num_signers == 1,
+        "only the fee payer can sign the transaction"
+    );
+    assert_throw!(order.len() <= 256, "too many accounts");
+    let index: BTreeMap<[u8; 32], u8> = order
+        .iter()
+        .enumerate()
+        .map(|(k, pubkey)| (*pubkey, k as u8))
+        .collect();
+
+    let mut buf = Vec::new();
+    if version == MessageVersion::V0 {
+        buf.push(0x80);
+    }
+    buf.push(num_signers as u8);
+    buf.push(num_readonly_signed as u8);
+    buf.push(num_readonly_unsigned as u8);
+    write_compact_u16(&mut buf, order.len()).catch_()?;
+    for pubkey in order.iter() {
+        buf.extend_from_slice(pubkey);
+    }
+    buf.extend_from_slice(&recent_blockhash);
+    write_compact_u16(&mut buf, instructions.len()).catch_()?;
+    for ix in instructions {
+        buf.push(index[&ix.program_id]);
+        write_compact_u16(&mut buf, ix.accounts.len()).catch_()?;
+        for meta in ix.accounts.iter() {
+            buf.push(index[&meta.pubkey]);
+        }
+        write_compact_u16(&mut buf, ix.data.len()).catch_()?;
+        buf.extend_from_slice(&ix.data);
+    }
+    if version == MessageVersion::V0 {
+        // address table lookups
+        write_compact_u16(&mut buf, 0).catch_()?;
+    }
+    Ok(buf)
+}
+
+/// 一场会话里签多笔交易, 返回base64编码的交易, 可直接用于`sendTransaction`.
+pub async fn biz_sign_tx(
+    sesman_url: String,
+    session_id: String,
+    keystore: Keystore<KeystoreSchnorr>,
+    txs: Vec<SolanaTx>,
+) -> Resultat<Vec<String>> {
+    let mut messages = Vec::new();
+    let mut payers = Vec::new();
+    for tx in txs.iter() {
+        let payer = derive_pk_ed25519(&keystore.share, &tx.bip32_path).catch_()?;
+        let payer = payer.compress().to_bytes();
+        let msg =
+            compile_message(payer, &tx.instructions, tx.recent_blockhash, tx.version).catch_()?;
+        messages.push(msg);
+        payers.push(payer);
+    }
+
+    let tasks = messages
+        .iter()
+        .zip(txs.iter())
+        .map(|(msg, tx)| SignTask {
+            message: msg.clone(),
+            bip32_path: tx.bip32_path.clone(),
+            kind: MessageKind::RawEd25519,
+        })
+        .collect();
+    let sigs = biz_sign(sesman_url, session_id, keystore, tasks)
+        .await
+        .catch_()?;
+    assert_throw!(sigs.len() == messages.len());
+
+    let mut res = Vec::new();
+    for ((msg, payer), sig) in messages.iter().zip(payers.iter()).zip(sigs.iter()) {
+        assert_throw!(sig.pk == payer, "signing key differs from the fee payer");
+        let mut wire = Vec::new();
+        write_compact_u16(&mut wire, 1).catch_()?;
+        wire.extend_from_slice(&sig.r);
+        wire.extend_from_slice(&sig.s);
+        wire.extend_from_slice(msg);
+        res.push(BASE64.encode(wire));
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_transfer() -> Resultat<()> {
+        let payer = [1u8; 32];
+        let to = [2u8; 32];
+        let ix = transfer(payer, to, 1000);
+        let msg = compile_message(payer, &[ix], [9u8; 32], MessageVersion::Legacy).catch_()?;
+
+        let mut expected = vec![1u8, 0, 1, 3];
+        expected.extend_from_slice(&payer);
+        expected.extend_from_slice(&to);
+        expected.extend_from_slice(&SYSTEM_PROGRAM_ID);
+        expected.extend_from_slice(&[9u8; 32]);
+        expected.extend_from_slice(&[1, 2, 2, 0, 1, 12, 2, 0, 0, 0]);
+        expected.extend_from_slice(&1000u64.to_le_bytes());
+        assert_throw!(msg == expected);
+
+        let msg_v0 = compile_message(
+            payer,
+            &[transfer(payer, to, 1000)],
+            [9u8; 32],
+            MessageVersion::V0,
+        )
+        .catch_()?;
+        assert_throw!(msg_v0[0] == 0x80 && msg_v0[1..msg_v0.len() - 1] == expected[..]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_u16() -> Resultat<()> {
+        for (n, encoded) in [
+            (0, vec![0u8]),
+            (0x7f, vec![0x7f]),
+            (0x80, vec![0x80, 0x01]),
+            (0x3fff, vec![0xff, 0x7f]),
+            (0x4000, vec![0x80, 0x80, 0x01]),
+        ] {
+            let mut buf = Vec::new();
+            write_compact_u16(&mut buf, n).catch_()?;
+            assert_throw!(buf == encoded);
+        }
+        Ok(())
+    }
+}
@@ -1,15 +1,51 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+/// 提交前可以用 `mnemonic::normalize_mnemonics` 校验. drop时清零, `Debug` 不输出内容.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Mnemonics {
     pub phrases: String,
     pub password: String,
 }
 
+impl std::fmt::Debug for Mnemonics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mnemonics").finish_non_exhaustive()
+    }
+}
+
+impl Drop for Mnemonics {
+    fn drop(&mut self) {
+        self.phrases.zeroize();
+        self.password.zeroize();
+    }
+}
+
+/// `SignTask.message` 的含义. secp256k1只接受前四种, ed25519只接受 `RawEd25519`.
+/// 缺省为 `Prehashed32`, 与引入该字段之前的行为一致.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MessageKind {
+    /// 调用方已算好的32字节哈希.
+    #[default]
+    Prehashed32,
+    /// 原始消息, 签名前做SHA-256.
+    Sha256,
+    /// 原始消息, 签名前做Keccak-256.
+    Keccak256,
+    /// 原始消息, 签名前做两次SHA-256.
+    DoubleSha256,
+    /// 原始消息, 由Ed25519直接签名.
+    RawEd25519,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct SignTask {
     pub message: Vec<u8>,
     pub bip32_path: String,
+    #[serde(default)]
+    pub kind: MessageKind,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -26,6 +62,27 @@ pub struct Signature {
     pub pk: Vec<u8>,
 }
 
+/// Keystore及其成员结构.
+/// `players` 是成员名称到成员序号的映射, 与会话中按名称排序编号的方式一致.
+/// `allowed_paths` 是允许签名的bip32路径前缀, 例如 `m/44/60`; 为空时不限制.
+/// `epoch` 在每次Refresh和Reshare后加一, Sign要求所有签名方的 `epoch` 相同, 旧分片因此作废.
+/// `refreshed_at` 是分片生成的时刻, Unix秒.
+/// `pending` 是Reshare时缺席, 尚未从邮箱取回分片的成员, 见 `mailbox`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Keystore<K> {
+    pub share: K,
+    pub threshold: usize,
+    pub players: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub allowed_paths: Vec<String>,
+    #[serde(default)]
+    pub epoch: u64,
+    #[serde(default)]
+    pub refreshed_at: u64,
+    #[serde(default)]
+    pub pending: Vec<String>,
+}
+
 pub use svarog_algo::elgamal_secp256k1::KeystoreElgamal;
 pub use svarog_algo::schnorr_ed25519::KeystoreSchnorr;
 pub use svarog_grpc::SessionConfig;
@@ -0,0 +1,351 @@
+//! 模块职责: 加密保管Keystore.
+//! 1. Keystore序列化后装入带版本号的信封, 用口令(argon2id派生)或密钥加密密钥(KEK)文件加密落盘.
+//! 2. 保管库目录下的`index.json`按公钥和成员身份索引全部Keystore. 一个保管库只服务一个参与方.
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use erreur::*;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+pub use crate::keystore::key_id;
+use crate::keystore::{share_fingerprint, MpcKeystore};
+
+pub const VAULT_VERSION: u32 = 1;
+const INDEX_FILE: &str = "index.json";
+const CIPHER: &str = "xchacha20poly1305";
+
+// OWASP推荐的argon2id参数下限.
+const ARGON2_M_COST: u32 = 19456;
+const ARGON2_T_COST: u32 = 2;
+const ARGON2_P_COST: u32 = 1;
+
+/// `Debug` 不输出口令.
+#[derive(Clone)]
+pub enum VaultKey {
+    Passphrase(Zeroizing<String>),
+    /// 文件内容为32字节密钥的hex编码.
+    KekFile(PathBuf),
+}
+
+impl std::fmt::Debug for VaultKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VaultKey::Passphrase(_) => f.write_str("Passphrase(..)"),
+            VaultKey::KekFile(path) => f.debug_tuple("KekFile").field(path).finish(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Kdf {
+    Argon2id {
+        salt: String,
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+    Kek,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Envelope {
+    version: u32,
+    algorithm: String,
+    kdf: Kdf,
+    cipher: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct VaultEntry {
+    pub key_id: String,
+    pub algorithm: String,
+    pub group_pk: String,
+    pub i: usize,
+    pub member_name: String,
+    pub file: String,
+    pub saved_at: u64,
+    #[serde(default)]
+    pub epoch: u64,
+    #[serde(default)]
+    pub fingerprint: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Vault {
+    dir: PathBuf,
+    key: VaultKey,
+}
+
+impl Vault {
+    pub fn open(dir: impl AsRef<Path>, key: VaultKey) -> Resultat<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).catch("", format!("Try creating {}", dir.display()))?;
+        let vault = Self { dir, key };
+        if !vault.dir.join(INDEX_FILE).exists() {
+            vault.write_index(&BTreeMap::new()).catch_()?;
+        }
+        Ok(vault)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 保存Keystore, 返回key id. 同一key id的旧Keystore会被覆盖, 但不接受比已保存的更旧的分片.
+    pub fn save<K: MpcKeystore>(&self, keystore: &K, member_name: &str) -> Resultat<String> {
+        let key_id = key_id(keystore);
+        let file = format!("{}.vault", &key_id);
+        let mut index = self.read_index().catch_()?;
+        if let Some(entry) = index.get(&key_id) {
+            assert_throw!(
+                keystore.epoch() >= entry.epoch,
+                format!(
+                    "key {} is at epoch {} in the vault, refusing the stale epoch {}",
+                    &key_id,
+                    entry.epoch,
+                    keystore.epoch()
+                )
+            );
+        }
+
+        let plain = Zeroizing::new(serde_pickle::to_vec(keystore, Default::default()).catch_()?);
+        let envelope = self.seal(K::ALGO, &plain).catch_()?;
+        let envelope = serde_json::to_vec_pretty(&envelope).catch_()?;
+        write_atomic(&self.dir.join(&file), &envelope).catch_()?;
+
+        let entry = VaultEntry {
+            key_id: key_id.clone(),
+            algorithm: K::ALGO.to_owned(),
+            group_pk: hex::encode(keystore.group_pk()),
+            i: keystore.party_index(),
+            member_name: member_name.to_owned(),
+            file,
+            saved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .catch_()?
+                .as_secs(),
+            epoch: keystore.epoch(),
+            fingerprint: share_fingerprint(keystore),
+        };
+        index.insert(key_id.clone(), entry);
+        self.write_index(&index).catch_()?;
+
+        Ok(key_id)
+    }
+
+    pub fn load<K: MpcKeystore>(&self, key_id: &str) -> Resultat<K> {
+        let index = self.read_index().catch_()?;
+        let entry = index.get(key_id).ifnone(
+            "KeyNotFound",
+            format!("key id {} is not in the vault", key_id),
+        )?;
+        assert_throw!(
+            entry.algorithm == K::ALGO,
+            format!("key {} is {}, not {}", key_id, &entry.algorithm, K::ALGO)
+        );
+
+        let path = self.dir.join(&entry.file);
+        let envelope = fs::read(&path).catch("", format!("Try reading {}", path.display()))?;
+        let envelope: Envelope = serde_json::from_slice(&envelope).catch_()?;
+        let plain = self.open_envelope(&envelope).catch_()?;
+        let keystore: K = serde_pickle::from_slice(&plain, Default::default()).catch_()?;
+        assert_throw!(
+            hex::encode(keystore.group_pk()) == entry.group_pk,
+            "group public key does not match the vault index"
+        );
+        Ok(keystore)
+    }
+
+    pub fn entries(&self) -> Resultat<Vec<VaultEntry>> {
+        let index = self.read_index().catch_()?;
+        Ok(index.into_values().collect())
+    }
+
+    pub fn find_by_pk(&self, group_pk: &[u8]) -> Resultat<Vec<VaultEntry>> {
+        let group_pk = hex::encode(group_pk);
+        let entries = self.entries().catch_()?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.group_pk == group_pk)
+            .collect())
+    }
+
+    pub fn find_by_member(&self, member_name: &str, i: Option<usize>) -> Resultat<Vec<VaultEntry>> {
+        let entries = self.entries().catch_()?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.member_name == member_name)
+            .filter(|entry| i.is_none() || i == Some(entry.i))
+            .collect())
+    }
+
+    fn seal(&self, algorithm: &str, plain: &[u8]) -> Resultat<Envelope> {
+        let kdf = match &self.key {
+            VaultKey::Passphrase(_) => {
+                let mut salt = [0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                Kdf::Argon2id {
+                    salt: hex::encode(salt),
+                    m_cost: ARGON2_M_COST,
+                    t_cost: ARGON2_T_COST,
+                    p_cost: ARGON2_P_COST,
+                }
+            }
+            VaultKey::KekFile(_) => Kdf::Kek,
+        };
+        let key = self.derive_key(&kdf).catch_()?;
+
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+        let aad = envelope_aad(VAULT_VERSION, algorithm);
+        let cipher = XChaCha20Poly1305::new_from_slice(&key[..]).catch_()?;
+        let ciphertext = cipher
+            .encrypt(
+                &XNonce::from(nonce),
+                Payload {
+                    msg: plain,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .catch("EncryptionFailed", "")?;
+
+        Ok(Envelope {
+            version: VAULT_VERSION,
+            algorithm: algorithm.to_owned(),
+            kdf,
+            cipher: CIPHER.to_owned(),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    fn open_envelope(&self, envelope: &Envelope) -> Resultat<Zeroizing<Vec<u8>>> {
+        assert_throw!(
+            envelope.version == VAULT_VERSION,
+            format!("unsupported vault version {}", envelope.version)
+        );
+        assert_throw!(envelope.cipher == CIPHER, "unsupported cipher");
+        let key = self.derive_key(&envelope.kdf).catch_()?;
+
+        let nonce = hex::decode(&envelope.nonce).catch_()?;
+        let nonce: [u8; 24] = nonce.try_into().ok().ifnone("", "invalid nonce")?;
+        let ciphertext = hex::decode(&envelope.ciphertext).catch_()?;
+        let aad = envelope_aad(envelope.version, &envelope.algorithm);
+        let cipher = XChaCha20Poly1305::new_from_slice(&key[..]).catch_()?;
+        let plain = cipher
+            .decrypt(
+                &XNonce::from(nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .catch("DecryptionFailed", "wrong passphrase/KEK or corrupted file")?;
+        Ok(Zeroizing::new(plain))
+    }
+
+    fn derive_key(&self, kdf: &Kdf) -> Resultat<Zeroizing<[u8; 32]>> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        match (&self.key, kdf) {
+            (
+                VaultKey::Passphrase(passphrase),
+                Kdf::Argon2id {
+                    salt,
+                    m_cost,
+                    t_cost,
+                    p_cost,
+                },
+            ) => {
+                let salt = hex::decode(salt).catch_()?;
+                let params = Params::new(*m_cost, *t_cost, *p_cost, Some(32)).catch_()?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), &salt, &mut key[..])
+                    .catch_()?;
+            }
+            (VaultKey::KekFile(path), Kdf::Kek) => {
+                let kek = Zeroizing::new(
+                    fs::read_to_string(path)
+                        .catch("", format!("Try reading {}", path.display()))?,
+                );
+                let kek = Zeroizing::new(hex::decode(kek.trim()).catch_()?);
+                assert_throw!(kek.len() == 32, "KEK should be 32 bytes");
+                key.copy_from_slice(&kek);
+            }
+            _ => {
+                assert_throw!(false, "vault key does not match the envelope kdf");
+            }
+        }
+        Ok(key)
+    }
+
+    fn read_index(&self) -> Resultat<BTreeMap<String, VaultEntry>> {
+        let path = self.dir.join(INDEX_FILE);
+        let index = fs::read(&path).catch("", format!("Try reading {}", path.display()))?;
+        let index = serde_json::from_slice(&index).catch_()?;
+        Ok(index)
+    }
+
+    fn write_index(&self, index: &BTreeMap<String, VaultEntry>) -> Resultat<()> {
+        let index = serde_json::to_vec_pretty(index).catch_()?;
+        write_atomic(&self.dir.join(INDEX_FILE), &index).catch_()?;
+        Ok(())
+    }
+}
+
+fn envelope_aad(version: u32, algorithm: &str) -> String {
+    format!("svarog-vault/{}/{}", version, algorithm)
+}
+
+fn write_atomic(path: &Path, data: &[u8]) -> Resultat<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data).catch("", format!("Try writing {}", tmp.display()))?;
+    fs::rename(&tmp, path).catch("", format!("Try renaming {}", tmp.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_roundtrip() -> Resultat<()> {
+        let dir = std::env::temp_dir().join(format!("svarog_vault_{}", uuid::Uuid::now_v7()));
+        let plain = b"Les sanglots longs des violons de l'automne".to_vec();
+
+        let vault = Vault::open(
+            &dir,
+            VaultKey::Passphrase("correct horse".to_owned().into()),
+        )
+        .catch_()?;
+        let envelope = vault.seal("elgamal_secp256k1", &plain).catch_()?;
+        assert_throw!(*vault.open_envelope(&envelope).catch_()? == plain);
+
+        let thief = Vault::open(
+            &dir,
+            VaultKey::Passphrase("battery staple".to_owned().into()),
+        )
+        .catch_()?;
+        assert_throw!(thief.open_envelope(&envelope).is_err());
+
+        let mut forged = envelope.clone();
+        forged.algorithm = "schnorr_ed25519".to_owned();
+        assert_throw!(vault.open_envelope(&forged).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+//! 签名的本地验证. 签名返回给调用方之前都要经过这里, 以免协议或派生的缺陷把无效签名带上链.
+use curve25519_dalek::{edwards::CompressedEdwardsY, EdwardsPoint};
+use erreur::*;
+use k256::{
+    ecdsa::{signature::hazmat::PrehashVerifier, Signature as EcdsaSignature, VerifyingKey},
+    elliptic_curve::{scalar::IsHigh, PrimeField},
+};
+use sha2::{Digest, Sha512};
+use svarog_algo::{
+    elgamal_secp256k1::{KeystoreElgamal, Scalar},
+    schnorr_ed25519::KeystoreSchnorr,
+};
+
+use crate::{keystore::MpcKeystore, structs::Signature};
+
+/// 按算法验证签名. `algorithm` 取 `elgamal_secp256k1` 或 `schnorr_ed25519`.
+/// secp256k1的 `message` 是32字节的消息哈希; ed25519的 `message` 是原始消息.
+pub fn verify_signature(algorithm: &str, sig: &Signature, message: &[u8]) -> Resultat<()> {
+    match algorithm {
+        KeystoreElgamal::ALGO => verify_ecdsa(sig, message),
+        KeystoreSchnorr::ALGO => verify_ed25519(sig, message),
+        _ => {
+            assert_throw!(false, format!("unknown algorithm {}", algorithm));
+            Ok(())
+        }
+    }
+}
+
+/// ECDSA验证, 要求s为低值 (BIP62, EIP-2).
+pub fn verify_ecdsa(sig: &Signature, message: &[u8]) -> Resultat<()> {
+    assert_throw!(
+        message.len() == 32,
+        format!("message hash should be 32 bytes, got {}", message.len())
+    );
+    let vk = VerifyingKey::from_sec1_bytes(&sig.pk).catch("InvalidSignature", "bad public key")?;
+    let ecdsa_sig = EcdsaSignature::from_scalars(sig.r, sig.s)
+        .catch("InvalidSignature", "r or s is out of range")?;
+    assert_throw!(ecdsa_sig.normalize_s().is_none(), "s is not low");
+    vk.verify_prehash(message, &ecdsa_sig)
+        .catch("InvalidSignature", "ecdsa verification failed")?;
+    Ok(())
+}
+
+/// RFC 8032 Ed25519验证: `[s]B == R + [k]A`, 其中 `k = SHA512(R || A || M) mod l`.
+pub fn verify_ed25519(sig: &Signature, message: &[u8]) -> Resultat<()> {
+    use curve25519_dalek::Scalar;
+
+    let a = CompressedEdwardsY::from_slice(&sig.pk).catch("InvalidSignature", "bad public key")?;
+    let a = a
+        .decompress()
+        .ifnone("InvalidSignature", "public key is not on the curve")?;
+    let r = CompressedEdwardsY(sig.r);
+    let s: Option<Scalar> = Scalar::from_canonical_bytes(sig.s).into();
+    let s = s.ifnone("InvalidSignature", "s is not canonical")?;
+
+    let mut hasher = Sha512::new();
+    hasher.update(sig.r);
+    hasher.update(&sig.pk);
+    hasher.update(message);
+    let k = Scalar::from_bytes_mod_order_wide(&hasher.finalize().into());
+
+    let expected = EdwardsPoint::vartime_double_scalar_mul_basepoint(&k, &(-a), &s);
+    assert_throw!(expected.compress() == r, "ed25519 verification failed");
+    Ok(())
+}
+
+/// 把高s的ECDSA签名换成等价的低s签名, 同时翻转恢复位.
+pub fn normalize_s(sig: &mut Signature) -> Resultat<()> {
+    let s: Option<Scalar> = Scalar::from_repr(sig.s.into()).into();
+    let s = s.ifnone("InvalidSignature", "s is out of range")?;
+    if bool::from(s.is_high()) {
+        sig.s = (-s).to_bytes().into();
+        sig.v ^= 1;
+    }
+    Ok(())
+}
+
+/// 逐个验证批量签名的结果, 失败时列出所有失败的任务. `tasks` 是交给 `sign_batch` 的 `(消息, bip32路径)`.
+/// 签名的公钥必须等于从 `keystore` 按路径派生的公钥, 而不是采信 `sign_batch` 返回的公钥.
+pub(crate) fn verify_batch<K: MpcKeystore>(
+    keystore: &K,
+    tasks: &[(Vec<u8>, String)],
+    sigs: &[Signature],
+) -> Resultat<()> {
+    assert_throw!(
+        tasks.len() == sigs.len(),
+        format!("{} tasks but {} signatures", tasks.len(), sigs.len())
+    );
+    let mut failures = Vec::new();
+    for (k, ((message, bip32_path), sig)) in tasks.iter().zip(sigs.iter()).enumerate() {
+        let res = keystore.derive_pk(bip32_path).and_then(|expected| {
+            assert_throw!(
+                sig.pk == expected,
+                format!(
+                    "signed by {}, but the keystore derives {} at this path",
+                    hex::encode(&sig.pk),
+                    hex::encode(&expected)
+                )
+            );
+            verify_signature(K::ALGO, sig, message)
+        });
+        if let Err(e) = res {
+            failures.push(format!("task #{} (bip32_path {}): {}", k, bip32_path, e));
+        }
+    }
+    assert_throw!(
+        failures.is_empty(),
+        format!("invalid signatures: {}", failures.join("; "))
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::{signature::hazmat::PrehashSigner, SigningKey};
+
+    use super::*;
+
+    #[test]
+    fn test_verify_ecdsa() -> Resultat<()> {
+        let sk = SigningKey::from_slice(&[7u8; 32]).catch_()?;
+        let msg = [3u8; 32];
+        let (ecdsa_sig, recid): (EcdsaSignature, _) = sk.sign_prehash(&msg).catch_()?;
+        let (r, s) = ecdsa_sig.split_bytes();
+        let mut sig = Signature {
+            r: r.into(),
+            s: s.into(),
+            v: recid.to_byte(),
+            pk: sk.verifying_key().to_sec1_bytes().to_vec(),
+        };
+        normalize_s(&mut sig).catch_()?;
+        verify_signature(KeystoreElgamal::ALGO, &sig, &msg).catch_()?;
+
+        let mut high = sig.clone();
+        high.s = (-Scalar::from_repr(sig.s.into()).unwrap())
+            .to_bytes()
+            .into();
+        assert_throw!(verify_ecdsa(&high, &msg).is_err());
+        assert_throw!(verify_ecdsa(&sig, &[4u8; 32]).is_err());
+        Ok(())
+    }
+
+    // RFC 8032 test vector 1.
+    #[test]
+    fn test_verify_ed25519() -> Resultat<()> {
+        let pk = hex::decode("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a")
+            .catch_()?;
+        let rs = hex::decode("e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b").catch_()?;
+        let sig = Signature {
+            r: rs[..32].try_into().catch_()?,
+            s: rs[32..].try_into().catch_()?,
+            v: 0,
+            pk,
+        };
+        verify_signature(KeystoreSchnorr::ALGO, &sig, b"").catch_()?;
+        assert_throw!(verify_ed25519(&sig, b"x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_batch_pk() -> Resultat<()> {
+        use std::collections::BTreeMap;
+
+        use curve25519_dalek::Scalar;
+
+        // 私钥为10, 在根节点上按RFC 8032签名.
+        let x = Scalar::from(10u64);
+        let keystore = KeystoreSchnorr {
+            i: 1,
+            xi: x + Scalar::from(5u64),
+            vss_scheme: BTreeMap::from([(
+                1,
+                vec![
+                    EdwardsPoint::mul_base(&x),
+                    EdwardsPoint::mul_base(&Scalar::from(5u64)),
+                ],
+            )]),
+            chain_code: [0u8; 32],
+        };
+        let pk = keystore.group_pk();
+        let nonce = Scalar::from(3u64);
+        let r = EdwardsPoint::mul_base(&nonce).compress().to_bytes();
+        let mut hasher = Sha512::new();
+        hasher.update(r);
+        hasher.update(&pk);
+        hasher.update(b"msg");
+        let k = Scalar::from_bytes_mod_order_wide(&hasher.finalize().into());
+        let sig = Signature {
+            r,
+            s: (nonce + k * x).to_bytes(),
+            v: 0,
+            pk,
+        };
+
+        let sigs = vec![sig];
+        verify_batch(&keystore, &[(b"msg".to_vec(), "m".to_owned())], &sigs)?;
+        // 签名本身有效, 但不是 `m/1` 的公钥签的.
+        assert_throw!(
+            verify_batch(&keystore, &[(b"msg".to_vec(), "m/1".to_owned())], &sigs).is_err()
+        );
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
 //! Sesman client library
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
@@ -9,8 +10,8 @@ use erreur::*;
 use mpc_sig_abs::BatchMessenger;
 use serde::{de::DeserializeOwned, Serialize};
 use svarog_grpc::{
-    mpc_session_manager_client::MpcSessionManagerClient, Message, SessionConfig, SessionId,
-    VecMessage,
+    mpc_session_manager_client::MpcSessionManagerClient, BlameRecord, CheckIn, Mail, MailboxId,
+    Message, ReadyMarker, SessionConfig, SessionId, SignerSet, VecMessage,
 };
 use tonic::{
     transport::{Certificate, Channel, ClientTlsConfig},
@@ -18,6 +19,10 @@ use tonic::{
 };
 
 pub const SESSION_EXPIRE_MS: u128 = 300_000;
+/// How long sesman holds an outbox request before returning the messages it has.
+pub const OUTBOX_WAIT_MS: u128 = SESSION_EXPIRE_MS - 10_000;
+/// Mails are kept for 7 days, so that absent members can fetch them later.
+pub const MAILBOX_EXPIRE_MS: u128 = 7 * 24 * 3600 * 1000;
 
 #[derive(Clone)]
 pub struct SvarogChannel {
@@ -25,6 +30,8 @@ pub struct SvarogChannel {
     cl: MpcSessionManagerClient<Channel>,
     tx: Vec<Message>,
     rx: HashMap<MessageIndex, Option<Vec<u8>>>,
+    /// Parties whose messages did not arrive in time. Shared by all clones of the channel.
+    missing: Arc<Mutex<BTreeSet<usize>>>,
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -35,26 +42,30 @@ struct MessageIndex {
     seq: usize,
 }
 
+async fn connect(sesman_url: &str, https: bool) -> Resultat<MpcSessionManagerClient<Channel>> {
+    let mut ch = Channel::from_shared(sesman_url.to_string()).catch_()?;
+    if https {
+        let pem = tokio::fs::read_to_string("tls/fullchain.pem")
+            .await
+            .catch_()?;
+        let ca = Certificate::from_pem(pem);
+        let tls = ClientTlsConfig::new().ca_certificate(ca);
+        ch = ch.tls_config(tls).catch_()?;
+    }
+    let ch = ch
+        .connect()
+        .await
+        .catch("", format!("Try connecting to {}", sesman_url))?;
+    Ok(MpcSessionManagerClient::new(ch))
+}
+
 impl SvarogChannel {
     pub fn sid(&self) -> &str {
         &self.sid
     }
 
     pub async fn new_session(cfg: &SessionConfig, sesman_url: &str, https: bool) -> Resultat<Self> {
-        let mut ch = Channel::from_shared(sesman_url.to_string()).catch_()?;
-        if https {
-            let pem = tokio::fs::read_to_string("tls/fullchain.pem")
-                .await
-                .catch_()?;
-            let ca = Certificate::from_pem(pem);
-            let tls = ClientTlsConfig::new().ca_certificate(ca);
-            ch = ch.tls_config(tls).catch_()?;
-        }
-        let ch = ch
-            .connect()
-            .await
-            .catch("", format!("Try connecting to {}", sesman_url))?;
-        let mut cl = MpcSessionManagerClient::new(ch);
+        let mut cl = connect(sesman_url, https).await.catch_()?;
 
         let sid = cl
             .new_session(cfg.clone())
@@ -67,6 +78,7 @@ impl SvarogChannel {
             cl,
             tx: Vec::new(),
             rx: HashMap::new(),
+            missing: Default::default(),
         })
     }
 
@@ -75,20 +87,7 @@ impl SvarogChannel {
         sesman_url: &str,
         https: bool,
     ) -> Resultat<(Self, SessionConfig)> {
-        let mut ch = Channel::from_shared(sesman_url.to_string()).catch_()?;
-        if https {
-            let pem = tokio::fs::read_to_string("tls/fullchain.pem")
-                .await
-                .catch_()?;
-            let ca = Certificate::from_pem(pem);
-            let tls = ClientTlsConfig::new().ca_certificate(ca);
-            ch = ch.tls_config(tls).catch_()?;
-        }
-        let ch = ch
-            .connect()
-            .await
-            .catch("", format!("Try connecting to {}", sesman_url))?;
-        let mut cl = MpcSessionManagerClient::new(ch);
+        let mut cl = connect(sesman_url, https).await.catch_()?;
 
         let mut req = Request::new(SessionId {
             value: sid.to_owned(),
@@ -104,9 +103,152 @@ impl SvarogChannel {
             cl,
             tx: Vec::new(),
             rx: HashMap::new(),
+            missing: Default::default(),
         };
         Ok((_self, cfg))
     }
+
+    /// Parties that failed to send an expected message on this channel or any of its clones.
+    pub fn missing_parties(&self) -> BTreeSet<usize> {
+        self.missing
+            .lock()
+            .map(|missing| missing.clone())
+            .unwrap_or_default()
+    }
+
+    /// Record on sesman that `reporter` holds `culprit` responsible for the failure of this session.
+    pub async fn report_blame(
+        &mut self,
+        reporter: &str,
+        culprit: usize,
+        culprit_name: &str,
+        reason: &str,
+    ) -> Resultat<()> {
+        let record = BlameRecord {
+            session_id: self.sid.clone(),
+            reporter: reporter.to_owned(),
+            culprit: culprit as u64,
+            culprit_name: culprit_name.to_owned(),
+            reason: reason.to_owned(),
+        };
+        let _ = self
+            .cl
+            .report_blame(record)
+            .await
+            .catch("GrpcCallFailed", "MpcSessionManager::ReportBlame")?;
+        Ok(())
+    }
+
+    /// Fetch all blame records of session `sid`.
+    pub async fn get_blame(sid: &str, sesman_url: &str, https: bool) -> Resultat<Vec<BlameRecord>> {
+        let mut cl = connect(sesman_url, https).await.catch_()?;
+        let req = SessionId {
+            value: sid.to_owned(),
+        };
+        let records = cl
+            .get_blame(req)
+            .await
+            .catch("GrpcCallFailed", "MpcSessionManager::GetBlame")?
+            .into_inner()
+            .values;
+        Ok(records)
+    }
+
+    /// Announce that `player` is ready to run this session.
+    pub async fn post_ready(
+        &mut self,
+        player: &str,
+        version: &str,
+        capabilities: Vec<String>,
+    ) -> Resultat<()> {
+        let marker = ReadyMarker {
+            session_id: self.sid.clone(),
+            player: player.to_owned(),
+            version: version.to_owned(),
+            capabilities,
+        };
+        let _ = self
+            .cl
+            .post_ready(marker)
+            .await
+            .catch("GrpcCallFailed", "MpcSessionManager::PostReady")?;
+        Ok(())
+    }
+
+    /// Ready markers posted so far in this session. Does not block.
+    pub async fn ready_markers(&mut self) -> Resultat<Vec<ReadyMarker>> {
+        let req = SessionId {
+            value: self.sid.clone(),
+        };
+        let markers = self
+            .cl
+            .get_ready(req)
+            .await
+            .catch("GrpcCallFailed", "MpcSessionManager::GetReady")?
+            .into_inner()
+            .values;
+        Ok(markers)
+    }
+
+    /// Check `player` in as a signer of this session. Blocks until the signer quorum is locked,
+    /// or until the player is left out of it, or until the check-in deadline passes.
+    pub async fn check_in(&mut self, player: &str) -> Resultat<SignerSet> {
+        let mut req = Request::new(CheckIn {
+            session_id: self.sid.clone(),
+            player: player.to_owned(),
+        });
+        req.set_timeout(Duration::from_millis(SESSION_EXPIRE_MS as u64));
+        let signers = self
+            .cl
+            .check_in(req)
+            .await
+            .catch("GrpcCallFailed", "MpcSessionManager::CheckIn")?
+            .into_inner();
+        Ok(signers)
+    }
+
+    /// Leave a mail for `recipient` in the mailbox of this session.
+    /// Fails if a different mail from `sender` to `recipient` is already there.
+    pub async fn put_mail(
+        &mut self,
+        recipient: &str,
+        sender: &str,
+        payload: Vec<u8>,
+    ) -> Resultat<()> {
+        let mail = Mail {
+            session_id: self.sid.clone(),
+            recipient: recipient.to_owned(),
+            sender: sender.to_owned(),
+            payload,
+        };
+        let _ = self
+            .cl
+            .put_mail(mail)
+            .await
+            .catch("GrpcCallFailed", "MpcSessionManager::PutMail")?;
+        Ok(())
+    }
+
+    /// Fetch all mails left for `recipient` in session `sid`. The session itself may have expired.
+    pub async fn get_mail(
+        sid: &str,
+        sesman_url: &str,
+        https: bool,
+        recipient: &str,
+    ) -> Resultat<Vec<Mail>> {
+        let mut cl = connect(sesman_url, https).await.catch_()?;
+        let req = MailboxId {
+            session_id: sid.to_owned(),
+            recipient: recipient.to_owned(),
+        };
+        let mails = cl
+            .get_mail(req)
+            .await
+            .catch("GrpcCallFailed", "MpcSessionManager::GetMail")?
+            .into_inner()
+            .values;
+        Ok(mails)
+    }
 }
 
 #[tonic::async_trait]
@@ -205,7 +347,17 @@ impl BatchMessenger for SvarogChannel {
             key_set.remove(&key);
             self.rx.insert(key, Some(obj)); // update
         }
-        assert_throw!(key_set.is_empty(), "Some messages are missing");
+        if !key_set.is_empty() {
+            let srcs: BTreeSet<usize> = key_set.iter().map(|key| key.src).collect();
+            if let Ok(mut missing) = self.missing.lock() {
+                missing.extend(srcs.iter().copied());
+            }
+            let srcs: Vec<String> = srcs.iter().map(|src| src.to_string()).collect();
+            assert_throw!(
+                false,
+                format!("Some messages are missing from parties {}", srcs.join(", "))
+            );
+        }
 
         Ok(())
     }
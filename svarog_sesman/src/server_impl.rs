@@ -1,11 +1,15 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 use blake2::digest::{Update, VariableOutput};
 use crossbeam_skiplist::SkipMap;
 use erreur::*;
 use svarog_grpc::{
-    mpc_session_manager_server::MpcSessionManager, EchoMessage, Message, SessionConfig, SessionId,
-    VecMessage, Void,
+    mpc_session_manager_server::MpcSessionManager, BlameRecord, CheckIn, EchoMessage, Mail,
+    MailboxId, Message, ReadyMarker, SessionConfig, SessionId, SignerSet, VecBlameRecord, VecMail,
+    VecMessage, VecReadyMarker, Void,
 };
 use tokio::{
     task::JoinHandle,
@@ -43,8 +47,81 @@ pub fn primary_key(sid: &str, topic: &str, src: u64, dst: u64, seq: u64) -> Resu
     Ok(pk)
 }
 
+pub fn mailbox_key(sid: &str, recipient: &str, sender: &str) -> Resultat<[u8; 32]> {
+    let mut key = [0u8; 32];
+
+    // mailbox, length-prefixed since names may contain any character
+    let mut ha = blake2::Blake2bVar::new(16).catch_()?;
+    for field in [sid, recipient] {
+        ha.update(&(field.len() as u64).to_be_bytes());
+        ha.update(field.as_bytes());
+    }
+    ha.finalize_variable(&mut key[..16]).catch_()?;
+
+    // sender
+    let mut ha = blake2::Blake2bVar::new(16).catch_()?;
+    ha.update(sender.as_bytes());
+    ha.finalize_variable(&mut key[16..]).catch_()?;
+
+    Ok(key)
+}
+
+pub fn blame_key(sid: &str, reporter: &str, culprit: u64) -> Resultat<[u8; 32]> {
+    let mut key = [0u8; 32];
+
+    // session
+    let mut ha = blake2::Blake2bVar::new(16).catch_()?;
+    ha.update(sid.as_bytes());
+    ha.finalize_variable(&mut key[..16]).catch_()?;
+
+    // reporter and culprit
+    let mut ha = blake2::Blake2bVar::new(16).catch_()?;
+    ha.update(format!("{}-{}", reporter, culprit).as_bytes());
+    ha.finalize_variable(&mut key[16..]).catch_()?;
+
+    Ok(key)
+}
+
+pub fn ready_key(sid: &str, player: &str) -> Resultat<[u8; 32]> {
+    let mut key = [0u8; 32];
+
+    // session
+    let mut ha = blake2::Blake2bVar::new(16).catch_()?;
+    ha.update(sid.as_bytes());
+    ha.finalize_variable(&mut key[..16]).catch_()?;
+
+    // player
+    let mut ha = blake2::Blake2bVar::new(16).catch_()?;
+    ha.update(player.as_bytes());
+    ha.finalize_variable(&mut key[16..]).catch_()?;
+
+    Ok(key)
+}
+
+fn now_ms() -> u128 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis()
+}
+
+/// Signers checked in to each session, in check-in order, with the time of the first check-in
+/// and whether the check-in is closed.
+type CheckIns = Mutex<HashMap<String, (u128, bool, Vec<String>)>>;
+
+/// Session messages, mailbox items `(deposit time, mail)`, blame records `(report time, record)`,
+/// ready markers `(post time, marker)` and signer check-ins.
+/// Messages, ready markers and check-ins are recycled once the session expires;
+/// mails and blame records are kept for `MAILBOX_EXPIRE_MS`.
 #[derive(Clone, Default)]
-pub struct Sesman(Arc<SkipMap<[u8; 32], Vec<u8>>>);
+pub struct Sesman(
+    Arc<SkipMap<[u8; 32], Vec<u8>>>,
+    Arc<SkipMap<[u8; 32], (u128, Mail)>>,
+    Arc<SkipMap<[u8; 32], (u128, BlameRecord)>>,
+    Arc<SkipMap<[u8; 32], (u128, ReadyMarker)>>,
+    Arc<CheckIns>,
+);
 
 impl Sesman {
     pub async fn init() -> Resultat<(Self, JoinHandle<()>)> {
@@ -54,6 +131,37 @@ impl Sesman {
         Ok((sesman, h))
     }
 
+    fn session_config(&self, sid: &str) -> Resultat<SessionConfig> {
+        let key = primary_key(sid, "session config", 0, 0, 0).catch_()?;
+        let entry = self.0.get(&key).ifnone_()?;
+        let cfg: SessionConfig =
+            serde_pickle::from_slice(entry.value(), Default::default()).catch_()?;
+        Ok(cfg)
+    }
+
+    /// Signers locked so far in session `sid`, checking `player` in if there is room left.
+    /// Once a waiter gives up (`expired`) on an incomplete quorum, the check-in is closed:
+    /// the signers that checked in early have left, so nobody else may complete the quorum.
+    fn lock_signers(
+        &self,
+        sid: &str,
+        player: &str,
+        quorum: usize,
+        expired: bool,
+    ) -> Resultat<Vec<String>> {
+        let mut checkins = self.4.lock().ok().ifnone("", "Check-in table poisoned")?;
+        let (_, closed, signers) = checkins
+            .entry(sid.to_owned())
+            .or_insert_with(|| (now_ms(), false, Vec::new()));
+        if !*closed && signers.len() < quorum && !signers.iter().any(|s| s == player) {
+            signers.push(player.to_owned());
+        }
+        if expired && signers.len() < quorum {
+            *closed = true;
+        }
+        Ok(signers.clone())
+    }
+
     async fn recycle(self) {
         loop {
             let pivot = pivot_key();
@@ -67,6 +175,25 @@ impl Sesman {
                     let _ = entry.remove();
                 }
             }
+            let now = now_ms();
+            for entry in self.1.iter() {
+                if entry.value().0 + svarog_sesman::MAILBOX_EXPIRE_MS < now {
+                    let _ = entry.remove();
+                }
+            }
+            for entry in self.2.iter() {
+                if entry.value().0 + svarog_sesman::MAILBOX_EXPIRE_MS < now {
+                    let _ = entry.remove();
+                }
+            }
+            for entry in self.3.iter() {
+                if entry.value().0 + svarog_sesman::SESSION_EXPIRE_MS < now {
+                    let _ = entry.remove();
+                }
+            }
+            if let Ok(mut checkins) = self.4.lock() {
+                checkins.retain(|_, (t, _, _)| *t + svarog_sesman::SESSION_EXPIRE_MS >= now);
+            }
             sleep(Duration::from_secs(60)).await;
         }
     }
@@ -103,17 +230,8 @@ impl MpcSessionManager for Sesman {
         request: Request<SessionId>,
     ) -> Result<Response<SessionConfig>, Status> {
         let sid = request.into_inner().value;
-        let key = primary_key(&sid, "session config", 0, 0, 0)
-            .catch_()
-            .map_err(|e| Status::internal(e.to_string()))?;
-        let entry = self
-            .0
-            .get(&key)
-            .ifnone_()
-            .map_err(|e| Status::internal(e.to_string()))?;
-        let val = entry.value();
-        let cfg: SessionConfig = serde_pickle::from_slice(val, Default::default())
-            .catch_()
+        let cfg = self
+            .session_config(&sid)
             .map_err(|e| Status::internal(e.to_string()))?;
         Ok(Response::new(cfg))
     }
@@ -137,34 +255,174 @@ impl MpcSessionManager for Sesman {
 
     async fn outbox(&self, request: Request<VecMessage>) -> Result<Response<VecMessage>, Status> {
         let idxs = request.into_inner().values;
+        let deadline = now_ms() + svarog_sesman::OUTBOX_WAIT_MS;
         let mut resp = Vec::new();
         for idx in idxs.iter() {
             let key = primary_key(&idx.session_id, &idx.topic, idx.src, idx.dst, idx.seq)
                 .catch_()
                 .map_err(|e| Status::internal(e.to_string()))?;
+            // Messages still missing at the deadline are left out of the response,
+            // so that the client can tell which parties did not send.
             let obj = loop {
                 let entry = self.0.get(&key);
                 match entry {
-                    Some(ref_obj) => break ref_obj.value().clone(),
+                    Some(ref_obj) => break Some(ref_obj.value().clone()),
+                    None if now_ms() >= deadline => break None,
                     None => {
                         sleep(Duration::from_secs(1)).await;
                         continue;
                     }
                 };
             };
-            resp.push(Message {
-                session_id: idx.session_id.clone(),
-                topic: idx.topic.clone(),
-                src: idx.src,
-                dst: idx.dst,
-                seq: idx.seq,
-                obj: Some(obj),
-            })
+            if obj.is_some() {
+                resp.push(Message {
+                    session_id: idx.session_id.clone(),
+                    topic: idx.topic.clone(),
+                    src: idx.src,
+                    dst: idx.dst,
+                    seq: idx.seq,
+                    obj,
+                })
+            }
         }
 
         Ok(Response::new(VecMessage { values: resp }))
     }
 
+    async fn put_mail(&self, request: Request<Mail>) -> Result<Response<Void>, Status> {
+        let mail = request.into_inner();
+        let key = mailbox_key(&mail.session_id, &mail.recipient, &mail.sender)
+            .catch_()
+            .map_err(|e| Status::internal(e.to_string()))?;
+        // Senders are not authenticated, so a second, different mail under the same sender name
+        // is rejected instead of silently dropped: the real sender learns that its slot was taken.
+        // Resending the same mail is accepted.
+        let entry = self.1.get_or_insert(key, (now_ms(), mail.clone()));
+        if entry.value().1 != mail {
+            return Err(Status::already_exists(format!(
+                "mail from {} to {} already exists",
+                &mail.sender, &mail.recipient
+            )));
+        }
+        Ok(Response::new(Void {}))
+    }
+
+    async fn get_mail(&self, request: Request<MailboxId>) -> Result<Response<VecMail>, Status> {
+        let id = request.into_inner();
+        let mut lo = mailbox_key(&id.session_id, &id.recipient, "")
+            .catch_()
+            .map_err(|e| Status::internal(e.to_string()))?;
+        lo[16..].fill(0x00);
+        let mut hi = lo;
+        hi[16..].fill(0xff);
+        let values = self
+            .1
+            .range(lo..=hi)
+            .map(|entry| entry.value().1.clone())
+            .collect();
+        Ok(Response::new(VecMail { values }))
+    }
+
+    async fn report_blame(&self, request: Request<BlameRecord>) -> Result<Response<Void>, Status> {
+        let record = request.into_inner();
+        let key = blame_key(&record.session_id, &record.reporter, record.culprit)
+            .catch_()
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let _ = self.2.get_or_insert(key, (now_ms(), record));
+        Ok(Response::new(Void {}))
+    }
+
+    async fn get_blame(
+        &self,
+        request: Request<SessionId>,
+    ) -> Result<Response<VecBlameRecord>, Status> {
+        let sid = request.into_inner().value;
+        let mut lo = blame_key(&sid, "", 0)
+            .catch_()
+            .map_err(|e| Status::internal(e.to_string()))?;
+        lo[16..].fill(0x00);
+        let mut hi = lo;
+        hi[16..].fill(0xff);
+        let values = self
+            .2
+            .range(lo..=hi)
+            .map(|entry| entry.value().1.clone())
+            .collect();
+        Ok(Response::new(VecBlameRecord { values }))
+    }
+
+    async fn post_ready(&self, request: Request<ReadyMarker>) -> Result<Response<Void>, Status> {
+        let marker = request.into_inner();
+        let key = ready_key(&marker.session_id, &marker.player)
+            .catch_()
+            .map_err(|e| Status::internal(e.to_string()))?;
+        self.3.insert(key, (now_ms(), marker));
+        Ok(Response::new(Void {}))
+    }
+
+    async fn get_ready(
+        &self,
+        request: Request<SessionId>,
+    ) -> Result<Response<VecReadyMarker>, Status> {
+        let sid = request.into_inner().value;
+        let mut lo = ready_key(&sid, "")
+            .catch_()
+            .map_err(|e| Status::internal(e.to_string()))?;
+        lo[16..].fill(0x00);
+        let mut hi = lo;
+        hi[16..].fill(0xff);
+        let values = self
+            .3
+            .range(lo..=hi)
+            .map(|entry| entry.value().1.clone())
+            .collect();
+        Ok(Response::new(VecReadyMarker { values }))
+    }
+
+    async fn check_in(&self, request: Request<CheckIn>) -> Result<Response<SignerSet>, Status> {
+        let req = request.into_inner();
+        let cfg = self
+            .session_config(&req.session_id)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        if cfg.signer_quorum == 0 {
+            return Err(Status::failed_precondition(
+                "Dynamic signer selection is not enabled for this session",
+            ));
+        }
+        if !cfg.players.contains_key(&req.player) {
+            return Err(Status::permission_denied(format!(
+                "{} is not a player of this session",
+                req.player
+            )));
+        }
+        let quorum = cfg.signer_quorum as usize;
+        // The check-in wait reuses `ready_timeout_secs`, see its doc in svarog.proto.
+        // Check-ins are not authenticated: anyone who knows the session id can check in
+        // under a player's name and take its signer slot.
+        let wait_ms = match cfg.ready_timeout_secs {
+            0 => svarog_sesman::OUTBOX_WAIT_MS,
+            secs => (secs as u128 * 1000).min(svarog_sesman::OUTBOX_WAIT_MS),
+        };
+        let deadline = now_ms() + wait_ms;
+        // Signers are locked in check-in order; wait until the quorum fills up.
+        loop {
+            let expired = now_ms() >= deadline;
+            let signers = self
+                .lock_signers(&req.session_id, &req.player, quorum, expired)
+                .map_err(|e| Status::internal(e.to_string()))?;
+            let selected = signers.contains(&req.player);
+            let complete = signers.len() >= quorum;
+            if complete || !selected || expired {
+                return Ok(Response::new(SignerSet {
+                    signers,
+                    selected,
+                    complete,
+                }));
+            }
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+
     async fn ping(&self, _: Request<Void>) -> Result<Response<EchoMessage>, Status> {
         Ok(Response::new(EchoMessage {
             value: "Svarog Session Manager is running.".to_owned(),